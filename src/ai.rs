@@ -0,0 +1,457 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use crate::{BOARD_HEIGHT_IN_TILES, BOARD_WIDTH_IN_TILES};
+use crate::board::Board;
+use crate::choice::Choice;
+use crate::game::{Game, GameStatus};
+use crate::input::Action;
+use crate::placement;
+use crate::rotation::RotationSystem;
+use crate::shape::{Shape, ShapeType, MAX_SHAPE_SIZE};
+use crate::update::put_shape_on_board_and_check_collision;
+
+/// How much each board feature counts when the AI compares placements. Line clears are
+/// rewarded, everything else is a penalty.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Weights {
+    pub aggregate_height: f64,
+    pub holes: f64,
+    pub bumpiness: f64,
+    pub wells: f64,
+    pub lines: f64,
+}
+
+impl Default for Weights {
+    /// Weights tuned for survival, after Yiyuan Lee's near perfect Tetris bot.
+    fn default() -> Weights {
+        Weights {
+            aggregate_height: 0.510066,
+            holes: 0.35663,
+            bumpiness: 0.184483,
+            wells: 0.1,
+            lines: 0.760666,
+        }
+    }
+}
+
+/// How well the AI opponent of a versus game plays.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+    Expert,
+}
+
+impl Choice for Difficulty {
+    const ALL: &'static [Difficulty] = &[Difficulty::Easy, Difficulty::Medium, Difficulty::Hard, Difficulty::Expert];
+
+    fn id(self) -> &'static str {
+        match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Medium => "medium",
+            Difficulty::Hard => "hard",
+            Difficulty::Expert => "expert",
+        }
+    }
+}
+
+impl Difficulty {
+    pub fn label(self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Medium => "Medium",
+            Difficulty::Hard => "Hard",
+            Difficulty::Expert => "Expert",
+        }
+    }
+
+    /// The most pieces the AI places per second.
+    pub fn pieces_per_second(self) -> f64 {
+        match self {
+            Difficulty::Easy => 0.5,
+            Difficulty::Medium => 1.0,
+            Difficulty::Hard => 2.0,
+            Difficulty::Expert => 3.5,
+        }
+    }
+
+    /// Whether the AI takes the next shape into account when placing the current one.
+    pub fn looks_ahead(self) -> bool {
+        matches!(self, Difficulty::Hard | Difficulty::Expert)
+    }
+
+    /// The weights the AI plays with, the easier levels leave out more of the `tuned` ones.
+    pub fn weights(self, tuned: Weights) -> Weights {
+        match self {
+            Difficulty::Easy => Weights { holes: tuned.holes / 4.0, bumpiness: 0.0, wells: 0.0, ..tuned },
+            Difficulty::Medium => Weights { wells: 0.0, ..tuned },
+            Difficulty::Hard | Difficulty::Expert => tuned,
+        }
+    }
+}
+
+/// A spot where the falling (or held) shape can come to rest, and how to get it there.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Placement {
+    /// The shape in its final position.
+    pub shape: Shape,
+    pub use_hold: bool,
+    /// Actions that move the shape from where it spawned to just above its final position.
+    pub actions: Vec<Action>,
+}
+
+/// Lists every resting position the falling shape can reach with moves, rotations and
+/// soft drops, and when hold is allowed also those of the shape that hold would bring in.
+pub fn find_placements(game: &Game) -> Vec<Placement> {
+    let rotation_system = game.rotation_system();
+    let mut placements = reachable_placements(&game.falling_shape, &game.locked_squares, rotation_system);
+    if game.ruleset.hold && !game.hold_used {
+        let shape_type = game.held_shape.unwrap_or(game.next_shapes[0].shape_type);
+        let (shape, is_colliding) =
+            put_shape_on_board_and_check_collision(shape_type, rotation_system, &game.locked_squares);
+        if !is_colliding {
+            for mut placement in reachable_placements(&shape, &game.locked_squares, rotation_system) {
+                placement.use_hold = true;
+                placement.actions.insert(0, Action::Hold);
+                placements.push(placement);
+            }
+        }
+    }
+    placements
+}
+
+fn reachable_placements(start: &Shape, board: &Board, rotation_system: &dyn RotationSystem) -> Vec<Placement> {
+    placement::reachable_landings(start, board, rotation_system)
+        .into_iter()
+        .map(|(shape, actions)| Placement { shape, use_hold: false, actions })
+        .collect()
+}
+
+/// Locks the shape on a copy of the board and rates the result, higher is better.
+pub fn evaluate(board: &Board, shape: &Shape, weights: &Weights) -> f64 {
+    let mut board = *board;
+    board.lock(shape);
+    let lines = board.delete_full_rows();
+
+    let heights: Vec<usize> = (0..BOARD_WIDTH_IN_TILES).map(|x| column_height(&board, x)).collect();
+    let aggregate_height: usize = heights.iter().sum();
+    let bumpiness: usize = heights.windows(2).map(|pair| pair[0].abs_diff(pair[1])).sum();
+    let holes: usize = (0..BOARD_WIDTH_IN_TILES)
+        .map(|x| {
+            let top = BOARD_HEIGHT_IN_TILES - heights[x];
+            (top..BOARD_HEIGHT_IN_TILES).filter(|&y| board.row(y) & 1 << x == 0).count()
+        })
+        .sum();
+    // A well is a column lower than both of its neighbours, the walls count as high.
+    let wells: usize = (0..BOARD_WIDTH_IN_TILES)
+        .map(|x| {
+            let left = if x == 0 { BOARD_HEIGHT_IN_TILES } else { heights[x - 1] };
+            let right = heights.get(x + 1).copied().unwrap_or(BOARD_HEIGHT_IN_TILES);
+            left.min(right).saturating_sub(heights[x])
+        })
+        .sum();
+
+    weights.lines * lines as f64
+        - weights.aggregate_height * aggregate_height as f64
+        - weights.holes * holes as f64
+        - weights.bumpiness * bumpiness as f64
+        - weights.wells * wells as f64
+}
+
+fn column_height(board: &Board, x: usize) -> usize {
+    (0..BOARD_HEIGHT_IN_TILES)
+        .find(|&y| board.row(y) & 1 << x != 0)
+        .map_or(0, |y| BOARD_HEIGHT_IN_TILES - y)
+}
+
+/// The placement with the best evaluation, if the shape fits anywhere at all.
+pub fn best_placement(game: &Game, weights: &Weights) -> Option<Placement> {
+    find_placements(game)
+        .into_iter()
+        .map(|placement| (evaluate(&game.locked_squares, &placement.shape, weights), placement))
+        .max_by(|(a, _), (b, _)| a.total_cmp(b))
+        .map(|(_, placement)| placement)
+}
+
+/// Like `best_placement`, but rates every placement by the best placement of the shape that
+/// comes after it, on the board it leaves behind.
+pub fn best_placement_looking_ahead(game: &Game, weights: &Weights) -> Option<Placement> {
+    let rotation_system = game.rotation_system();
+    find_placements(game)
+        .into_iter()
+        .map(|placement| {
+            let mut board = game.locked_squares;
+            board.lock(&placement.shape);
+            let lines = board.delete_full_rows();
+            // Holding with nothing held uses up the first of the next shapes.
+            let next_index = if placement.use_hold && game.held_shape.is_none() { 1 } else { 0 };
+            let (next, is_colliding) = put_shape_on_board_and_check_collision(
+                game.next_shapes[next_index].shape_type, rotation_system, &board);
+            let best_next = if is_colliding {
+                f64::NEG_INFINITY
+            }
+            else {
+                drop_landings(&next, &board)
+                    .iter()
+                    .map(|shape| evaluate(&board, shape, weights))
+                    .fold(f64::NEG_INFINITY, f64::max)
+            };
+            (best_next + weights.lines * lines as f64, placement)
+        })
+        .max_by(|(a, _), (b, _)| a.total_cmp(b))
+        .map(|(_, placement)| placement)
+}
+
+/// Resting positions the shape reaches by turning and moving it at the top of the board and
+/// dropping it straight down. Cheaper than `placement::reachable_landings` where the paths and
+/// tucks under overhangs do not matter.
+fn drop_landings(start: &Shape, board: &Board) -> Vec<Shape> {
+    let mut landings = Vec::new();
+    for rotation in 0..4 {
+        let mut shape = *start;
+        shape.rotate(rotation);
+        for x in -(MAX_SHAPE_SIZE as i16)..BOARD_WIDTH_IN_TILES as i16 {
+            shape.x = x;
+            shape.y = start.y;
+            if board.collides(&shape) {
+                continue;
+            }
+            loop {
+                shape.y += 1;
+                if board.collides(&shape) {
+                    break;
+                }
+            }
+            shape.y -= 1;
+            landings.push(shape);
+        }
+    }
+    landings
+}
+
+/// Plays a game through the same actions as a human player, one action at a time.
+#[derive(Clone, Debug)]
+pub struct Bot {
+    weights: Weights,
+    /// Whether placements are chosen with `best_placement_looking_ahead`.
+    lookahead: bool,
+    plan: VecDeque<Action>,
+    /// Where the falling shape should be when the next action of the plan is applied.
+    expected: Option<Shape>,
+}
+
+impl Bot {
+    pub fn new(weights: Weights) -> Bot {
+        Bot { weights, lookahead: false, plan: VecDeque::new(), expected: None }
+    }
+
+    pub fn with_lookahead(mut self, lookahead: bool) -> Bot {
+        self.lookahead = lookahead;
+        self
+    }
+
+    /// The next action to apply. Returns `Action::None` while waiting for gravity to lock a
+    /// shape when the rules have no hard drop.
+    pub fn next_action(&mut self, game: &Game) -> Action {
+        // Plans are made again when gravity or a new shape moved things in the meantime.
+        if self.expected != Some(game.falling_shape) {
+            self.plan = self.make_plan(game);
+        }
+        let action = self.plan.pop_front().unwrap_or(Action::None);
+        self.expected = falling_shape_after(game, action);
+        action
+    }
+
+    fn make_plan(&self, game: &Game) -> VecDeque<Action> {
+        let placement = if self.lookahead {
+            best_placement_looking_ahead(game, &self.weights)
+        }
+        else {
+            best_placement(game, &self.weights)
+        };
+        match placement {
+            Some(placement) => plan_actions(game, &placement),
+            None => VecDeque::new(),
+        }
+    }
+}
+
+/// The AI player of a versus game, which places shapes no faster than its difficulty allows.
+#[derive(Clone, Debug)]
+pub struct Opponent {
+    pub difficulty: Difficulty,
+    bot: Bot,
+    /// Pieces placed so far, to tell when a new shape comes in.
+    pieces: u32,
+    /// When the AI started on the current shape, and when it may start on the next one.
+    started_at: Instant,
+    next_start: Instant,
+}
+
+impl Opponent {
+    pub fn new(difficulty: Difficulty, weights: Weights) -> Opponent {
+        let bot = Bot::new(difficulty.weights(weights)).with_lookahead(difficulty.looks_ahead());
+        let now = Instant::now();
+        Opponent { difficulty, bot, pieces: 0, started_at: now, next_start: now }
+    }
+
+    /// The next action of the AI, `None` while it waits before starting on a new shape.
+    pub fn next_action(&mut self, game: &Game) -> Option<Action> {
+        let now = Instant::now();
+        if game.stats.pieces != self.pieces {
+            self.pieces = game.stats.pieces;
+            self.next_start = self.started_at + Duration::from_secs_f64(1.0 / self.difficulty.pieces_per_second());
+        }
+        if now < self.next_start {
+            return None;
+        }
+        if self.started_at < self.next_start {
+            self.started_at = now;
+        }
+        Some(self.bot.next_action(game))
+    }
+}
+
+/// The actions that bring the falling shape to the placement and lock it there. When the rules
+/// have no hard drop the shape is soft dropped, which may take a different path, e.g. moving
+/// before dropping rather than after.
+pub fn plan_actions(game: &Game, placement: &Placement) -> VecDeque<Action> {
+    if game.ruleset.hard_drop {
+        let mut plan: VecDeque<Action> = placement.actions.iter().copied().collect();
+        plan.push_back(Action::HardDrop);
+        return plan;
+    }
+    let start = plan_start(game, placement.use_hold);
+    let path = placement::find_path(&start, &placement.shape, &game.locked_squares, game.rotation_system(), false)
+        .unwrap_or_default();
+    let hold = placement.use_hold.then_some(Action::Hold);
+    hold.into_iter().chain(path).collect()
+}
+
+/// Where the falling shape will be after the action, `None` if it will be replaced.
+pub fn falling_shape_after(game: &Game, action: Action) -> Option<Shape> {
+    match action {
+        Action::HardDrop | Action::Hold => None,
+        action => {
+            let mut copy = game.clone();
+            let step = copy.apply_action(action);
+            (!step.locked).then_some(copy.falling_shape)
+        },
+    }
+}
+
+/// The shape the plan starts from, taking a leading hold into account.
+fn plan_start(game: &Game, use_hold: bool) -> Shape {
+    if use_hold {
+        let shape_type: ShapeType = game.held_shape.unwrap_or(game.next_shapes[0].shape_type);
+        put_shape_on_board_and_check_collision(shape_type, game.rotation_system(), &game.locked_squares).0
+    }
+    else {
+        game.falling_shape
+    }
+}
+
+/// Results of letting the bot play a number of games without a terminal.
+#[derive(Clone, Debug, Default)]
+pub struct BenchmarkResult {
+    pub games: Vec<(u32, u32, u32)>,
+    pub thinking_time: Duration,
+}
+
+/// Plays `games` games with the bot, each until it tops out or has placed `max_pieces` pieces.
+/// Gravity is only applied while the bot waits for it, so the results do not depend on speed.
+pub fn benchmark(new_game: impl Fn() -> Game, weights: Weights, games: u32, max_pieces: u32) -> BenchmarkResult {
+    let mut result = BenchmarkResult::default();
+    for _ in 0..games {
+        let mut game = new_game();
+        let mut bot = Bot::new(weights);
+        while game.status == GameStatus::Playing && game.stats.pieces < max_pieces {
+            let started_at = Instant::now();
+            let action = bot.next_action(&game);
+            result.thinking_time += started_at.elapsed();
+            match action {
+                Action::None => game.apply_gravity(),
+                action => game.apply_action(action),
+            };
+        }
+        result.games.push((game.stats.pieces, game.stats.lines, game.score));
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Square;
+    use crate::mode::GameMode;
+    use crate::rotation::Srs;
+    use crate::ruleset::{Ruleset, RulesetName};
+
+    #[test]
+    fn harder_opponents_are_faster_and_think_further() {
+        for pair in Difficulty::ALL.windows(2) {
+            assert!(pair[0].pieces_per_second() < pair[1].pieces_per_second());
+        }
+        assert!(!Difficulty::Easy.looks_ahead());
+        assert!(Difficulty::Expert.looks_ahead());
+        assert_eq!(Difficulty::Expert.weights(Weights::default()), Weights::default());
+    }
+
+    #[test]
+    fn looking_ahead_survives() {
+        let mut game = Game::new(GameMode::Endless, Ruleset::preset(RulesetName::Guideline));
+        let mut bot = Bot::new(Weights::default()).with_lookahead(true);
+        while game.status == GameStatus::Playing && game.stats.pieces < 50 {
+            match bot.next_action(&game) {
+                Action::None => game.apply_gravity(),
+                action => game.apply_action(action),
+            };
+        }
+        assert_eq!(game.status, GameStatus::Playing);
+    }
+
+    #[test]
+    fn opponents_wait_between_pieces() {
+        let mut game = Game::new(GameMode::Endless, Ruleset::preset(RulesetName::Guideline));
+        let mut opponent = Opponent::new(Difficulty::Easy, Weights::default());
+        while game.stats.pieces == 0 {
+            let action = opponent.next_action(&game).expect("the first shape is played right away");
+            game.apply_action(action);
+        }
+        assert_eq!(opponent.next_action(&game), None);
+    }
+
+    #[test]
+    fn evaluation_counts_each_feature() {
+        let mut board = Board::new();
+        board.set(3, 19, Some(Square::Garbage));
+        board.set(5, 17, Some(Square::Garbage));
+        // An O in the bottom left corner: heights 2 2 0 1 0 3 0 0 0 0 with two holes below the 3.
+        let shape = Shape::new(&Srs, ShapeType::O, -1, 18);
+        let only = |weights: Weights| evaluate(&board, &shape, &weights);
+        let none = Weights { aggregate_height: 0.0, holes: 0.0, bumpiness: 0.0, wells: 0.0, lines: 0.0 };
+        assert_eq!(only(Weights { aggregate_height: 1.0, ..none }), -8.0);
+        assert_eq!(only(Weights { holes: 1.0, ..none }), -2.0);
+        assert_eq!(only(Weights { bumpiness: 1.0, ..none }), -10.0);
+        assert_eq!(only(Weights { wells: 1.0, ..none }), -2.0);
+        assert_eq!(only(Weights { lines: 1.0, ..none }), 0.0);
+
+        for x in 2..BOARD_WIDTH_IN_TILES {
+            board.set(x, 19, Some(Square::Garbage));
+        }
+        assert_eq!(evaluate(&board, &shape, &Weights { lines: 1.0, ..none }), 1.0);
+    }
+
+    #[test]
+    fn placements_include_the_shape_from_hold() {
+        let game = Game::new(GameMode::Endless, Ruleset::preset(RulesetName::Guideline));
+        let placements = find_placements(&game);
+        let (held, falling): (Vec<&Placement>, Vec<&Placement>) = placements.iter().partition(|placement| placement.use_hold);
+        assert!(!held.is_empty());
+        assert!(held.iter().all(|placement| placement.shape.shape_type == game.next_shapes[0].shape_type
+            && placement.actions.first() == Some(&Action::Hold)));
+        assert!(!falling.is_empty());
+        assert!(falling.iter().all(|placement| placement.shape.shape_type == game.falling_shape.shape_type));
+    }
+}
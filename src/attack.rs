@@ -0,0 +1,85 @@
+use crate::game::Step;
+
+/// How many garbage lines a clear sends to the opponent.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AttackTable {
+    /// Lines sent for clearing 0 to 4 rows at once.
+    pub clears: [u32; 5],
+    /// Lines sent for T-spins clearing 0 to 3 rows, instead of `clears`.
+    pub t_spins: [u32; 4],
+    /// Lines added for the first, second, ... clear in a row. The last entry is used for
+    /// every longer combo.
+    pub combos: Vec<u32>,
+    /// Lines added for a tetris or T-spin clear following another one.
+    pub back_to_back: u32,
+}
+
+impl Default for AttackTable {
+    /// The guideline table.
+    fn default() -> AttackTable {
+        AttackTable {
+            clears: [0, 0, 1, 2, 4],
+            t_spins: [0, 2, 4, 6],
+            combos: vec![0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 4, 5],
+            back_to_back: 1,
+        }
+    }
+}
+
+impl AttackTable {
+    /// Lines sent for the lock of a shape, 0 for anything else.
+    pub fn attack(&self, step: &Step) -> u32 {
+        if !step.locked || step.rows_deleted == 0 {
+            return 0;
+        }
+        let rows = step.rows_deleted as usize;
+        let mut lines = if step.t_spin {
+            self.t_spins[rows.min(self.t_spins.len() - 1)]
+        }
+        else {
+            self.clears[rows.min(self.clears.len() - 1)]
+        };
+        if let Some(&last) = self.combos.last() {
+            lines += self.combos.get(step.combo as usize - 1).copied().unwrap_or(last);
+        }
+        if step.back_to_back {
+            lines += self.back_to_back;
+        }
+        lines
+    }
+}
+
+/// Parses a list of line counts separated by spaces or commas, e.g. `0 0 1 2 4`.
+pub fn parse_lines(value: &str) -> Result<Vec<u32>, String> {
+    value.split([' ', ',']).filter(|part| !part.is_empty())
+        .map(|part| part.parse().map_err(|_| format!("expected a number of lines, got `{}`", part)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clear(rows_deleted: u8, combo: u32) -> Step {
+        Step { locked: true, rows_deleted, combo, ..Step::default() }
+    }
+
+    #[test]
+    fn sends_lines_for_clears_combos_and_back_to_back() {
+        let table = AttackTable::default();
+        assert_eq!(table.attack(&Step { locked: true, ..Step::default() }), 0);
+        assert_eq!(table.attack(&clear(1, 1)), 0);
+        assert_eq!(table.attack(&clear(4, 1)), 4);
+        assert_eq!(table.attack(&Step { t_spin: true, ..clear(2, 1) }), 4);
+        assert_eq!(table.attack(&Step { back_to_back: true, ..clear(4, 1) }), 5);
+        assert_eq!(table.attack(&clear(1, 3)), 1);
+        // Combos longer than the table keep its last entry.
+        assert_eq!(table.attack(&clear(2, 20)), 6);
+    }
+
+    #[test]
+    fn parses_line_lists() {
+        assert_eq!(parse_lines("0 0 1, 2,4"), Ok(vec![0, 0, 1, 2, 4]));
+        assert!(parse_lines("1 two").is_err());
+    }
+}
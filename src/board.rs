@@ -0,0 +1,189 @@
+use crate::{Square, BOARD_HEIGHT_IN_TILES, BOARD_WIDTH_IN_TILES};
+use crate::shape::Shape;
+
+/// Bits of a row with every column filled, column 0 is the lowest bit.
+pub const FULL_ROW: u16 = (1 << BOARD_WIDTH_IN_TILES) - 1;
+
+/// The locked squares of the board.
+///
+/// Occupancy is kept as one bitmask per row so that collisions and full rows are found with
+/// a few bit operations per row, the squares themselves are only needed to draw the board.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Board {
+    rows: [u16; BOARD_HEIGHT_IN_TILES],
+    squares: [[Option<Square>; BOARD_WIDTH_IN_TILES]; BOARD_HEIGHT_IN_TILES],
+}
+
+impl Board {
+    pub fn new() -> Board {
+        Board {
+            rows: [0; BOARD_HEIGHT_IN_TILES],
+            squares: [[None; BOARD_WIDTH_IN_TILES]; BOARD_HEIGHT_IN_TILES],
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<Square> {
+        self.squares[y][x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, square: Option<Square>) {
+        self.squares[y][x] = square;
+        match square {
+            Some(_) => self.rows[y] |= 1 << x,
+            None => self.rows[y] &= !(1 << x),
+        }
+    }
+
+    /// Occupancy bits of a row, column 0 is the lowest bit.
+    pub fn row(&self, y: usize) -> u16 {
+        self.rows[y]
+    }
+
+    pub fn is_row_full(&self, y: usize) -> bool {
+        self.rows[y] == FULL_ROW
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.iter().all(|&row| row == 0)
+    }
+
+    /// Every locked square with its position.
+    pub fn squares(&self) -> impl Iterator<Item = (usize, usize, Square)> + '_ {
+        self.squares.iter().enumerate().flat_map(|(y, row)| {
+            row.iter().enumerate().filter_map(move |(x, square)| square.map(|square| (x, y, square)))
+        })
+    }
+
+    /// Whether the shape overlaps a locked square or sticks out of the board.
+    pub fn collides(&self, shape: &Shape) -> bool {
+        for (dy, &mask) in shape.get_cells().row_masks().iter().enumerate() {
+            if mask == 0 {
+                continue;
+            }
+            let y = shape.y + dy as i16;
+            if y < 0 || y >= BOARD_HEIGHT_IN_TILES as i16 {
+                return true;
+            }
+            // Shifts are capped, anything moved that far is off the board either way.
+            let shifted = if shape.x >= 0 {
+                (mask as u32) << shape.x.min(16)
+            }
+            else {
+                let shift = (-shape.x).min(16) as u32;
+                // Cells that would be shifted out of the mask are past the left wall.
+                if mask as u32 & ((1 << shift) - 1) != 0 {
+                    return true;
+                }
+                mask as u32 >> shift
+            };
+            if shifted & !(FULL_ROW as u32) != 0 || shifted as u16 & self.rows[y as usize] != 0 {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Writes the squares of the shape into the board.
+    pub fn lock(&mut self, shape: &Shape) {
+        for (x, y) in shape.get_occupied_squares() {
+            self.set(x as usize, y as usize, Some(Square::Shape(shape.shape_type)));
+        }
+    }
+
+    /// Removes all full rows, moving the rows above them down. Returns the number removed.
+    pub fn delete_full_rows(&mut self) -> u8 {
+        let mut copy_to_y = BOARD_HEIGHT_IN_TILES;
+        for y in (0..BOARD_HEIGHT_IN_TILES).rev() {
+            if self.is_row_full(y) {
+                continue;
+            }
+            copy_to_y -= 1;
+            self.rows[copy_to_y] = self.rows[y];
+            self.squares[copy_to_y] = self.squares[y];
+        }
+        for y in 0..copy_to_y {
+            self.rows[y] = 0;
+            self.squares[y] = [None; BOARD_WIDTH_IN_TILES];
+        }
+        copy_to_y as u8
+    }
+
+    /// Moves every row up by `count`, leaving empty rows at the bottom.
+    /// Returns `true` if any locked square was pushed out of the top of the board.
+    pub fn push_up(&mut self, count: usize) -> bool {
+        let count = count.min(BOARD_HEIGHT_IN_TILES);
+        let overflowed = self.rows[..count].iter().any(|&row| row != 0);
+        self.rows.rotate_left(count);
+        self.squares.rotate_left(count);
+        for y in BOARD_HEIGHT_IN_TILES - count..BOARD_HEIGHT_IN_TILES {
+            self.rows[y] = 0;
+            self.squares[y] = [None; BOARD_WIDTH_IN_TILES];
+        }
+        overflowed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rotation::RotationSystemName;
+    use crate::shape::ShapeType;
+
+    fn shape(shape_type: ShapeType, x: i16, y: i16) -> Shape {
+        Shape::new(RotationSystemName::Srs.system(), shape_type, x, y)
+    }
+
+    #[test]
+    fn collides_with_walls_and_floor() {
+        let board = Board::new();
+        // The I lies in the second row of its box.
+        assert!(!board.collides(&shape(ShapeType::I, 0, 0)));
+        assert!(board.collides(&shape(ShapeType::I, -1, 0)));
+        assert!(!board.collides(&shape(ShapeType::I, 6, 0)));
+        assert!(board.collides(&shape(ShapeType::I, 7, 0)));
+        assert!(!board.collides(&shape(ShapeType::I, 0, -1)));
+        assert!(board.collides(&shape(ShapeType::I, 0, -2)));
+        assert!(!board.collides(&shape(ShapeType::I, 0, 18)));
+        assert!(board.collides(&shape(ShapeType::I, 0, 19)));
+        // The O has an empty first column, so its box may stick out on the left.
+        assert!(!board.collides(&shape(ShapeType::O, -1, 0)));
+        assert!(board.collides(&shape(ShapeType::O, -2, 0)));
+        assert!(board.collides(&shape(ShapeType::O, 30, 0)));
+    }
+
+    #[test]
+    fn collides_with_locked_squares() {
+        let mut board = Board::new();
+        board.set(4, 19, Some(Square::Garbage));
+        assert!(board.collides(&shape(ShapeType::T, 3, 18)));
+        assert!(!board.collides(&shape(ShapeType::T, 3, 17)));
+        board.set(4, 19, None);
+        assert!(!board.collides(&shape(ShapeType::T, 3, 18)));
+    }
+
+    #[test]
+    fn deletes_full_rows() {
+        let mut board = Board::new();
+        for x in 0..BOARD_WIDTH_IN_TILES {
+            board.set(x, 19, Some(Square::Garbage));
+            board.set(x, 17, Some(Square::Garbage));
+        }
+        board.set(0, 18, Some(Square::Garbage));
+        board.set(5, 16, Some(Square::Shape(ShapeType::T)));
+        assert_eq!(board.delete_full_rows(), 2);
+        assert_eq!(board.rows[19], 1);
+        assert_eq!(board.rows[18], 1 << 5);
+        assert_eq!(board.get(5, 18), Some(Square::Shape(ShapeType::T)));
+        assert!(board.rows[..18].iter().all(|&row| row == 0));
+    }
+
+    #[test]
+    fn push_up_reports_overflow() {
+        let mut board = Board::new();
+        board.set(0, 1, Some(Square::Garbage));
+        assert!(!board.push_up(1));
+        assert_eq!(board.get(0, 0), Some(Square::Garbage));
+        assert_eq!(board.rows[19], 0);
+        assert!(board.push_up(1));
+    }
+}
@@ -0,0 +1,59 @@
+/// A setting with a fixed list of values, written by its id in the config file and cycled through
+/// in the start menu.
+pub trait Choice: Copy + PartialEq + 'static {
+    /// Every value, in the order the menu goes through them.
+    const ALL: &'static [Self];
+
+    fn id(self) -> &'static str;
+
+    fn from_id(id: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|choice| choice.id() == id)
+    }
+
+    /// The value after this one, the first after the last.
+    fn next(self) -> Self {
+        Self::ALL[(position(self) + 1) % Self::ALL.len()]
+    }
+
+    /// The value before this one, the last before the first.
+    fn prev(self) -> Self {
+        Self::ALL[(position(self) + Self::ALL.len() - 1) % Self::ALL.len()]
+    }
+}
+
+fn position<C: Choice>(choice: C) -> usize {
+    C::ALL.iter().position(|&other| other == choice).expect("every value is listed in ALL")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::Difficulty;
+    use crate::mode::ModeName;
+    use crate::rotation::RotationSystemName;
+    use crate::royale::Targeting;
+    use crate::ruleset::RulesetName;
+    use crate::theme::ThemeName;
+    use crate::versus::Players;
+
+    fn assert_choice<C: Choice + std::fmt::Debug>() {
+        for &choice in C::ALL {
+            assert_eq!(C::from_id(choice.id()), Some(choice));
+            assert_eq!(choice.next().prev(), choice);
+        }
+        assert_eq!(C::ALL[C::ALL.len() - 1].next(), C::ALL[0]);
+        assert_eq!(C::ALL[0].prev(), C::ALL[C::ALL.len() - 1]);
+        assert_eq!(C::from_id("unknown"), None);
+    }
+
+    #[test]
+    fn ids_round_trip_and_the_menu_wraps_around() {
+        assert_choice::<Difficulty>();
+        assert_choice::<ModeName>();
+        assert_choice::<RotationSystemName>();
+        assert_choice::<Targeting>();
+        assert_choice::<RulesetName>();
+        assert_choice::<ThemeName>();
+        assert_choice::<Players>();
+    }
+}
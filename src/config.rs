@@ -0,0 +1,462 @@
+use std::{env, fs};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use crate::ai::{Difficulty, Weights};
+use crate::attack::{self, AttackTable};
+use crate::choice::Choice;
+use crate::editor::Position;
+use crate::garbage::GarbageQueue;
+use crate::mode::{GameMode, MarathonGoal, ModeName};
+use crate::piece_set::PieceSet;
+use crate::rotation::RotationSystemName;
+use crate::ruleset::{Ruleset, RulesetName};
+use crate::royale::Targeting;
+use crate::versus::Players;
+use crate::theme::{ColorSupport, ThemeName};
+
+/// User settings, read from the config file and overridden by command line flags.
+///
+/// The config file is a list of `key = value` lines, `#` starts a comment:
+///
+/// ```text
+/// theme = colour-blind-safe
+/// colors = 256
+/// mode = sprint
+/// rules = guideline
+/// ```
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub theme: ThemeName,
+    pub color_support: ColorSupport,
+    pub mode: ModeName,
+    pub ruleset: RulesetName,
+    /// Replaces the rotation system of the rule set when set.
+    pub rotation: Option<RotationSystemName>,
+    /// Replaces the tetrominoes when set.
+    pub piece_set: Option<Arc<PieceSet>>,
+    pub sprint_lines: u32,
+    pub ultra_seconds: u32,
+    pub dig_lines: u32,
+    pub dig_messiness: u32,
+    pub marathon_goal: MarathonGoal,
+    pub marathon_endless: bool,
+    pub finesse_replay: bool,
+    pub players: Players,
+    /// Garbage sent for clears in versus games.
+    pub attack: AttackTable,
+    /// How long garbage sent in versus games waits before it can come in.
+    pub garbage_delay: Duration,
+    /// Percentage of garbage rows in versus games whose hole moves from the row above.
+    pub garbage_messiness: u32,
+    /// Let the AI play instead of the keyboard.
+    pub autoplay: bool,
+    pub ai_weights: Weights,
+    /// How well the AI opponent plays in versus games against it.
+    pub ai_difficulty: Difficulty,
+    /// Players of a battle royale, including the one at the keyboard.
+    pub royale_players: u32,
+    /// Who the player sends garbage to in a battle royale.
+    pub royale_targeting: Targeting,
+    /// Games played and pieces per game for the `bench` command.
+    pub bench_games: u32,
+    pub bench_pieces: u32,
+    /// Command of an external bot to play instead of the keyboard, speaking the Tetris Bot Protocol.
+    pub bot: Option<String>,
+    /// Address on which spectators can watch the games played.
+    pub broadcast: Option<String>,
+    /// Address of a broadcast game to watch instead of playing.
+    pub spectate: Option<String>,
+    /// Puzzle pack offered next to those of the data directory.
+    pub puzzles: Option<PathBuf>,
+    /// Openers offered next to those of the data directory.
+    pub openers: Option<PathBuf>,
+    /// The position the board editor starts from, empty when not set.
+    pub position: Option<Position>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            theme: ThemeName::Guideline,
+            color_support: ColorSupport::detect(),
+            mode: ModeName::Endless,
+            ruleset: RulesetName::Standard,
+            rotation: None,
+            piece_set: None,
+            sprint_lines: 40,
+            ultra_seconds: 120,
+            dig_lines: 18,
+            dig_messiness: 30,
+            marathon_goal: MarathonGoal::Lines(150),
+            marathon_endless: false,
+            finesse_replay: false,
+            players: Players::Single,
+            attack: AttackTable::default(),
+            garbage_delay: Duration::from_millis(500),
+            garbage_messiness: 0,
+            autoplay: false,
+            ai_weights: Weights::default(),
+            ai_difficulty: Difficulty::Medium,
+            royale_players: 8,
+            royale_targeting: Targeting::Random,
+            bench_games: 10,
+            bench_pieces: 1000,
+            bot: None,
+            broadcast: None,
+            spectate: None,
+            puzzles: None,
+            openers: None,
+            position: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn load() -> Result<Config, String> {
+        let args: Vec<String> = env::args().skip(1).collect();
+        let mut config = Config::default();
+
+        let path = match flag_value(&args, "--config")? {
+            Some(path) => Some(PathBuf::from(path)),
+            None => default_config_path().filter(|path| path.exists()),
+        };
+        if let Some(path) = path {
+            let contents = fs::read_to_string(&path)
+                .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+            config.apply_file(&contents)?;
+        }
+
+        if let Some(theme) = flag_value(&args, "--theme")? {
+            config.set("theme", theme)?;
+        }
+        if let Some(colors) = flag_value(&args, "--colors")? {
+            config.set("colors", colors)?;
+        }
+        if let Some(mode) = flag_value(&args, "--mode")? {
+            config.set("mode", mode)?;
+        }
+        if let Some(ruleset) = flag_value(&args, "--rules")? {
+            config.set("rules", ruleset)?;
+        }
+        if let Some(rotation) = flag_value(&args, "--rotation")? {
+            config.set("rotation", rotation)?;
+        }
+        if let Some(pieces) = flag_value(&args, "--pieces")? {
+            config.set("pieces", pieces)?;
+        }
+        if let Some(players) = flag_value(&args, "--players")? {
+            config.set("players", players)?;
+        }
+        if let Some(difficulty) = flag_value(&args, "--difficulty")? {
+            config.set("ai_difficulty", difficulty)?;
+        }
+        if let Some(players) = flag_value(&args, "--royale-players")? {
+            config.set("royale_players", players)?;
+        }
+        if let Some(targeting) = flag_value(&args, "--targeting")? {
+            config.set("royale_targeting", targeting)?;
+        }
+        if args.iter().any(|arg| arg == "--autoplay") {
+            config.autoplay = true;
+        }
+        if let Some(games) = flag_value(&args, "--games")? {
+            config.bench_games = parse_positive(games)?;
+        }
+        if let Some(pieces) = flag_value(&args, "--max-pieces")? {
+            config.bench_pieces = parse_positive(pieces)?;
+        }
+        if let Some(puzzles) = flag_value(&args, "--puzzles")? {
+            config.set("puzzles", puzzles)?;
+        }
+        if let Some(openers) = flag_value(&args, "--openers")? {
+            config.set("openers", openers)?;
+        }
+        if let Some(position) = flag_value(&args, "--position")? {
+            config.set("position", position)?;
+        }
+        if let Some(bot) = flag_value(&args, "--bot")? {
+            config.set("bot", bot)?;
+        }
+        if let Some(address) = flag_value(&args, "--broadcast")? {
+            config.set("broadcast", address)?;
+        }
+        config.spectate = flag_value(&args, "--spectate")?.map(str::to_string);
+        config.check()?;
+        Ok(config)
+    }
+
+    /// Refuses settings that cannot be played together.
+    fn check(&self) -> Result<(), String> {
+        if self.bot.is_some() && self.piece_set.is_some() {
+            return Err("bots can only play the standard pieces".to_string());
+        }
+        if self.piece_set.is_some() && self.mode.needs_tetrominoes() {
+            return Err(format!("the {} mode can only be played with the standard pieces", self.mode.id()));
+        }
+        if !self.allows_players(self.players) {
+            return Err(format!("the {} mode can only be played alone", self.mode.id()));
+        }
+        Ok(())
+    }
+
+    /// Whether the mode can be played with the pieces and players of the config.
+    pub fn allows_mode(&self, mode: ModeName) -> bool {
+        (self.piece_set.is_none() || !mode.needs_tetrominoes()) && (self.players == Players::Single || !mode.plays_alone())
+    }
+
+    /// Whether the players can play the mode of the config.
+    pub fn allows_players(&self, players: Players) -> bool {
+        players == Players::Single || !self.mode.plays_alone()
+    }
+
+    pub fn game_mode(&self) -> GameMode {
+        match self.mode {
+            ModeName::Endless => GameMode::Endless,
+            ModeName::Sprint => GameMode::Sprint { lines: self.sprint_lines },
+            ModeName::Ultra => GameMode::Ultra { duration: Duration::from_secs(self.ultra_seconds as u64) },
+            ModeName::Dig => GameMode::Dig { lines: self.dig_lines, messiness: self.dig_messiness },
+            ModeName::Puzzle => GameMode::Puzzle,
+            ModeName::PerfectClear => GameMode::PerfectClear,
+            ModeName::Opener => GameMode::Opener,
+            ModeName::Editor => GameMode::Editor,
+            ModeName::Marathon => GameMode::Marathon { goal: self.marathon_goal, endless: self.marathon_endless },
+            ModeName::Finesse => GameMode::Finesse { replay: self.finesse_replay },
+        }
+    }
+
+    /// An empty queue for the garbage sent to a player in versus games.
+    pub fn garbage_queue(&self) -> GarbageQueue {
+        GarbageQueue::new(self.garbage_delay, self.garbage_messiness as f64 / 100.0)
+    }
+
+    pub fn game_ruleset(&self) -> Ruleset {
+        let mut ruleset = Ruleset::preset(self.ruleset);
+        if let Some(rotation) = self.rotation {
+            ruleset.rotation = rotation;
+        }
+        ruleset.piece_set = self.piece_set.clone();
+        ruleset
+    }
+
+    fn apply_file(&mut self, contents: &str) -> Result<(), String> {
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = line.split_once('=')
+                .ok_or_else(|| format!("config line {}: expected `key = value`", i + 1))?;
+            self.set(key.trim(), value.trim())
+                .map_err(|e| format!("config line {}: {}", i + 1, e))?;
+        }
+        Ok(())
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "theme" => {
+                self.theme = ThemeName::from_id(value)
+                    .ok_or_else(|| format!("unknown theme `{}`", value))?;
+            },
+            "colors" => {
+                if value != "auto" {
+                    self.color_support = ColorSupport::from_id(value)
+                        .ok_or_else(|| format!("unknown color support `{}`, expected 16, 256 or truecolor", value))?;
+                }
+            },
+            "mode" => {
+                self.mode = ModeName::from_id(value)
+                    .ok_or_else(|| format!("unknown mode `{}`", value))?;
+            },
+            "rules" => {
+                self.ruleset = RulesetName::from_id(value)
+                    .ok_or_else(|| format!("unknown rule set `{}`", value))?;
+            },
+            "rotation" => {
+                self.rotation = match value {
+                    "default" => None,
+                    _ => Some(RotationSystemName::from_id(value)
+                        .ok_or_else(|| format!("unknown rotation system `{}`", value))?),
+                };
+            },
+            "pieces" => {
+                self.piece_set = match value {
+                    "tetromino" => None,
+                    _ => Some(Arc::new(PieceSet::load(&piece_set_path(value))?)),
+                };
+            },
+            "sprint_lines" => self.sprint_lines = parse_positive(value)?,
+            "ultra_seconds" => self.ultra_seconds = parse_positive(value)?,
+            "dig_lines" => self.dig_lines = parse_positive(value)?,
+            "dig_messiness" => self.dig_messiness = parse_percentage(value)?,
+            "marathon_lines" => self.marathon_goal = MarathonGoal::Lines(parse_positive(value)?),
+            "marathon_level" => self.marathon_goal = MarathonGoal::Level(parse_positive(value)?),
+            "marathon_endless" => self.marathon_endless = parse_bool(value)?,
+            "finesse_replay" => self.finesse_replay = parse_bool(value)?,
+            "players" => {
+                self.players = Players::from_id(value)
+                    .ok_or_else(|| format!("unknown players `{}`, expected single, versus, ai or royale", value))?;
+            },
+            "attack_clears" => self.attack.clears = parse_fixed_lines(value)?,
+            "attack_t_spins" => self.attack.t_spins = parse_fixed_lines(value)?,
+            "attack_combos" => self.attack.combos = attack::parse_lines(value)?,
+            "attack_back_to_back" => {
+                self.attack.back_to_back = value.parse()
+                    .map_err(|_| format!("expected a number of lines, got `{}`", value))?;
+            },
+            "garbage_delay" => {
+                let milliseconds: u64 = value.parse()
+                    .map_err(|_| format!("expected a number of milliseconds, got `{}`", value))?;
+                self.garbage_delay = Duration::from_millis(milliseconds);
+            },
+            "garbage_messiness" => self.garbage_messiness = parse_percentage(value)?,
+            "autoplay" => self.autoplay = parse_bool(value)?,
+            "puzzles" => self.puzzles = Some(PathBuf::from(value)),
+            "openers" => self.openers = Some(PathBuf::from(value)),
+            "position" => self.position = Some(Position::parse(value)?),
+            "bot" => self.bot = Some(value.to_string()).filter(|command| !command.trim().is_empty()),
+            "broadcast" => self.broadcast = Some(value.to_string()).filter(|address| !address.is_empty()),
+            "ai_difficulty" => {
+                self.ai_difficulty = Difficulty::from_id(value)
+                    .ok_or_else(|| format!("unknown difficulty `{}`, expected easy, medium, hard or expert", value))?;
+            },
+            "royale_players" => {
+                self.royale_players = parse_positive(value)?;
+                if self.royale_players < 2 {
+                    return Err("a battle royale needs at least 2 players".to_string());
+                }
+            },
+            "royale_targeting" => {
+                self.royale_targeting = Targeting::from_id(value)
+                    .ok_or_else(|| format!("unknown targeting `{}`, expected random, attackers, kos or badges", value))?;
+            },
+            "ai_height" => self.ai_weights.aggregate_height = parse_weight(value)?,
+            "ai_holes" => self.ai_weights.holes = parse_weight(value)?,
+            "ai_bumpiness" => self.ai_weights.bumpiness = parse_weight(value)?,
+            "ai_wells" => self.ai_weights.wells = parse_weight(value)?,
+            "ai_lines" => self.ai_weights.lines = parse_weight(value)?,
+            _ => return Err(format!("unknown setting `{}`", key)),
+        }
+        Ok(())
+    }
+}
+
+fn parse_positive(value: &str) -> Result<u32, String> {
+    match value.parse() {
+        Ok(number) if number > 0 => Ok(number),
+        _ => Err(format!("expected a positive number, got `{}`", value)),
+    }
+}
+
+/// Parses a list of exactly `N` line counts.
+fn parse_fixed_lines<const N: usize>(value: &str) -> Result<[u32; N], String> {
+    let lines = attack::parse_lines(value)?;
+    let count = lines.len();
+    lines.try_into().map_err(|_| format!("expected {} numbers of lines, got {}", N, count))
+}
+
+fn parse_weight(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(weight) if weight.is_finite() => Ok(weight),
+        _ => Err(format!("expected a number, got `{}`", value)),
+    }
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value {
+        "true" | "yes" | "on" => Ok(true),
+        "false" | "no" | "off" => Ok(false),
+        _ => Err(format!("expected true or false, got `{}`", value)),
+    }
+}
+
+fn parse_percentage(value: &str) -> Result<u32, String> {
+    match value.parse() {
+        Ok(number) if number <= 100 => Ok(number),
+        _ => Err(format!("expected a percentage between 0 and 100, got `{}`", value)),
+    }
+}
+
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Result<Option<&'a str>, String> {
+    match args.iter().position(|arg| arg == flag) {
+        Some(i) => args.get(i + 1)
+            .map(|value| Some(value.as_str()))
+            .ok_or_else(|| format!("{} expects a value", flag)),
+        None => Ok(None),
+    }
+}
+
+fn default_config_path() -> Option<PathBuf> {
+    let config_dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_dir.join("rust-tetris").join("config"))
+}
+
+/// Directory where records and other generated files are kept.
+pub fn data_dir() -> Option<PathBuf> {
+    let data_dir = match env::var_os("XDG_DATA_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".local").join("share"),
+    };
+    Some(data_dir.join("rust-tetris"))
+}
+
+/// The files in the given directory of the data directory, sorted by name, followed by the file
+/// given in the config.
+pub fn data_files(dir: &str, configured: Option<&PathBuf>) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    if let Some(dir) = data_dir().map(|data_dir| data_dir.join(dir))
+        && let Ok(entries) = fs::read_dir(dir) {
+        paths.extend(entries.filter_map(|entry| Some(entry.ok()?.path())).filter(|path| path.is_file()));
+        paths.sort();
+    }
+    paths.extend(configured.cloned());
+    paths
+}
+
+/// Resolves a piece set given either as a path or by the name of a file in the `pieces`
+/// directory of the data directory.
+fn piece_set_path(value: &str) -> PathBuf {
+    let path = PathBuf::from(value);
+    if path.exists() {
+        return path;
+    }
+    data_dir().map(|dir| dir.join("pieces").join(value)).filter(|path| path.exists()).unwrap_or(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modes_made_for_tetrominoes_refuse_piece_sets() {
+        let mut config = Config { mode: ModeName::Puzzle, ..Config::default() };
+        assert_eq!(config.check(), Ok(()));
+        config.piece_set = Some(Arc::new(PieceSet::parse("piece I3\ncolor = #00ffff\nXXX\n").unwrap()));
+        assert_eq!(config.check(), Err("the puzzle mode can only be played with the standard pieces".to_string()));
+        assert!(!config.allows_mode(ModeName::Puzzle));
+        assert!(!config.allows_mode(ModeName::PerfectClear));
+        assert!(!config.allows_mode(ModeName::Opener));
+        assert!(!config.allows_mode(ModeName::Editor));
+        assert!(config.allows_mode(ModeName::Sprint));
+        config.mode = ModeName::Sprint;
+        assert_eq!(config.check(), Ok(()));
+    }
+
+    #[test]
+    fn modes_played_alone_refuse_opponents() {
+        let mut config = Config { mode: ModeName::PerfectClear, players: Players::Ai, ..Config::default() };
+        assert_eq!(config.check(), Err("the pc mode can only be played alone".to_string()));
+        for mode in [ModeName::Puzzle, ModeName::PerfectClear, ModeName::Opener, ModeName::Editor] {
+            assert!(!config.allows_mode(mode));
+        }
+        assert!(config.allows_mode(ModeName::Ultra));
+        assert!(!config.allows_players(Players::Versus) && !config.allows_players(Players::Royale));
+        assert!(config.allows_players(Players::Single));
+        config.players = Players::Single;
+        assert_eq!(config.check(), Ok(()));
+    }
+}
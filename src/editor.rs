@@ -0,0 +1,415 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::time::Duration;
+use crate::{NextShapes, Square, BOARD_HEIGHT_IN_TILES, BOARD_WIDTH_IN_TILES};
+use crate::ai::{self, Weights};
+use crate::board::Board;
+use crate::config::{data_dir, Config};
+use crate::game::{Game, GameStatus};
+use crate::input::{receive_editor_input, wait_for_key, EditorAction};
+use crate::mode::GameMode;
+use crate::net::square_to_char;
+use crate::puzzle::{board_from_rows, board_rows, parse_hold, parse_pieces, parse_row, play_until_back, Puzzle,
+    PuzzleGoal, PuzzlePack};
+use crate::randomizer::Randomizer;
+use crate::records::Records;
+use crate::render::{self, Layout};
+use crate::rotation::RotationSystem;
+use crate::ruleset::Ruleset;
+use crate::shape::{Shape, ShapeType};
+use crate::theme::Theme;
+
+/// Most pieces the queue of a position takes, three bags.
+const MAX_QUEUE: usize = 21;
+/// Goals a position saved as a puzzle can be given.
+const GOALS: [PuzzleGoal; 6] = [PuzzleGoal::Lines(1), PuzzleGoal::Lines(2), PuzzleGoal::Lines(3), PuzzleGoal::Lines(4),
+    PuzzleGoal::TSpinDouble, PuzzleGoal::PerfectClear];
+/// The pack in the `puzzles` directory of the data directory that positions are saved to.
+const SAVED_PACK: &str = "edited";
+
+/// A board with the pieces known to come and the held one, made in the board editor.
+///
+/// Positions are shared as codes: the rows of the board from the highest one with a square in it
+/// down, separated by `/` and drawn like puzzle boards, then `:` and the pieces of the queue and
+/// after another `:` the held piece, if any. Spaces and line breaks in codes are ignored.
+///
+/// ```text
+/// GGGG..GGGG/GGGG..GGGG:OT:I
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Position {
+    pub board: Board,
+    /// Dealt in this order before the pieces of the randomizer.
+    pub queue: Vec<ShapeType>,
+    pub hold: Option<ShapeType>,
+}
+
+impl Position {
+    pub fn new() -> Position {
+        Position { board: Board::new(), queue: Vec::new(), hold: None }
+    }
+
+    pub fn parse(code: &str) -> Result<Position, String> {
+        let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+        let parts: Vec<&str> = code.split(':').collect();
+        let (rows, queue, hold) = match *parts.as_slice() {
+            [rows] => (rows, "", ""),
+            [rows, queue] => (rows, queue, ""),
+            [rows, queue, hold] => (rows, queue, hold),
+            _ => return Err(format!("expected rows, pieces and a held piece separated by `:`, got `{}`", code)),
+        };
+        let rows = match rows {
+            "" => Vec::new(),
+            rows => rows.split('/').map(parse_row).collect::<Result<Vec<_>, _>>()?,
+        };
+        let board = board_from_rows(&rows)?;
+        let hold = match hold {
+            "" => None,
+            hold => Some(parse_hold(hold)?),
+        };
+        Ok(Position { board, queue: parse_pieces(queue)?, hold })
+    }
+
+    pub fn code(&self) -> String {
+        let hold = self.hold.map_or(String::new(), |shape_type| letters(&[shape_type]));
+        format!("{}:{}:{}", board_rows(&self.board).join("/"), letters(&self.queue), hold)
+    }
+
+    /// A game on the board of the position that deals the queue before the pieces of the rule
+    /// set, starting with the held piece when the rule set has hold.
+    pub fn start(&self, ruleset: Ruleset) -> Game {
+        let mut game = Game::new(GameMode::Editor, ruleset);
+        let mut randomizer = Randomizer::new(game.ruleset.randomizer, game.ruleset.shape_types());
+        randomizer.deal_first(&self.queue);
+        game.set_up(self.board, randomizer);
+        if game.ruleset.hold {
+            game.held_shape = self.hold;
+        }
+        game
+    }
+
+    pub fn puzzle(&self, name: &str, goal: PuzzleGoal) -> Puzzle {
+        Puzzle { name: name.to_string(), goal, pieces: self.queue.clone(), hold: self.hold, board: self.board }
+    }
+
+    /// Adds the position with the goal to the pack of saved positions, where the puzzle mode
+    /// finds it. Returns the name of the new puzzle.
+    fn save(&self, goal: PuzzleGoal) -> Result<String, String> {
+        if self.queue.is_empty() {
+            return Err("the queue is empty".to_string());
+        }
+        let dir = data_dir().ok_or("there is no data directory")?.join("puzzles");
+        fs::create_dir_all(&dir).map_err(|e| format!("could not create {}: {}", dir.display(), e))?;
+        let path = dir.join(SAVED_PACK);
+        let count = if path.exists() { PuzzlePack::load(&path)?.puzzles.len() } else { 0 };
+        let name = format!("Position {}", count + 1);
+        let header = if count == 0 { "name = Edited\n" } else { "" };
+        let text = format!("{}\n{}", header, self.puzzle(&name, goal).to_text());
+        OpenOptions::new().create(true).append(true).open(&path)
+            .and_then(|mut file| file.write_all(text.as_bytes()))
+            .map_err(|e| format!("could not write {}: {}", path.display(), e))?;
+        Ok(name)
+    }
+
+    /// Lets the AI play the pieces of the queue from the position, hard dropping them so that
+    /// they land where it planned. Returns the shapes it locked and the game they leave.
+    pub fn analyse(&self, ruleset: Ruleset, weights: &Weights) -> (Vec<Shape>, Game) {
+        let mut game = self.start(Ruleset { hard_drop: true, ..ruleset });
+        let mut shapes = Vec::new();
+        while game.status == GameStatus::Playing && shapes.len() < self.queue.len() {
+            let Some(placement) = ai::best_placement(&game, weights) else {
+                break;
+            };
+            for action in ai::plan_actions(&game, &placement) {
+                game.apply_action(action);
+            }
+            shapes.push(placement.shape);
+        }
+        (shapes, game)
+    }
+}
+
+fn letters(shape_types: &[ShapeType]) -> String {
+    shape_types.iter().map(|&shape_type| square_to_char(Some(Square::Shape(shape_type)))).collect()
+}
+
+/// The part of the position the keys of the board editor change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Field {
+    /// The squares of the board, painted with the brush.
+    Board,
+    Queue,
+    Hold,
+    /// The goal the position gets when saved as a puzzle.
+    Goal,
+}
+
+impl Field {
+    const ALL: [Field; 4] = [Field::Board, Field::Queue, Field::Hold, Field::Goal];
+
+    fn next(self) -> Field {
+        let i = Field::ALL.iter().position(|&field| field == self).unwrap();
+        Field::ALL[(i + 1) % Field::ALL.len()]
+    }
+}
+
+/// The position being edited and how the keys change it.
+#[derive(Clone, Debug)]
+struct Editor {
+    position: Position,
+    cursor: (usize, usize),
+    brush: Square,
+    field: Field,
+    goal: PuzzleGoal,
+}
+
+impl Editor {
+    fn new(position: Position) -> Editor {
+        Editor {
+            position,
+            cursor: (0, BOARD_HEIGHT_IN_TILES - 1),
+            brush: Square::Garbage,
+            field: Field::Board,
+            goal: GOALS[0],
+        }
+    }
+
+    fn apply(&mut self, action: EditorAction) {
+        let (x, y) = self.cursor;
+        let goal = GOALS.iter().position(|&goal| goal == self.goal).unwrap_or(0);
+        let position = &mut self.position;
+        match (action, self.field) {
+            (EditorAction::Up, Field::Board) => self.cursor.1 = y.saturating_sub(1),
+            (EditorAction::Down, Field::Board) => self.cursor.1 = (y + 1).min(BOARD_HEIGHT_IN_TILES - 1),
+            (EditorAction::Left, Field::Board) => self.cursor.0 = x.saturating_sub(1),
+            (EditorAction::Right, Field::Board) => self.cursor.0 = (x + 1).min(BOARD_WIDTH_IN_TILES - 1),
+            (EditorAction::Left, Field::Goal) => self.goal = GOALS[(goal + GOALS.len() - 1) % GOALS.len()],
+            (EditorAction::Right, Field::Goal) => self.goal = GOALS[(goal + 1) % GOALS.len()],
+            (EditorAction::Paint, Field::Board) => position.board.set(x, y, Some(self.brush)),
+            (EditorAction::Piece(square), Field::Board) => {
+                self.brush = square;
+                position.board.set(x, y, Some(square));
+            },
+            (EditorAction::Piece(Square::Shape(shape_type)), Field::Queue) if position.queue.len() < MAX_QUEUE => {
+                position.queue.push(shape_type);
+            },
+            (EditorAction::Piece(Square::Shape(shape_type)), Field::Hold) => position.hold = Some(shape_type),
+            (EditorAction::Erase, Field::Board) => position.board.set(x, y, None),
+            (EditorAction::Erase, Field::Queue) => {
+                position.queue.pop();
+            },
+            (EditorAction::Erase, Field::Hold) => position.hold = None,
+            (EditorAction::FillRow, _) => {
+                for column in 0..BOARD_WIDTH_IN_TILES {
+                    position.board.set(column, y, (column != x).then_some(Square::Garbage));
+                }
+            },
+            (EditorAction::Clear, _) => position.board = Board::new(),
+            (EditorAction::NextField, _) => self.field = self.field.next(),
+            _ => {},
+        }
+    }
+
+    /// The side panel: the fields, the one being edited marked, followed by the keys.
+    fn lines(&self) -> Vec<String> {
+        let marker = |field: Field| if field == self.field { ">" } else { " " };
+        let queue = if self.position.queue.is_empty() {
+            vec!["-".to_string()]
+        }
+        else {
+            self.position.queue.chunks(7).map(letters).collect()
+        };
+        let hold = self.position.hold.map_or("-".to_string(), |shape_type| letters(&[shape_type]));
+        let mut lines = vec![
+            "Editor".to_string(),
+            String::new(),
+            format!("{} Brush:  {}", marker(Field::Board), square_to_char(Some(self.brush))),
+        ];
+        for (i, pieces) in queue.iter().enumerate() {
+            let label = if i == 0 { "Queue:" } else { "" };
+            lines.push(format!("{} {:<8}{}", if i == 0 { marker(Field::Queue) } else { " " }, label, pieces));
+        }
+        lines.extend([
+            format!("{} Hold:   {}", marker(Field::Hold), hold),
+            format!("{} Goal:   {}", marker(Field::Goal), self.goal.label()),
+            String::new(),
+            "Arrows   Move, goal".to_string(),
+            "IJLOSTZG Brush, piece".to_string(),
+            "Space    Paint".to_string(),
+            "Del      Erase".to_string(),
+            "Tab      Next field".to_string(),
+            "f        Garbage row".to_string(),
+            "c        Clear board".to_string(),
+            "p        Play".to_string(),
+            "a        AI analysis".to_string(),
+            "w        Save as puzzle".to_string(),
+            "x        Share code".to_string(),
+            "q        Back".to_string(),
+        ]);
+        lines
+    }
+}
+
+/// Lets the player edit positions, starting from `position`, and play them, have the AI play
+/// them, save them as puzzles or share them until they go back to the menu.
+pub fn play(theme: &Theme, config: &Config, records: &mut Records) -> io::Result<()> {
+    let layout = Layout::single();
+    let ruleset = config.game_ruleset();
+    let mut editor = Editor::new(config.position.clone().unwrap_or_else(Position::new));
+    render::clear_screen()?;
+    loop {
+        render(theme, &layout, &editor, ruleset.rotation_system())?;
+        match receive_editor_input()? {
+            EditorAction::Quit => break,
+            EditorAction::Play => {
+                play_from(theme, config, records, &editor.position)?;
+                render::clear_screen()?;
+            },
+            EditorAction::Analyse if editor.position.queue.is_empty() => {
+                show_message(theme, &layout, vec!["NO PIECES".to_string(), String::new(), "Add pieces to the queue".to_string()])?;
+            },
+            EditorAction::Analyse => {
+                let (shapes, game) = editor.position.analyse(ruleset.clone(), &config.ai_weights);
+                render::render_borders(theme, &layout)?;
+                render::render_locked_squares(theme, &layout, &editor.position.board)?;
+                render::render_locks(theme, &layout, &editor.position.board, &shapes, Duration::from_millis(400))?;
+                let title = if game.status == GameStatus::ToppedOut { "TOPPED OUT" } else { "AI" };
+                show_message(theme, &layout, vec![title.to_string(), String::new(),
+                    format!("Pieces: {}", shapes.len()), format!("Lines:  {}", game.stats.lines),
+                    format!("Score:  {}", game.score)])?;
+            },
+            EditorAction::Save => {
+                let lines = match editor.position.save(editor.goal) {
+                    Ok(name) => vec!["SAVED".to_string(), String::new(), format!("{} of the Edited pack", name)],
+                    Err(e) => vec!["NOT SAVED".to_string(), String::new(), e],
+                };
+                show_message(theme, &layout, lines)?;
+            },
+            EditorAction::Share => {
+                let mut lines = vec!["CODE".to_string(), String::new()];
+                lines.extend(editor.position.code().split_inclusive('/').map(str::to_string));
+                lines.extend([String::new(), "Open it with --position".to_string()]);
+                show_message(theme, &layout, lines)?;
+            },
+            action => editor.apply(action),
+        }
+    }
+    render::clear_screen()
+}
+
+fn render(theme: &Theme, layout: &Layout, editor: &Editor, rotation_system: &dyn RotationSystem) -> io::Result<()> {
+    let position = &editor.position;
+    render::render_borders(theme, layout)?;
+    render::render_next_shapes_borders(theme, layout)?;
+    render::render_hold_borders(theme, layout)?;
+    render::render_stats_borders(theme, layout)?;
+    render::render_locked_squares(theme, layout, &position.board)?;
+    // The preview only has room for the first pieces of the queue.
+    let mut next_shapes: NextShapes = [0, 1, 2].map(|i| {
+        Shape::new(rotation_system, position.queue.get(i).copied().unwrap_or(ShapeType::I), 0, 0)
+    });
+    let preview_count = position.queue.len().min(next_shapes.len());
+    render::render_next_shapes(theme, layout, &mut next_shapes, preview_count)?;
+    if let Some(shape_type) = position.hold {
+        render::render_held_shape(theme, layout, Shape::new(rotation_system, shape_type, 0, 0))?;
+    }
+    let (x, y) = editor.cursor;
+    render::render_cursor(theme, layout, editor.brush, x as u16, y as u16)?;
+    render::render_panel(theme, layout, &editor.lines())
+}
+
+/// Shows the lines over the board until a key is pressed.
+fn show_message(theme: &Theme, layout: &Layout, mut lines: Vec<String>) -> io::Result<()> {
+    lines.extend([String::new(), "Press any key".to_string()]);
+    render::render_popup(theme, layout, &lines)?;
+    wait_for_key()?;
+    render::clear_screen()
+}
+
+/// Plays on from the position until the player goes back to the editor.
+fn play_from(theme: &Theme, config: &Config, records: &mut Records, position: &Position) -> io::Result<()> {
+    play_until_back(theme, records, "Position", false, || position.start(config.game_ruleset()), |_, _| Ok("GAME OVER"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ruleset::RulesetName;
+
+    fn position() -> Position {
+        Position::parse("TTT......./GGGG..GGGG/GGGG..GGGG:OIT:L").unwrap()
+    }
+
+    #[test]
+    fn codes_survive_a_round_trip() {
+        let position = position();
+        assert_eq!(position.board.get(1, BOARD_HEIGHT_IN_TILES - 3), Some(Square::Shape(ShapeType::T)));
+        assert_eq!(position.queue, vec![ShapeType::O, ShapeType::I, ShapeType::T]);
+        assert_eq!(position.hold, Some(ShapeType::L));
+        assert_eq!(Position::parse(&position.code()), Ok(position.clone()));
+        assert_eq!(Position::parse(&position.code().replace('/', "/\n")), Ok(position));
+        assert_eq!(Position::parse("::"), Ok(Position::new()));
+        assert_eq!(Position::new().code(), "::");
+    }
+
+    #[test]
+    fn rejects_broken_codes() {
+        assert_eq!(Position::parse("GGGG:O"), Err("expected rows of 10 squares, got `GGGG`".to_string()));
+        assert_eq!(Position::parse(":O:IT"), Err("expected a single held piece, got `IT`".to_string()));
+        assert_eq!(Position::parse(":o"), Err("unknown piece `o`, expected one of IJLOSTZ".to_string()));
+        assert_eq!(Position::parse("GGGG1GGGG.:I"), Err("unknown piece `1`, expected one of IJLOSTZ".to_string()));
+        assert_eq!(Position::parse(":O:I:T"),
+            Err("expected rows, pieces and a held piece separated by `:`, got `:O:I:T`".to_string()));
+    }
+
+    #[test]
+    fn games_start_from_the_position() {
+        let game = position().start(Ruleset::preset(RulesetName::Guideline));
+        assert_eq!(game.locked_squares, position().board);
+        assert_eq!(game.falling_shape.shape_type, ShapeType::O);
+        assert_eq!(game.next_shapes[0].shape_type, ShapeType::I);
+        assert_eq!(game.held_shape, Some(ShapeType::L));
+        let game = position().start(Ruleset::preset(RulesetName::Nes));
+        assert_eq!((game.falling_shape.shape_type, game.held_shape), (ShapeType::O, None));
+    }
+
+    #[test]
+    fn positions_become_puzzles() {
+        let puzzle = position().puzzle("Position 1", PuzzleGoal::TSpinDouble);
+        let pack = PuzzlePack::parse(SAVED_PACK, &format!("name = Edited\n{}", puzzle.to_text())).unwrap();
+        assert_eq!(pack.puzzles, vec![puzzle.clone()]);
+        let game = puzzle.start(Ruleset::preset(RulesetName::Guideline));
+        assert_eq!((game.held_shape, game.challenge.unwrap().queued), (Some(ShapeType::L), 2));
+    }
+
+    #[test]
+    fn the_ai_plays_the_queue() {
+        let position = Position::parse("GGGGGGGGG./GGGGGGGGG./GGGGGGGGG./GGGGGGGGG.:IO").unwrap();
+        let (shapes, game) = position.analyse(Ruleset::preset(RulesetName::Standard), &Weights::default());
+        assert_eq!(shapes.len(), 2);
+        assert_eq!(game.stats.lines, 4);
+    }
+
+    #[test]
+    fn edits_the_board_the_queue_and_the_hold() {
+        let mut editor = Editor::new(Position::new());
+        editor.apply(EditorAction::Right);
+        editor.apply(EditorAction::Piece(Square::Shape(ShapeType::S)));
+        editor.apply(EditorAction::Up);
+        editor.apply(EditorAction::Paint);
+        editor.apply(EditorAction::FillRow);
+        assert_eq!(editor.position.code(), "G.GGGGGGGG/.S........::");
+        editor.apply(EditorAction::NextField);
+        for shape_type in [ShapeType::T, ShapeType::Z] {
+            editor.apply(EditorAction::Piece(Square::Shape(shape_type)));
+        }
+        editor.apply(EditorAction::Erase);
+        editor.apply(EditorAction::NextField);
+        editor.apply(EditorAction::Piece(Square::Garbage));
+        editor.apply(EditorAction::Piece(Square::Shape(ShapeType::J)));
+        assert_eq!(editor.position.code(), "G.GGGGGGGG/.S........:T:J");
+        editor.apply(EditorAction::NextField);
+        editor.apply(EditorAction::Left);
+        assert_eq!(editor.goal, PuzzleGoal::PerfectClear);
+    }
+}
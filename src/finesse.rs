@@ -0,0 +1,260 @@
+use crate::board::Board;
+use crate::input::Action;
+use crate::placement::{apply_move, find_press_path};
+use crate::rotation::RotationSystem;
+use crate::shape::Shape;
+
+/// An input and, for sideways moves, whether the shape ended up against the wall or the stack.
+pub type Input = (Action, bool);
+
+/// How the inputs spent on one piece compare with the fewest that would have placed it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PieceFinesse {
+    pub inputs: u32,
+    pub optimal: u32,
+    /// The shape where it appeared and the path with the fewest presses from there to where it
+    /// was locked.
+    pub start: Shape,
+    pub path: Vec<Action>,
+}
+
+impl PieceFinesse {
+    pub fn is_fault(&self) -> bool {
+        self.inputs > self.optimal
+    }
+}
+
+/// Counts the inputs spent on every piece and judges them once the piece is locked.
+#[derive(Clone, Debug)]
+pub struct Finesse {
+    spawned: Shape,
+    inputs: Vec<Input>,
+    pub pieces: u32,
+    pub faults: u32,
+    pub last: Option<PieceFinesse>,
+}
+
+impl Finesse {
+    pub fn new(spawned: Shape) -> Finesse {
+        Finesse { spawned, inputs: Vec::new(), pieces: 0, faults: 0, last: None }
+    }
+
+    /// Records an input that is about to be applied to `shape`.
+    pub fn record_input(&mut self, action: Action, shape: &Shape, board: &Board, rotation_system: &dyn RotationSystem) {
+        self.inputs.push((action, ends_blocked(shape, action, board, rotation_system)));
+    }
+
+    /// Starts counting for a shape that just appeared, after a lock or a hold.
+    pub fn start_shape(&mut self, shape: Shape) {
+        self.spawned = shape;
+        self.inputs.clear();
+    }
+
+    /// Judges the inputs spent on the shape, which is about to be locked on the board.
+    pub fn judge(&mut self, locked: &Shape, board: &Board, rotation_system: &dyn RotationSystem, hard_drop: bool) {
+        // The locked shape was reached, so a path always exists, the fallback is only a precaution.
+        let path = find_press_path(&self.spawned, locked, board, rotation_system, hard_drop).unwrap_or_default();
+        let mut shape = self.spawned;
+        let path_inputs: Vec<Input> = path.iter()
+            .map(|&action| {
+                let input = (action, ends_blocked(&shape, action, board, rotation_system));
+                apply_move(&mut shape, action, board, rotation_system);
+                input
+            })
+            .collect();
+        let piece = PieceFinesse {
+            inputs: count_inputs(&self.inputs, hard_drop),
+            optimal: count_inputs(&path_inputs, hard_drop),
+            start: self.spawned,
+            path,
+        };
+        self.pieces += 1;
+        if piece.is_fault() {
+            self.faults += 1;
+        }
+        self.last = Some(piece);
+    }
+
+    /// Share of the pieces placed without a fault, 1 before the first piece.
+    pub fn accuracy(&self) -> f64 {
+        if self.pieces == 0 {
+            return 1.0;
+        }
+        (self.pieces - self.faults) as f64 / self.pieces as f64
+    }
+}
+
+/// Key presses needed for the inputs. Holding a key repeats it, so a run of soft drops counts
+/// once and so does a run of moves that ends against the wall or the stack. Without hard drop a
+/// final run of soft drops is not counted at all since gravity does the same.
+pub fn count_inputs(inputs: &[Input], hard_drop: bool) -> u32 {
+    let mut inputs = inputs;
+    if !hard_drop {
+        while let [rest @ .., (Action::SoftDrop, _)] = inputs {
+            inputs = rest;
+        }
+    }
+    let mut count = 0;
+    let mut run: Vec<Input> = Vec::new();
+    for &input in inputs {
+        if run.first().is_some_and(|&(action, _)| action != input.0) {
+            count += count_run(&run);
+            run.clear();
+        }
+        run.push(input);
+    }
+    count + count_run(&run)
+}
+
+/// Key presses for a run of the same action.
+fn count_run(run: &[Input]) -> u32 {
+    match run.last() {
+        None => 0,
+        Some(&(Action::SoftDrop, _)) | Some(&(Action::MoveLeft | Action::MoveRight, true)) => 1,
+        Some(_) => run.len() as u32,
+    }
+}
+
+/// Whether a sideways move applied to `shape` leaves it unable to move further that way.
+fn ends_blocked(shape: &Shape, action: Action, board: &Board, rotation_system: &dyn RotationSystem) -> bool {
+    if !matches!(action, Action::MoveLeft | Action::MoveRight) {
+        return false;
+    }
+    let mut shape = *shape;
+    apply_move(&mut shape, action, board, rotation_system);
+    !apply_move(&mut shape, action, board, rotation_system)
+}
+
+/// Every position the shape goes through when the path is played, for showing it move.
+pub fn replay_shapes(finesse: &PieceFinesse, board: &Board, rotation_system: &dyn RotationSystem) -> Vec<Shape> {
+    let mut shape = finesse.start;
+    let mut shapes = vec![shape];
+    for &action in &finesse.path {
+        if action == Action::HardDrop {
+            while apply_move(&mut shape, Action::SoftDrop, board, rotation_system) {}
+        }
+        else {
+            apply_move(&mut shape, action, board, rotation_system);
+        }
+        shapes.push(shape);
+    }
+    shapes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rotation::RotationSystemName;
+    use crate::shape::ShapeType;
+
+    fn spawn(shape_type: ShapeType) -> Shape {
+        let srs = RotationSystemName::Srs.system();
+        let (x, y) = srs.spawn_position(shape_type);
+        Shape::new(srs, shape_type, x, y)
+    }
+
+    fn inputs(actions: &[Action]) -> Vec<Input> {
+        actions.iter().map(|&action| (action, false)).collect()
+    }
+
+    #[test]
+    fn soft_drop_runs_count_once() {
+        let actions = inputs(&[Action::MoveLeft, Action::SoftDrop, Action::SoftDrop, Action::MoveLeft, Action::SoftDrop]);
+        assert_eq!(count_inputs(&actions, true), 4);
+        assert_eq!(count_inputs(&actions, false), 3);
+        assert_eq!(count_inputs(&inputs(&[Action::SoftDrop, Action::SoftDrop]), false), 0);
+    }
+
+    #[test]
+    fn holding_a_move_to_the_wall_counts_once() {
+        let srs = RotationSystemName::Srs.system();
+        let board = Board::new();
+        let mut finesse = Finesse::new(spawn(ShapeType::T));
+        let mut target = spawn(ShapeType::T);
+        target.x = 0;
+        target.y = 18;
+
+        // Key repeat keeps sending moves after the T reached the wall.
+        let mut shape = spawn(ShapeType::T);
+        for action in [Action::MoveLeft; 5].into_iter().chain([Action::HardDrop]) {
+            finesse.record_input(action, &shape, &board, srs);
+            apply_move(&mut shape, action, &board, srs);
+        }
+        finesse.judge(&target, &board, srs, true);
+        let last = finesse.last.as_ref().unwrap();
+        assert_eq!((last.inputs, last.optimal), (2, 2));
+        assert!(!last.is_fault());
+
+        // Tapping to the wall takes a press per column.
+        finesse.start_shape(spawn(ShapeType::T));
+        let mut shape = spawn(ShapeType::T);
+        for action in [Action::MoveLeft, Action::MoveLeft, Action::MoveRight, Action::MoveLeft, Action::MoveLeft, Action::HardDrop] {
+            finesse.record_input(action, &shape, &board, srs);
+            apply_move(&mut shape, action, &board, srs);
+        }
+        finesse.judge(&target, &board, srs, true);
+        assert!(finesse.last.as_ref().unwrap().is_fault());
+    }
+
+    #[test]
+    fn holding_to_the_wall_and_tapping_back_beats_tapping_across() {
+        let srs = RotationSystemName::Srs.system();
+        let board = Board::new();
+        let mut finesse = Finesse::new(spawn(ShapeType::T));
+        let mut target = spawn(ShapeType::T);
+        target.x = 6;
+        target.y = 18;
+
+        let mut shape = spawn(ShapeType::T);
+        for action in [Action::MoveRight, Action::MoveRight, Action::MoveRight, Action::HardDrop] {
+            finesse.record_input(action, &shape, &board, srs);
+            apply_move(&mut shape, action, &board, srs);
+        }
+        finesse.judge(&target, &board, srs, true);
+        let last = finesse.last.as_ref().unwrap();
+        assert_eq!((last.inputs, last.optimal), (4, 3));
+        assert!(last.is_fault());
+        assert_eq!(replay_shapes(last, &board, srs).last(), Some(&target));
+
+        finesse.start_shape(spawn(ShapeType::T));
+        let mut shape = spawn(ShapeType::T);
+        for action in [Action::MoveRight; 6].into_iter().chain([Action::MoveLeft, Action::HardDrop]) {
+            finesse.record_input(action, &shape, &board, srs);
+            apply_move(&mut shape, action, &board, srs);
+        }
+        finesse.judge(&target, &board, srs, true);
+        assert!(!finesse.last.as_ref().unwrap().is_fault());
+    }
+
+    #[test]
+    fn flags_extra_inputs_as_faults() {
+        let srs = RotationSystemName::Srs.system();
+        let board = Board::new();
+        let mut finesse = Finesse::new(spawn(ShapeType::T));
+        let mut target = spawn(ShapeType::T);
+        target.x -= 1;
+        target.y = 18;
+
+        let mut shape = spawn(ShapeType::T);
+        for action in [Action::MoveLeft, Action::HardDrop] {
+            finesse.record_input(action, &shape, &board, srs);
+            apply_move(&mut shape, action, &board, srs);
+        }
+        finesse.judge(&target, &board, srs, true);
+        assert!(!finesse.last.as_ref().unwrap().is_fault());
+
+        finesse.start_shape(spawn(ShapeType::T));
+        let mut shape = spawn(ShapeType::T);
+        for action in [Action::MoveLeft, Action::MoveLeft, Action::MoveRight, Action::HardDrop] {
+            finesse.record_input(action, &shape, &board, srs);
+            apply_move(&mut shape, action, &board, srs);
+        }
+        finesse.judge(&target, &board, srs, true);
+        let last = finesse.last.as_ref().unwrap();
+        assert!(last.is_fault());
+        assert_eq!((last.inputs, last.optimal), (4, 2));
+        assert_eq!((finesse.pieces, finesse.faults), (2, 1));
+        assert_eq!(finesse.accuracy(), 0.5);
+        assert_eq!(replay_shapes(last, &board, srs).last(), Some(&target));
+    }
+}
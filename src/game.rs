@@ -0,0 +1,379 @@
+use std::time::Duration;
+use crate::NextShapes;
+use crate::board::Board;
+use crate::finesse::Finesse;
+use crate::garbage::{self, GarbageGenerator, GarbageQueue};
+use crate::input::Action;
+use crate::mode::GameMode;
+use crate::opener::Template;
+use crate::pc::Round;
+use crate::puzzle::Challenge;
+use crate::randomizer::Randomizer;
+use crate::rotation::RotationSystem;
+use crate::ruleset::Ruleset;
+use crate::shape::{Shape, ShapeType};
+use crate::stats::Stats;
+use crate::update::{self, fall_instantly, put_next_shape_on_board_and_check_collision, put_shape_on_board_and_check_collision,
+    try_fall, try_move_left, try_move_right, try_rotate};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameStatus {
+    Playing,
+    /// The goal of the mode was reached.
+    Finished,
+    /// A new shape could not be placed on the board.
+    ToppedOut,
+}
+
+/// What happened to the falling shape during a single update.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Step {
+    pub locked: bool,
+    pub rows_deleted: u8,
+    /// The shape was a T rotated into a spot with at least three of its corners filled.
+    pub t_spin: bool,
+    /// Clearing locks in a row including this one, 0 if it cleared nothing.
+    pub combo: u32,
+    /// The clear was a tetris or T-spin following another one.
+    pub back_to_back: bool,
+    /// The clear left the board empty.
+    pub perfect_clear: bool,
+}
+
+/// The whole state of a single game, independent of the terminal.
+#[derive(Clone, Debug)]
+pub struct Game {
+    pub mode: GameMode,
+    pub ruleset: Ruleset,
+    pub locked_squares: Board,
+    pub falling_shape: Shape,
+    pub next_shapes: NextShapes,
+    pub held_shape: Option<ShapeType>,
+    /// Whether the hold was already used for the current falling shape.
+    pub hold_used: bool,
+    pub randomizer: Randomizer,
+    pub score: u32,
+    pub stats: Stats,
+    pub status: GameStatus,
+    pub garbage_generator: GarbageGenerator,
+    /// Garbage sent by opponents, coming in when a shape locks without clearing.
+    pub garbage_queue: GarbageQueue,
+    /// Judges the inputs spent on every piece, only in the finesse mode.
+    pub finesse: Option<Finesse>,
+    /// The goal of the puzzle being solved, only in the puzzle mode.
+    pub challenge: Option<Challenge>,
+    /// The perfect clear being worked towards, only in the perfect clear mode.
+    pub round: Option<Round>,
+    /// The stack to build, only in the opener mode.
+    pub template: Option<Template>,
+    /// Clearing locks in a row, 0 after a lock that cleared nothing.
+    pub combo: u32,
+    /// Whether the last clear was a tetris or a T-spin, so that another one is back to back.
+    pub back_to_back: bool,
+    /// Whether the last move of the falling shape was a rotation, for detecting T-spins.
+    last_move_was_rotation: bool,
+}
+
+impl Game {
+    pub fn new(mode: GameMode, ruleset: Ruleset) -> Game {
+        let shape_types = ruleset.shape_types();
+        let shape_names = shape_types.iter().map(|&shape_type| ruleset.shape_name(shape_type)).collect();
+        let mut randomizer = Randomizer::new(ruleset.randomizer, shape_types);
+        let rotation_system = ruleset.rotation_system();
+        let mut next_shapes: NextShapes = [(); 3].map(|_| Shape::new(rotation_system, randomizer.next(), 0, 0));
+        let locked_squares = Board::new();
+        let falling_shape = put_next_shape_on_board_and_check_collision(
+            &mut next_shapes, &mut randomizer, rotation_system, &locked_squares).0;
+        let mut game = Game {
+            mode,
+            ruleset,
+            locked_squares,
+            falling_shape,
+            next_shapes,
+            held_shape: None,
+            hold_used: false,
+            randomizer,
+            score: 0,
+            stats: Stats::new(shape_names),
+            status: GameStatus::Playing,
+            garbage_generator: GarbageGenerator::new(mode.garbage_messiness()),
+            garbage_queue: GarbageQueue::new(Duration::ZERO, 0.0),
+            finesse: mode.judges_finesse().then(|| Finesse::new(falling_shape)),
+            challenge: None,
+            round: None,
+            template: None,
+            combo: 0,
+            back_to_back: false,
+            last_move_was_rotation: false,
+        };
+        mode.prepare(&mut game);
+        game
+    }
+
+    /// Starts over on the given board, with the shapes dealt by the randomizer.
+    pub fn set_up(&mut self, board: Board, randomizer: Randomizer) {
+        self.locked_squares = board;
+        self.randomizer = randomizer;
+        let rotation_system = self.ruleset.rotation_system();
+        self.next_shapes = [(); 3].map(|_| Shape::new(rotation_system, self.randomizer.next(), 0, 0));
+        let (falling_shape, is_colliding) = put_next_shape_on_board_and_check_collision(
+            &mut self.next_shapes, &mut self.randomizer, rotation_system, &self.locked_squares);
+        self.falling_shape = falling_shape;
+        if is_colliding {
+            self.status = GameStatus::ToppedOut;
+            self.stats.stop();
+        }
+        if let Some(finesse) = &mut self.finesse {
+            finesse.start_shape(falling_shape);
+        }
+    }
+
+    pub fn rotation_system(&self) -> &dyn RotationSystem {
+        self.ruleset.rotation_system()
+    }
+
+    pub fn gravity_interval(&self) -> Duration {
+        self.ruleset.gravity_interval(&self.mode, &self.stats)
+    }
+
+    /// Applies an action coming from the player.
+    pub fn apply_action(&mut self, action: Action) -> Step {
+        if self.status != GameStatus::Playing {
+            return Step::default();
+        }
+        if !matches!(action, Action::None | Action::Quit | Action::Undo | Action::Retry | Action::Hint) {
+            self.stats.record_key_press();
+            if let Some(finesse) = &mut self.finesse {
+                finesse.record_input(action, &self.falling_shape, &self.locked_squares, self.ruleset.rotation_system());
+            }
+        }
+        match action {
+            Action::MoveLeft => {
+                if try_move_left(&mut self.falling_shape, &self.locked_squares) {
+                    self.last_move_was_rotation = false;
+                }
+            },
+            Action::MoveRight => {
+                if try_move_right(&mut self.falling_shape, &self.locked_squares) {
+                    self.last_move_was_rotation = false;
+                }
+            },
+            Action::Rotate => {
+                let rotation_system = self.ruleset.rotation_system();
+                if try_rotate(&mut self.falling_shape, &self.locked_squares, rotation_system, 1) {
+                    self.last_move_was_rotation = true;
+                }
+            },
+            Action::RotateCounterClockwise => {
+                let rotation_system = self.ruleset.rotation_system();
+                if try_rotate(&mut self.falling_shape, &self.locked_squares, rotation_system, -1) {
+                    self.last_move_was_rotation = true;
+                }
+            },
+            Action::SoftDrop => {
+                if try_fall(&mut self.falling_shape, &self.locked_squares) {
+                    self.last_move_was_rotation = false;
+                    self.score = update::calculate_drop_score(self.ruleset.scoring, self.score, 1, false);
+                }
+            },
+            Action::HardDrop if self.ruleset.hard_drop => {
+                let rows = fall_instantly(&mut self.falling_shape, &self.locked_squares);
+                if rows > 0 {
+                    self.last_move_was_rotation = false;
+                }
+                self.score = update::calculate_drop_score(self.ruleset.scoring, self.score, rows, true);
+                return self.lock_falling_shape();
+            },
+            Action::Hold if self.ruleset.hold && !self.hold_used => self.hold(),
+            Action::HardDrop | Action::Hold => {},
+            Action::Undo | Action::Retry | Action::Hint | Action::Quit | Action::None => {},
+        }
+        Step::default()
+    }
+
+    /// Moves the falling shape one row down, locking it if it cannot fall any further.
+    pub fn apply_gravity(&mut self) -> Step {
+        if self.status != GameStatus::Playing {
+            return Step::default();
+        }
+        if try_fall(&mut self.falling_shape, &self.locked_squares) {
+            self.last_move_was_rotation = false;
+            return Step::default();
+        }
+        self.lock_falling_shape()
+    }
+
+    /// Ends the game once the time limit of the mode runs out.
+    pub fn update_clock(&mut self) {
+        if self.status == GameStatus::Playing && self.mode.is_complete(self) {
+            self.finish();
+        }
+    }
+
+    /// Swaps the falling shape with the held one, or with the next shape if nothing is held yet.
+    fn hold(&mut self) {
+        let rotation_system = self.ruleset.rotation_system();
+        let (new_falling_shape, is_colliding) = match self.held_shape {
+            Some(held_shape_type) => {
+                put_shape_on_board_and_check_collision(held_shape_type, rotation_system, &self.locked_squares)
+            },
+            // A puzzle whose pieces ran out has nothing left to swap in.
+            None if self.challenge.is_some_and(|challenge| challenge.queued == 0) => return,
+            None => self.take_next_shape(),
+        };
+        self.held_shape = Some(self.falling_shape.shape_type);
+        self.hold_used = true;
+        if is_colliding {
+            self.status = GameStatus::ToppedOut;
+            self.stats.stop();
+        }
+        else {
+            self.falling_shape = new_falling_shape;
+            if let Some(finesse) = &mut self.finesse {
+                finesse.start_shape(new_falling_shape);
+            }
+        }
+    }
+
+    /// Puts the next shape of the queue on the board. A puzzle plays its held piece once the
+    /// pieces in its queue run out.
+    fn take_next_shape(&mut self) -> (Shape, bool) {
+        let rotation_system = self.ruleset.rotation_system();
+        if let Some(challenge) = &mut self.challenge {
+            if challenge.queued == 0 && let Some(held_shape_type) = self.held_shape.take() {
+                return put_shape_on_board_and_check_collision(held_shape_type, rotation_system, &self.locked_squares);
+            }
+            challenge.queued = challenge.queued.saturating_sub(1);
+        }
+        put_next_shape_on_board_and_check_collision(
+            &mut self.next_shapes, &mut self.randomizer, rotation_system, &self.locked_squares)
+    }
+
+    /// Number of next shapes shown, a puzzle only shows the pieces it still gives.
+    pub fn preview_count(&self) -> usize {
+        match self.challenge {
+            Some(challenge) => self.ruleset.preview_count.min(challenge.queued as usize),
+            None => self.ruleset.preview_count,
+        }
+    }
+
+    /// Pushes `rows` garbage rows in from the bottom, topping out if the stack is pushed past the top.
+    pub fn add_garbage(&mut self, rows: usize) {
+        let holes: Vec<usize> = (0..rows).map(|_| self.garbage_generator.next_hole()).collect();
+        if garbage::add_garbage_rows(&mut self.locked_squares, &holes) {
+            self.status = GameStatus::ToppedOut;
+            self.stats.stop();
+        }
+    }
+
+    /// Pushes the queued garbage that has arrived in from the bottom, topping out if the stack is
+    /// pushed past the top.
+    fn receive_garbage(&mut self) {
+        let holes = self.garbage_queue.take_arrived();
+        if !holes.is_empty() && garbage::add_garbage_rows(&mut self.locked_squares, &holes) {
+            self.status = GameStatus::ToppedOut;
+            self.stats.stop();
+        }
+    }
+
+    fn finish(&mut self) {
+        self.status = GameStatus::Finished;
+        self.stats.stop();
+        if let Some(limit) = self.mode.time_limit() {
+            self.stats.stopped_at = self.stats.stopped_at.map(|stopped_at| stopped_at.min(self.stats.started_at + limit));
+        }
+    }
+
+    fn lock_falling_shape(&mut self) -> Step {
+        if let Some(finesse) = &mut self.finesse {
+            finesse.judge(&self.falling_shape, &self.locked_squares, self.ruleset.rotation_system(), self.ruleset.hard_drop);
+        }
+        let t_spin = self.last_move_was_rotation && update::is_t_spin(&self.falling_shape, &self.locked_squares);
+        self.last_move_was_rotation = false;
+        self.locked_squares.lock(&self.falling_shape);
+        self.stats.garbage_cleared += garbage::count_full_garbage_rows(&self.locked_squares);
+        let rows_deleted = self.locked_squares.delete_full_rows();
+        let perfect_clear = rows_deleted > 0 && self.locked_squares.is_empty();
+        let mut back_to_back = false;
+        if rows_deleted > 0 {
+            let difficult = rows_deleted >= 4 || t_spin;
+            back_to_back = difficult && self.back_to_back;
+            self.back_to_back = difficult;
+            self.combo += 1;
+        }
+        else {
+            self.combo = 0;
+        }
+        self.score = update::calculate_score(self.ruleset.scoring, self.score, true, rows_deleted, self.stats.level());
+        if perfect_clear {
+            self.score += update::perfect_clear_bonus(self.ruleset.scoring, rows_deleted, self.stats.level());
+            self.stats.perfect_clears += 1;
+        }
+        self.stats.record_piece(self.falling_shape.shape_type, rows_deleted, t_spin);
+        self.hold_used = false;
+
+        let mode = self.mode;
+        if mode.is_complete(self) {
+            self.finish();
+        }
+        else {
+            mode.after_lock(self);
+        }
+        if self.status == GameStatus::Playing && rows_deleted == 0 {
+            self.receive_garbage();
+        }
+
+        if self.status == GameStatus::Playing {
+            let (new_falling_shape, is_colliding) = self.take_next_shape();
+            if is_colliding {
+                self.status = GameStatus::ToppedOut;
+                self.stats.stop();
+            }
+            else {
+                self.falling_shape = new_falling_shape;
+                if let Some(finesse) = &mut self.finesse {
+                    finesse.start_shape(new_falling_shape);
+                }
+            }
+        }
+        Step { locked: true, rows_deleted, t_spin, combo: self.combo, back_to_back, perfect_clear }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Square, BOARD_HEIGHT_IN_TILES, BOARD_WIDTH_IN_TILES};
+    use crate::randomizer::RandomizerKind;
+    use crate::ruleset::{RulesetName, Scoring};
+
+    /// Hard drops an I into a bottom row that misses the four squares it fills.
+    fn drop_i(scoring: Scoring, extra_square: bool) -> Game {
+        let mut board = Board::new();
+        let bottom = BOARD_HEIGHT_IN_TILES - 1;
+        for x in (0..BOARD_WIDTH_IN_TILES).filter(|x| !(3..7).contains(x)) {
+            board.set(x, bottom, Some(Square::Garbage));
+        }
+        if extra_square {
+            board.set(0, bottom - 1, Some(Square::Garbage));
+        }
+        let ruleset = Ruleset { scoring, ..Ruleset::preset(RulesetName::Guideline) };
+        let mut game = Game::new(GameMode::Endless, ruleset);
+        game.set_up(board, Randomizer::new(RandomizerKind::Bag, vec![ShapeType::I]));
+        game.apply_action(Action::HardDrop);
+        game
+    }
+
+    #[test]
+    fn perfect_clears_score_a_bonus() {
+        // 100 for the single and 2 for each of the 18 rows the I was dropped.
+        let game = drop_i(Scoring::Guideline, true);
+        assert_eq!((game.score, game.stats.perfect_clears), (136, 0));
+        let game = drop_i(Scoring::Guideline, false);
+        assert_eq!((game.score, game.stats.perfect_clears), (136 + 800, 1));
+        let game = drop_i(Scoring::Standard, false);
+        assert_eq!((game.score, game.stats.perfect_clears), (125 + 1000, 1));
+        let game = drop_i(Scoring::Nes, false);
+        assert_eq!((game.score, game.stats.perfect_clears), (40, 1));
+    }
+}
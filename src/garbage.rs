@@ -0,0 +1,170 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use rand::prelude::*;
+use crate::{Square, BOARD_HEIGHT_IN_TILES, BOARD_WIDTH_IN_TILES};
+use crate::board::Board;
+
+/// Chooses where the hole of each new garbage row goes.
+///
+/// `messiness` is the chance that a row's hole is in a different column than the
+/// previous one: `0.0` gives a single clean well, `1.0` moves the hole on every row.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GarbageGenerator {
+    messiness: f64,
+    hole: usize,
+}
+
+impl GarbageGenerator {
+    pub fn new(messiness: f64) -> GarbageGenerator {
+        GarbageGenerator {
+            messiness: messiness.clamp(0.0, 1.0),
+            hole: thread_rng().gen_range(0..BOARD_WIDTH_IN_TILES),
+        }
+    }
+
+    pub fn next_hole(&mut self) -> usize {
+        if thread_rng().gen_bool(self.messiness) {
+            self.move_hole();
+        }
+        self.hole
+    }
+
+    /// Moves the hole to another column, whatever the messiness.
+    pub fn move_hole(&mut self) {
+        let other_column = thread_rng().gen_range(0..BOARD_WIDTH_IN_TILES - 1);
+        self.hole = if other_column >= self.hole { other_column + 1 } else { other_column };
+    }
+}
+
+/// Garbage sent by opponents that has not come in yet.
+///
+/// Every attack waits `delay` before it can come in, and then rises the next time the player
+/// locks a shape without clearing. Its rows share a single hole, in a new column for every attack,
+/// unless `messiness` moves the hole between rows too.
+#[derive(Clone, Debug)]
+pub struct GarbageQueue {
+    delay: Duration,
+    generator: GarbageGenerator,
+    /// Rows of every attack and when they may come in, oldest first.
+    attacks: VecDeque<(u32, Instant)>,
+}
+
+impl GarbageQueue {
+    pub fn new(delay: Duration, messiness: f64) -> GarbageQueue {
+        GarbageQueue { delay, generator: GarbageGenerator::new(messiness), attacks: VecDeque::new() }
+    }
+
+    pub fn push(&mut self, rows: u32) {
+        if rows > 0 {
+            self.attacks.push_back((rows, Instant::now() + self.delay));
+        }
+    }
+
+    /// Rows waiting, including those still delayed.
+    pub fn total(&self) -> u32 {
+        self.attacks.iter().map(|&(rows, _)| rows).sum()
+    }
+
+    /// Rows that would come in if the player locked a shape now.
+    pub fn arrived(&self) -> u32 {
+        let now = Instant::now();
+        self.attacks.iter().filter(|&&(_, arrives_at)| arrives_at <= now).map(|&(rows, _)| rows).sum()
+    }
+
+    /// Cancels waiting rows with `lines` of attack, oldest first, and returns the lines left over.
+    pub fn cancel(&mut self, mut lines: u32) -> u32 {
+        while lines > 0 && let Some((rows, _)) = self.attacks.front_mut() {
+            let cancelled = lines.min(*rows);
+            *rows -= cancelled;
+            lines -= cancelled;
+            if *rows == 0 {
+                self.attacks.pop_front();
+            }
+        }
+        lines
+    }
+
+    /// Removes the attacks that may come in and returns the hole of each of their rows, the oldest
+    /// attack first, as expected by `add_garbage_rows`.
+    pub fn take_arrived(&mut self) -> Vec<usize> {
+        let now = Instant::now();
+        let mut holes = Vec::new();
+        while let Some(&(rows, arrives_at)) = self.attacks.front() && arrives_at <= now {
+            self.attacks.pop_front();
+            self.generator.move_hole();
+            holes.push(self.generator.hole);
+            holes.extend((1..rows).map(|_| self.generator.next_hole()));
+        }
+        holes
+    }
+}
+
+/// Pushes the board up and fills the bottom with one garbage row per entry of `holes`.
+/// Returns `true` if any locked square was pushed out of the top of the board.
+pub fn add_garbage_rows(board: &mut Board, holes: &[usize]) -> bool {
+    let count = holes.len().min(BOARD_HEIGHT_IN_TILES);
+    let overflowed = board.push_up(count);
+    for (i, &hole) in holes[..count].iter().enumerate() {
+        let y = BOARD_HEIGHT_IN_TILES - count + i;
+        for x in (0..BOARD_WIDTH_IN_TILES).filter(|&x| x != hole) {
+            board.set(x, y, Some(Square::Garbage));
+        }
+    }
+    overflowed
+}
+
+/// Number of rows containing at least one garbage square.
+pub fn count_garbage_rows(board: &Board) -> u32 {
+    (0..BOARD_HEIGHT_IN_TILES)
+        .filter(|&y| has_garbage(board, y))
+        .count() as u32
+}
+
+/// Number of full rows containing at least one garbage square, i.e. garbage about to be cleared.
+pub fn count_full_garbage_rows(board: &Board) -> u32 {
+    (0..BOARD_HEIGHT_IN_TILES)
+        .filter(|&y| board.is_row_full(y) && has_garbage(board, y))
+        .count() as u32
+}
+
+fn has_garbage(board: &Board, y: usize) -> bool {
+    (0..BOARD_WIDTH_IN_TILES).any(|x| board.get(x, y) == Some(Square::Garbage))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attacks_cancel_the_oldest_garbage_first() {
+        let mut queue = GarbageQueue::new(Duration::ZERO, 0.0);
+        queue.push(2);
+        queue.push(3);
+        assert_eq!(queue.cancel(3), 0);
+        assert_eq!(queue.total(), 2);
+        assert_eq!(queue.cancel(4), 2);
+        assert_eq!(queue.total(), 0);
+    }
+
+    #[test]
+    fn garbage_waits_for_its_delay() {
+        let mut queue = GarbageQueue::new(Duration::from_secs(60), 0.0);
+        queue.push(2);
+        assert_eq!((queue.total(), queue.arrived()), (2, 0));
+        assert!(queue.take_arrived().is_empty());
+        assert_eq!(queue.total(), 2);
+    }
+
+    #[test]
+    fn clean_garbage_shares_a_hole_per_attack() {
+        let mut queue = GarbageQueue::new(Duration::ZERO, 0.0);
+        queue.push(3);
+        queue.push(2);
+        let holes = queue.take_arrived();
+        assert_eq!(holes.len(), 5);
+        assert!(holes[..3].iter().all(|&hole| hole == holes[0]));
+        assert_eq!(holes[3], holes[4]);
+        assert_ne!(holes[2], holes[3]);
+        assert_eq!(queue.total(), 0);
+    }
+}
@@ -5,15 +5,25 @@ use std::{
     io,
     time::Duration,
 };
+use crate::Square;
+use crate::net::shape_from_char;
 
 /// Represents the different game actions triggered by key presses.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Action {
     MoveLeft,
     MoveRight,
     Rotate,
+    RotateCounterClockwise,
     SoftDrop,
     HardDrop,
+    Hold,
+    /// Takes back the last locked shape, in the puzzle mode.
+    Undo,
+    /// Starts the puzzle, the opener or the position over.
+    Retry,
+    /// Shows how the board can be cleared, in the perfect clear mode.
+    Hint,
     Quit,
     None,
 }
@@ -28,6 +38,11 @@ pub fn receive_input() -> io::Result<Action> {
                     KeyCode::Up | KeyCode::Char('w') => Action::Rotate,
                     KeyCode::Down | KeyCode::Char('s') => Action::SoftDrop,
                     KeyCode::Char(' ') => Action::HardDrop,
+                    KeyCode::Char('z') => Action::RotateCounterClockwise,
+                    KeyCode::Char('c') => Action::Hold,
+                    KeyCode::Char('u') | KeyCode::Backspace => Action::Undo,
+                    KeyCode::Char('r') => Action::Retry,
+                    KeyCode::Char('h') => Action::Hint,
                     KeyCode::Char('q') | KeyCode::Esc => Action::Quit,
                     _ => Action::None,
                 };
@@ -41,3 +56,138 @@ pub fn receive_input() -> io::Result<Action> {
         Ok(Action::None)
     }
 }
+
+/// Reads a key of either player when two share the keyboard, with the index of the player.
+/// The left player moves with WASD, rotates back with Q, holds with E and drops with Space.
+/// The right player moves with the arrow keys, rotates back with `.`, holds with `/` and drops
+/// with Enter. Esc quits.
+pub fn receive_versus_input() -> io::Result<Option<(usize, Action)>> {
+    if !event::poll(Duration::from_millis(50))? {
+        return Ok(None);
+    }
+    match event::read()? {
+        Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
+            let input = match key_event.code {
+                KeyCode::Char('a') => (0, Action::MoveLeft),
+                KeyCode::Char('d') => (0, Action::MoveRight),
+                KeyCode::Char('w') => (0, Action::Rotate),
+                KeyCode::Char('s') => (0, Action::SoftDrop),
+                KeyCode::Char('q') => (0, Action::RotateCounterClockwise),
+                KeyCode::Char('e') => (0, Action::Hold),
+                KeyCode::Char(' ') => (0, Action::HardDrop),
+                KeyCode::Left => (1, Action::MoveLeft),
+                KeyCode::Right => (1, Action::MoveRight),
+                KeyCode::Up => (1, Action::Rotate),
+                KeyCode::Down => (1, Action::SoftDrop),
+                KeyCode::Char('.') => (1, Action::RotateCounterClockwise),
+                KeyCode::Char('/') => (1, Action::Hold),
+                KeyCode::Enter => (1, Action::HardDrop),
+                KeyCode::Esc => (0, Action::Quit),
+                _ => return Ok(None),
+            };
+            Ok(Some(input))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Represents navigation through the start menu.
+#[derive(Debug, Clone, Copy)]
+pub enum MenuAction {
+    Up,
+    Down,
+    Left,
+    Right,
+    Select,
+    Quit,
+    None,
+}
+
+pub fn receive_menu_input() -> io::Result<MenuAction> {
+    match event::read()? {
+        Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
+            let action = match key_event.code {
+                KeyCode::Up | KeyCode::Char('w') => MenuAction::Up,
+                KeyCode::Down | KeyCode::Char('s') => MenuAction::Down,
+                KeyCode::Left | KeyCode::Char('a') => MenuAction::Left,
+                KeyCode::Right | KeyCode::Char('d') => MenuAction::Right,
+                KeyCode::Enter | KeyCode::Char(' ') => MenuAction::Select,
+                KeyCode::Char('q') | KeyCode::Esc => MenuAction::Quit,
+                _ => MenuAction::None,
+            };
+            Ok(action)
+        }
+        _ => Ok(MenuAction::None)
+    }
+}
+
+/// Represents the keys of the board editor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditorAction {
+    Up,
+    Down,
+    Left,
+    Right,
+    /// Paints the square under the cursor with the brush.
+    Paint,
+    /// Empties the square under the cursor, or takes back the last piece of the queue or the hold.
+    Erase,
+    /// The letter of a tetromino or `G` for garbage: picks the brush and paints with it, or adds
+    /// the piece to the queue or the hold.
+    Piece(Square),
+    /// Moves on to editing the next of the board, the queue, the hold and the goal.
+    NextField,
+    /// Fills the row of the cursor with garbage, leaving a hole under the cursor.
+    FillRow,
+    Clear,
+    Play,
+    Analyse,
+    Save,
+    Share,
+    Quit,
+    None,
+}
+
+pub fn receive_editor_input() -> io::Result<EditorAction> {
+    match event::read()? {
+        Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
+            let action = match key_event.code {
+                KeyCode::Up => EditorAction::Up,
+                KeyCode::Down => EditorAction::Down,
+                KeyCode::Left => EditorAction::Left,
+                KeyCode::Right => EditorAction::Right,
+                KeyCode::Char(' ') | KeyCode::Enter => EditorAction::Paint,
+                KeyCode::Backspace | KeyCode::Delete | KeyCode::Char('.') => EditorAction::Erase,
+                KeyCode::Tab => EditorAction::NextField,
+                KeyCode::Char('f') => EditorAction::FillRow,
+                KeyCode::Char('c') => EditorAction::Clear,
+                KeyCode::Char('p') => EditorAction::Play,
+                KeyCode::Char('a') => EditorAction::Analyse,
+                KeyCode::Char('w') => EditorAction::Save,
+                KeyCode::Char('x') => EditorAction::Share,
+                KeyCode::Char('q') | KeyCode::Esc => EditorAction::Quit,
+                KeyCode::Char('g' | 'G') => EditorAction::Piece(Square::Garbage),
+                KeyCode::Char(c) => match shape_from_char(c.to_ascii_uppercase()) {
+                    Ok(shape_type) if c.is_ascii_alphabetic() => EditorAction::Piece(Square::Shape(shape_type)),
+                    _ => EditorAction::None,
+                },
+                _ => EditorAction::None,
+            };
+            Ok(action)
+        }
+        _ => Ok(EditorAction::None)
+    }
+}
+
+/// Blocks until any key is pressed. Keys pressed before the call are ignored.
+pub fn wait_for_key() -> io::Result<()> {
+    while event::poll(Duration::ZERO)? {
+        event::read()?;
+    }
+    loop {
+        if let Event::Key(key_event) = event::read()?
+            && key_event.kind == KeyEventKind::Press {
+            return Ok(());
+        }
+    }
+}
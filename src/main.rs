@@ -1,116 +1,255 @@
+mod ai;
+mod attack;
+mod board;
+mod choice;
+mod config;
+mod editor;
+mod finesse;
+mod game;
+mod garbage;
+mod menu;
+mod mode;
+mod net;
+mod opener;
+mod pc;
+mod piece_set;
+mod placement;
+mod puzzle;
+mod randomizer;
+mod records;
 mod render;
+mod rotation;
+mod royale;
+mod ruleset;
+mod server;
 mod shape;
+mod spectate;
 mod input;
+mod stats;
+mod tbp;
+mod theme;
 mod update;
+mod versus;
 
-use std::io::{Result};
+use std::env;
+use std::io::{Error, ErrorKind, Result};
 use std::time::{Duration, SystemTime};
-use crossterm::style::Color;
-use crate::input::{receive_input, Action};
-use crate::shape::Shape;
-use crate::update::{fall_instantly, put_next_shape_on_board_and_check_collision, try_fall, try_move_left, try_move_right, try_rotate};
+use crate::ai::Bot;
+use crate::board::Board;
+use crate::config::Config;
+use crate::finesse::PieceFinesse;
+use crate::game::{Game, GameStatus, Step};
+use crate::input::{receive_input, wait_for_key, Action};
+use crate::mode::{GameMode, ModeName};
+use crate::net::Connection;
+use crate::pc::Outlook;
+use crate::records::Records;
+use crate::render::Layout;
+use crate::rotation::RotationSystem;
+use crate::shape::{Shape, ShapeType};
+use crate::spectate::Broadcast;
+use crate::tbp::ExternalBot;
+use crate::theme::Theme;
+use crate::versus::Players;
 
 pub const BOARD_WIDTH_IN_TILES: usize = 10;
 pub const BOARD_HEIGHT_IN_TILES: usize = 20;
 
-pub type LockedSquareMatrix = [[Option<Color>; BOARD_HEIGHT_IN_TILES]; BOARD_WIDTH_IN_TILES];
+/// A single occupied square of the board.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Square {
+    Shape(ShapeType),
+    Garbage,
+}
+
 pub type NextShapes = [Shape; 3];
 
 fn main() -> Result<()> {
+    let mut config = Config::load().map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+    let command = env::args().nth(1);
+    // The address may be left out in favour of flags.
+    let address = env::args().nth(2).filter(|arg| !arg.starts_with("--"));
+    match command.as_deref() {
+        Some("bench") => {
+            bench(&config);
+            return Ok(());
+        },
+        Some("serve") => return server::serve(address.as_deref().unwrap_or(net::DEFAULT_ADDRESS)),
+        Some("connect") => {
+            let address = address.ok_or_else(|| Error::new(ErrorKind::InvalidInput, "usage: rust-tetris connect <address>"))?;
+            return play_online(&config, &address);
+        },
+        _ => {},
+    }
+    if let Some(address) = &config.spectate {
+        return spectate(&config, address);
+    }
+    let mut records = Records::load()?;
+    let mut external_bot = match &config.bot {
+        Some(command) => Some(ExternalBot::spawn(command)?),
+        None => None,
+    };
+    let mut broadcast = match &config.broadcast {
+        Some(address) => Some(Broadcast::start(address)?),
+        None => None,
+    };
+
     render::start()?;
-    render::render_borders()?;
-    render::render_next_shapes_borders()?;
-    render::render_score(0)?;
-
-    let mut next_shapes: NextShapes = [Shape::new_random(0, 0); 3];
-    let mut locked_squares: LockedSquareMatrix = [[None; BOARD_HEIGHT_IN_TILES]; BOARD_WIDTH_IN_TILES];
-    let mut falling_shape = 
-        put_next_shape_on_board_and_check_collision(&mut next_shapes, &locked_squares).0;
-    let mut next_fall = SystemTime::now() + Duration::from_millis(1000);
-    let mut score = 0;
-
-    render::render_next_shapes(&mut next_shapes)?;
-
-    loop {
-        let mut prev_next_shapes = next_shapes.clone();
-        let shape_before_action = falling_shape.clone();
-        
-        let finished_falling = if next_fall < SystemTime::now() {
-            next_fall += Duration::from_millis(1000);
-            !try_fall(&mut falling_shape, &locked_squares)
+    while menu::run(&mut config)? {
+        let theme = Theme::new(config.theme, config.color_support).with_piece_set(config.piece_set.clone());
+        let keep_playing = match config.players {
+            Players::Single if config.mode == ModeName::Puzzle => {
+                puzzle::play(&theme, &config, &mut records)?;
+                true
+            },
+            Players::Single if config.mode == ModeName::Opener => {
+                opener::play(&theme, &config, &mut records)?;
+                true
+            },
+            Players::Single if config.mode == ModeName::Editor => {
+                editor::play(&theme, &config, &mut records)?;
+                true
+            },
+            Players::Single => play(&theme, &config, &mut records, external_bot.as_mut(), broadcast.as_mut())?,
+            Players::Versus | Players::Ai => versus::play(&theme, &config)?,
+            Players::Royale => royale::play(&theme, &config)?,
+        };
+        if !keep_playing {
+            break;
+        }
+    }
+    render::stop()?;
+    Ok(())
+}
+
+/// Plays matches against another player through a server until the player quits.
+fn play_online(config: &Config, address: &str) -> Result<()> {
+    let mut connection = Connection::open(address)?;
+    let theme = Theme::new(config.theme, config.color_support).with_piece_set(config.piece_set.clone());
+    render::start()?;
+    let result = versus::play_online(&theme, config, &mut connection);
+    render::stop()?;
+    result
+}
+
+/// Watches the game broadcast on the address until the spectator quits.
+fn spectate(config: &Config, address: &str) -> Result<()> {
+    let mut connection = Connection::watch(address)?;
+    let theme = Theme::new(config.theme, config.color_support).with_piece_set(config.piece_set.clone());
+    render::start()?;
+    let result = spectate::spectate(&theme, config, &mut connection);
+    render::stop()?;
+    result
+}
+
+/// Lets the AI play games without a terminal and prints how it did.
+fn bench(config: &Config) {
+    let result = ai::benchmark(
+        || Game::new(config.game_mode(), config.game_ruleset()),
+        config.ai_weights, config.bench_games, config.bench_pieces);
+    for (i, (pieces, lines, score)) in result.games.iter().enumerate() {
+        println!("game {:>3}: {:>6} pieces {:>6} lines {:>8} points", i + 1, pieces, lines, score);
+    }
+    let pieces: u32 = result.games.iter().map(|&(pieces, _, _)| pieces).sum();
+    let lines: u32 = result.games.iter().map(|&(_, lines, _)| lines).sum();
+    let games = result.games.len().max(1) as f64;
+    println!("average: {:.1} pieces {:.1} lines", pieces as f64 / games, lines as f64 / games);
+    println!("thinking: {:.0} pieces per second", pieces as f64 / result.thinking_time.as_secs_f64().max(f64::EPSILON));
+}
+
+/// Plays a single game until it ends. Returns `false` if the player quit.
+fn play(theme: &Theme, config: &Config, records: &mut Records, mut external_bot: Option<&mut ExternalBot>,
+        mut broadcast: Option<&mut Broadcast>) -> Result<bool> {
+    let layout = Layout::single();
+    let mut game = Game::new(config.game_mode(), config.game_ruleset());
+    let mut next_fall = SystemTime::now() + game.gravity_interval();
+    let mut bot = config.autoplay.then(|| Bot::new(config.ai_weights));
+    if let Some(external_bot) = external_bot.as_mut() {
+        external_bot.new_game();
+    }
+    if let Some(broadcast) = broadcast.as_mut() {
+        broadcast.new_game(&game);
+    }
+    render::render_game(theme, &layout, &mut game)?;
+
+    while game.status == GameStatus::Playing {
+        let mut before = game.clone();
+
+        let step = if next_fall < SystemTime::now() {
+            next_fall += game.gravity_interval();
+            game.apply_gravity()
         }
         else {
-            match receive_input()? {
-                Action::MoveLeft => {
-                    try_move_left(&mut falling_shape, &locked_squares);
-                    false
-                },
-                Action::MoveRight => {
-                    try_move_right(&mut falling_shape, &locked_squares);
-                    false
-                },
-                Action::Rotate => {
-                    try_rotate(&mut falling_shape, &locked_squares);
-                    false
+            match (receive_input()?, &mut external_bot, &mut bot) {
+                (Action::Quit, _, _) => return Ok(false),
+                (Action::Hint, _, _) => {
+                    show_solution(theme, &layout, &game)?;
+                    next_fall = SystemTime::now() + game.gravity_interval();
+                    Step::default()
                 },
-                Action::SoftDrop => {
-                    try_fall(&mut falling_shape, &locked_squares);
-                    false
-                },
-                Action::HardDrop => {
-                    fall_instantly(&mut falling_shape, &locked_squares);
-                    true
-                },
-                Action::Quit => break,
-                Action::None => false
+                (_, Some(external_bot), _) => game.apply_action(external_bot.next_action(&game)),
+                (_, None, Some(bot)) => game.apply_action(bot.next_action(&game)),
+                (action, None, None) => game.apply_action(action),
             }
         };
 
-        let shape_after_action = falling_shape.clone();
-        let mut locked_squares_after_action = locked_squares.clone();
-        
-        let mut rows_deleted = 0;
-
-        if finished_falling {
-            for (x, y) in falling_shape.get_occupied_squares() {
-                locked_squares[x as usize][y as usize] = Some(falling_shape.color);
-            }
-            locked_squares_after_action = locked_squares.clone();
-            let (new_falling_shape, is_colliding) =
-                put_next_shape_on_board_and_check_collision(&mut next_shapes, &locked_squares);
-            if is_colliding {
-                break;
-            }
-            falling_shape = new_falling_shape;
-            rows_deleted = update::delete_full_rows(&mut locked_squares);
-        }
-
-        score = update::calculate_score(score, finished_falling, rows_deleted);
+        game.update_clock();
 
-        if shape_before_action != falling_shape {
-            render::clear_shape(&shape_before_action)?;
+        if step.locked && let GameMode::Finesse { replay: true } = game.mode
+            && let Some(last) = game.finesse.as_ref().and_then(|finesse| finesse.last.as_ref())
+            && last.is_fault() {
+            render::clear_shape(theme, &layout, &before.falling_shape)?;
+            replay_finesse(theme, &layout, last, &before.locked_squares, game.rotation_system())?;
+            next_fall = SystemTime::now() + game.gravity_interval();
         }
 
-        if rows_deleted != 0 {
-            render::clear_locked_squares(&locked_squares_after_action)?;
-            render::render_locked_squares(&locked_squares)?;
-        } 
-        else if finished_falling {
-            render::render_shape(&shape_after_action)?;
+        let mut hud_lines = game.mode.hud_lines(&game, records);
+        if external_bot.as_ref().is_some_and(|external_bot| external_bot.has_failed()) {
+            hud_lines.push("Bot failed".to_string());
         }
-
-        render::render_shape(&falling_shape)?;
-        
-        render::render_score(score)?;
-
-        if finished_falling {
-            render::clear_next_shapes(&mut prev_next_shapes)?;
+        render::render_game_changes(theme, &layout, &mut before, &mut game, step, &hud_lines)?;
+        if let Some(broadcast) = broadcast.as_mut() {
+            broadcast.update(&game, &hud_lines);
         }
-        render::render_next_shapes(&mut next_shapes)?;
 
         std::thread::sleep(Duration::from_millis(50));
     }
 
-    render::stop()?;
+    let title = match game.status {
+        GameStatus::Finished => "FINISHED",
+        _ => "GAME OVER",
+    };
+    if let Some(broadcast) = broadcast {
+        broadcast.game_over(title);
+    }
+    let finish_lines = game.mode.finish(&game, records);
+    records.save()?;
+    render::render_game_over(theme, &layout, title, &finish_lines, &game.stats, game.score)?;
+    wait_for_key()?;
+    Ok(true)
+}
+
+/// Shows the shape moving along the shortest path to where it was locked, on the board from
+/// before the lock.
+fn replay_finesse(theme: &Theme, layout: &Layout, finesse: &PieceFinesse, board: &Board,
+        rotation_system: &dyn RotationSystem) -> Result<()> {
+    for shape in finesse::replay_shapes(finesse, board, rotation_system) {
+        render::render_shape(theme, layout, &shape)?;
+        std::thread::sleep(Duration::from_millis(150));
+        render::clear_shape(theme, layout, &shape)?;
+    }
     Ok(())
 }
+
+/// Locks the shapes of the solution the perfect clear solver found one by one on the board, then
+/// puts the board back as it was.
+fn show_solution(theme: &Theme, layout: &Layout, game: &Game) -> Result<()> {
+    let Some(Outlook::Solution(shapes)) = game.round.as_ref().map(|round| &round.outlook) else {
+        return Ok(());
+    };
+    render::clear_shape(theme, layout, &game.falling_shape)?;
+    let board = render::render_locks(theme, layout, &game.locked_squares, shapes, Duration::from_millis(400))?;
+    std::thread::sleep(Duration::from_millis(400));
+    render::render_locked_squares_changes(theme, layout, &board, &game.locked_squares)?;
+    render::render_shape(theme, layout, &game.falling_shape)
+}
@@ -0,0 +1,88 @@
+use std::io::Result;
+use crate::choice::Choice;
+use crate::config::Config;
+use crate::input::{receive_menu_input, MenuAction};
+use crate::mode::ModeName;
+use crate::render;
+use crate::theme::Theme;
+use crate::versus::Players;
+
+const ITEM_START: usize = 0;
+const ITEM_MODE: usize = 1;
+const ITEM_PLAYERS: usize = 2;
+const ITEM_DIFFICULTY: usize = 3;
+const ITEM_TARGETING: usize = 4;
+const ITEM_RULES: usize = 5;
+const ITEM_ROTATION: usize = 6;
+const ITEM_THEME: usize = 7;
+const ITEM_QUIT: usize = 8;
+const ITEM_COUNT: usize = 9;
+
+/// Shows the start menu and lets the player adjust the config before playing.
+/// Returns `false` if the player chose to quit.
+pub fn run(config: &mut Config) -> Result<bool> {
+    let mut selected = ITEM_START;
+    loop {
+        let theme = Theme::new(config.theme, config.color_support);
+        let items = [
+            "Start".to_string(),
+            format!("Mode:  < {} >", config.game_mode().label()),
+            format!("Play:  < {} >", config.players.label()),
+            format!("AI:    < {} >", config.ai_difficulty.label()),
+            format!("Aim:   < {} >", config.royale_targeting.label()),
+            format!("Rules: < {} >", config.ruleset.label()),
+            format!("Spin:  < {} >", config.game_ruleset().rotation.label()),
+            format!("Theme: < {} >", config.theme.label()),
+            "Quit".to_string(),
+        ];
+        render::render_menu(&theme, &items, selected)?;
+
+        match receive_menu_input()? {
+            MenuAction::Up => selected = (selected + ITEM_COUNT - 1) % ITEM_COUNT,
+            MenuAction::Down => selected = (selected + 1) % ITEM_COUNT,
+            MenuAction::Left if selected == ITEM_MODE => config.mode = cycle_mode(config, ModeName::prev),
+            MenuAction::Right if selected == ITEM_MODE => config.mode = cycle_mode(config, ModeName::next),
+            MenuAction::Left if selected == ITEM_PLAYERS => config.players = cycle_players(config, Players::prev),
+            MenuAction::Right if selected == ITEM_PLAYERS => config.players = cycle_players(config, Players::next),
+            MenuAction::Left if selected == ITEM_DIFFICULTY => config.ai_difficulty = config.ai_difficulty.prev(),
+            MenuAction::Right if selected == ITEM_DIFFICULTY => config.ai_difficulty = config.ai_difficulty.next(),
+            MenuAction::Left if selected == ITEM_TARGETING => config.royale_targeting = config.royale_targeting.prev(),
+            MenuAction::Right if selected == ITEM_TARGETING => config.royale_targeting = config.royale_targeting.next(),
+            MenuAction::Left if selected == ITEM_RULES => config.ruleset = config.ruleset.prev(),
+            MenuAction::Right if selected == ITEM_RULES => config.ruleset = config.ruleset.next(),
+            MenuAction::Left if selected == ITEM_ROTATION => {
+                config.rotation = Some(config.game_ruleset().rotation.prev());
+            },
+            MenuAction::Right if selected == ITEM_ROTATION => {
+                config.rotation = Some(config.game_ruleset().rotation.next());
+            },
+            MenuAction::Left if selected == ITEM_THEME => config.theme = config.theme.prev(),
+            MenuAction::Right if selected == ITEM_THEME => config.theme = config.theme.next(),
+            MenuAction::Select if selected == ITEM_START => break,
+            MenuAction::Select if selected == ITEM_QUIT => return Ok(false),
+            MenuAction::Quit => return Ok(false),
+            _ => {}
+        }
+    }
+    render::clear_screen()?;
+    Ok(true)
+}
+
+/// The mode `step` leads to, skipping those that cannot be played with the pieces and players of
+/// the config.
+fn cycle_mode(config: &Config, step: fn(ModeName) -> ModeName) -> ModeName {
+    let mut mode = step(config.mode);
+    while !config.allows_mode(mode) {
+        mode = step(mode);
+    }
+    mode
+}
+
+/// The players `step` leads to, skipping those that cannot play the mode of the config.
+fn cycle_players(config: &Config, step: fn(Players) -> Players) -> Players {
+    let mut players = step(config.players);
+    while !config.allows_players(players) {
+        players = step(players);
+    }
+    players
+}
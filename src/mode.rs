@@ -0,0 +1,418 @@
+use std::time::Duration;
+use crate::choice::Choice;
+use crate::garbage::count_garbage_rows;
+use crate::game::{Game, GameStatus};
+use crate::pc;
+use crate::records::{Records, SprintRecord};
+use crate::stats::{format_time, Stats};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ModeName {
+    Endless,
+    Sprint,
+    Ultra,
+    Dig,
+    Marathon,
+    Finesse,
+    Puzzle,
+    PerfectClear,
+    Opener,
+    Editor,
+}
+
+impl Choice for ModeName {
+    const ALL: &'static [ModeName] = &[ModeName::Endless, ModeName::Marathon, ModeName::Sprint,
+        ModeName::Ultra, ModeName::Dig, ModeName::Finesse, ModeName::Puzzle, ModeName::PerfectClear, ModeName::Opener,
+        ModeName::Editor];
+
+    fn id(self) -> &'static str {
+        match self {
+            ModeName::Endless => "endless",
+            ModeName::Sprint => "sprint",
+            ModeName::Ultra => "ultra",
+            ModeName::Dig => "dig",
+            ModeName::Marathon => "marathon",
+            ModeName::Finesse => "finesse",
+            ModeName::Puzzle => "puzzle",
+            ModeName::PerfectClear => "pc",
+            ModeName::Opener => "opener",
+            ModeName::Editor => "editor",
+        }
+    }
+}
+
+impl ModeName {
+    /// Whether the mode comes with boards or pieces of its own, made for the seven tetrominoes.
+    pub fn needs_tetrominoes(self) -> bool {
+        matches!(self, ModeName::Puzzle | ModeName::PerfectClear | ModeName::Opener | ModeName::Editor)
+    }
+
+    /// Whether the mode is only played alone, its boards have no room for garbage from opponents.
+    pub fn plays_alone(self) -> bool {
+        matches!(self, ModeName::Puzzle | ModeName::PerfectClear | ModeName::Opener | ModeName::Editor)
+    }
+}
+
+/// The rules deciding when a game ends and what is shown next to the board.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameMode {
+    /// Play until topping out.
+    Endless,
+    /// Clear the given number of lines as fast as possible.
+    Sprint { lines: u32 },
+    /// Score as much as possible before the time runs out.
+    Ultra { duration: Duration },
+    /// Dig through the given number of garbage rows as fast as possible.
+    /// `messiness` is the percentage of rows whose hole moves to another column.
+    Dig { lines: u32, messiness: u32 },
+    /// Clear lines while the gravity speeds up every level, until the goal is reached.
+    /// With `endless` the game continues past the goal until topping out.
+    Marathon { goal: MarathonGoal, endless: bool },
+    /// Practice placing every piece with as few inputs as possible, until topping out.
+    /// With `replay` the shortest inputs are shown after every fault.
+    Finesse { replay: bool },
+    /// Reach the goal of a puzzle with the pieces it gives, see `Game::challenge`.
+    Puzzle,
+    /// Clear the board again and again, starting from setups, until a perfect clear can no longer
+    /// be reached, see `Game::round`.
+    PerfectClear,
+    /// Build the stack of an opener with the bag it is made for, see `Game::template`.
+    Opener,
+    /// Play on from a position made in the board editor until topping out.
+    Editor,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MarathonGoal {
+    Lines(u32),
+    /// Complete the given level.
+    Level(u32),
+}
+
+impl MarathonGoal {
+    pub fn is_reached(self, stats: &Stats) -> bool {
+        match self {
+            MarathonGoal::Lines(lines) => stats.lines >= lines,
+            MarathonGoal::Level(level) => stats.level() > level,
+        }
+    }
+
+    /// Identifies the goal in the records file.
+    pub fn id(self) -> String {
+        match self {
+            MarathonGoal::Lines(lines) => format!("lines-{}", lines),
+            MarathonGoal::Level(level) => format!("level-{}", level),
+        }
+    }
+
+    fn progress(self, stats: &Stats) -> String {
+        match self {
+            MarathonGoal::Lines(lines) => format!("Goal:   {}/{}", stats.lines.min(lines), lines),
+            MarathonGoal::Level(level) => format!("Goal:   {}/{}", stats.level().min(level), level),
+        }
+    }
+}
+
+/// Garbage rows kept on the board at once in dig mode.
+const DIG_VISIBLE_ROWS: u32 = 10;
+
+impl GameMode {
+    pub fn label(&self) -> String {
+        match *self {
+            GameMode::Endless => "Endless".to_string(),
+            GameMode::Sprint { lines } => format!("Sprint {}L", lines),
+            GameMode::Ultra { duration } => format!("Ultra {}", format_time(duration).trim_end_matches(".000")),
+            GameMode::Dig { lines, .. } => format!("Dig {}L", lines),
+            GameMode::Marathon { goal: MarathonGoal::Lines(lines), .. } => format!("Marathon {}L", lines),
+            GameMode::Marathon { goal: MarathonGoal::Level(level), .. } => format!("Marathon Lv{}", level),
+            GameMode::Finesse { .. } => "Finesse".to_string(),
+            GameMode::Puzzle => "Puzzle".to_string(),
+            GameMode::PerfectClear => "PC practice".to_string(),
+            GameMode::Opener => "Opener".to_string(),
+            GameMode::Editor => "Editor".to_string(),
+        }
+    }
+
+    /// Time between the falling shape moving one row down.
+    pub fn gravity_interval(&self, stats: &Stats) -> Duration {
+        match *self {
+            GameMode::Marathon { .. } => {
+                // Guideline gravity curve, in seconds per row.
+                let level = stats.level().min(20) as f64 - 1.0;
+                Duration::from_secs_f64((0.8 - level * 0.007).powf(level))
+            },
+            _ => Duration::from_millis(1000),
+        }
+    }
+
+    pub fn garbage_messiness(&self) -> f64 {
+        match *self {
+            GameMode::Dig { messiness, .. } => messiness as f64 / 100.0,
+            _ => 0.0,
+        }
+    }
+
+    pub fn judges_finesse(&self) -> bool {
+        matches!(self, GameMode::Finesse { .. })
+    }
+
+    /// Sets up the board before the first shape falls.
+    pub fn prepare(&self, game: &mut Game) {
+        match self {
+            GameMode::Dig { .. } => self.after_lock(game),
+            GameMode::PerfectClear => pc::start_round(game, true),
+            _ => {},
+        }
+    }
+
+    /// Called after every locked shape of an unfinished game.
+    pub fn after_lock(&self, game: &mut Game) {
+        match *self {
+            GameMode::Dig { lines, .. } => {
+                let remaining = lines.saturating_sub(game.stats.garbage_cleared);
+                let wanted = remaining.min(DIG_VISIBLE_ROWS);
+                let present = count_garbage_rows(&game.locked_squares);
+                if present < wanted {
+                    game.add_garbage((wanted - present) as usize);
+                }
+            },
+            // The puzzle is failed once its pieces run out, the held one included.
+            GameMode::Puzzle if game.challenge.is_some_and(|challenge| challenge.queued == 0 && game.held_shape.is_none()) => {
+                game.status = GameStatus::ToppedOut;
+                game.stats.stop();
+            },
+            GameMode::PerfectClear => pc::after_lock(game),
+            _ => {},
+        }
+    }
+
+    /// Time after which the game ends, if the mode has one.
+    pub fn time_limit(&self) -> Option<Duration> {
+        match *self {
+            GameMode::Ultra { duration } => Some(duration),
+            _ => None,
+        }
+    }
+
+    pub fn is_complete(&self, game: &Game) -> bool {
+        match *self {
+            GameMode::Endless | GameMode::Finesse { .. } | GameMode::PerfectClear | GameMode::Editor => false,
+            GameMode::Puzzle => game.challenge.is_some_and(|challenge| challenge.goal.is_reached(game)),
+            GameMode::Opener => game.template.is_some_and(|template| game.stats.pieces >= template.pieces),
+            GameMode::Sprint { lines } => game.stats.lines >= lines,
+            GameMode::Ultra { duration } => game.stats.elapsed() >= duration,
+            GameMode::Dig { lines, .. } => game.stats.garbage_cleared >= lines,
+            GameMode::Marathon { goal, endless } => !endless && goal.is_reached(&game.stats),
+        }
+    }
+
+    /// Lines shown above the statistics while the game is played.
+    pub fn hud_lines(&self, game: &Game, records: &Records) -> Vec<String> {
+        match *self {
+            GameMode::Endless | GameMode::Editor => Vec::new(),
+            GameMode::Puzzle => match game.challenge {
+                Some(challenge) => vec![
+                    challenge.goal.label(),
+                    format!("Left:   {}", challenge.queued + 1 + game.held_shape.is_some() as u32),
+                    String::new(),
+                ],
+                None => Vec::new(),
+            },
+            GameMode::Opener => match game.template {
+                Some(template) => vec![
+                    format!("Left:   {}", template.pieces.saturating_sub(game.stats.pieces)),
+                    format!("Match:  {}/{}", template.matched(&game.locked_squares), template.size()),
+                    String::new(),
+                ],
+                None => Vec::new(),
+            },
+            GameMode::PerfectClear => {
+                let mut result = vec![self.label(), format!("PCs:    {}", game.stats.perfect_clears)];
+                if let Some(round) = &game.round {
+                    result.push(round.outlook.label());
+                }
+                result.push(String::new());
+                result
+            },
+            GameMode::Sprint { lines } => {
+                let mut result = vec![
+                    self.label(),
+                    format!("Left:   {}", lines.saturating_sub(game.stats.lines)),
+                ];
+                let best = records.sprint.get(&lines);
+                let current = sprint_splits(game, lines);
+                for (i, split) in current.iter().enumerate() {
+                    result.push(split_line(i, lines, *split, best.and_then(|best| best.splits.get(i))));
+                }
+                if let Some(best) = best {
+                    result.push(format!("PB:     {}", format_time(best.time)));
+                }
+                result.push(String::new());
+                result
+            },
+            GameMode::Ultra { duration } => {
+                let remaining = duration.saturating_sub(game.stats.elapsed());
+                let mut result = vec![
+                    self.label(),
+                    format!("Left:   {}", format_time(remaining)),
+                ];
+                if let Some(best) = records.ultra_scores(duration).first() {
+                    result.push(format!("Best:   {}", best));
+                }
+                result.push(String::new());
+                result
+            },
+            GameMode::Dig { lines, .. } => {
+                let mut result = vec![
+                    self.label(),
+                    format!("Left:   {}", lines.saturating_sub(game.stats.garbage_cleared)),
+                ];
+                if let Some(best) = records.dig.get(&lines) {
+                    result.push(format!("PB:     {}", format_time(*best)));
+                }
+                result.push(String::new());
+                result
+            },
+            GameMode::Marathon { goal, .. } => {
+                let mut result = vec![self.label(), goal.progress(&game.stats)];
+                if goal.is_reached(&game.stats) {
+                    result.push("Goal reached!".to_string());
+                }
+                if let Some(best) = records.marathon_scores(goal).first() {
+                    result.push(format!("Best:   {}", best));
+                }
+                result.push(String::new());
+                result
+            },
+            GameMode::Finesse { .. } => {
+                let mut result = vec![self.label()];
+                result.extend(finesse_lines(game));
+                if let Some(last) = game.finesse.as_ref().and_then(|finesse| finesse.last.as_ref()) {
+                    let label = if last.is_fault() { "Fault!" } else { "Last:" };
+                    result.push(format!("{:<8}{}/{} keys", label, last.inputs, last.optimal));
+                }
+                result.push(String::new());
+                result
+            },
+        }
+    }
+
+    /// Stores the result of an ended game and returns the lines of the end screen.
+    pub fn finish(&self, game: &Game, records: &mut Records) -> Vec<String> {
+        match *self {
+            GameMode::Endless | GameMode::Puzzle | GameMode::Editor => Vec::new(),
+            GameMode::Finesse { .. } => finesse_lines(game),
+            GameMode::PerfectClear => vec![format!("PCs:    {}", game.stats.perfect_clears)],
+            GameMode::Opener => match game.template {
+                Some(template) => vec![format!("Match:  {}/{}", template.matched(&game.locked_squares), template.size())],
+                None => Vec::new(),
+            },
+            GameMode::Marathon { goal, .. } => {
+                let rank = records.submit_marathon(goal, game.score);
+                let mut result = vec![goal.progress(&game.stats)];
+                if goal.is_reached(&game.stats) {
+                    result.push("Goal reached!".to_string());
+                }
+                result.push(String::new());
+                result.extend(high_score_lines(records.marathon_scores(goal), rank));
+                result
+            },
+            _ if game.status != GameStatus::Finished => Vec::new(),
+            GameMode::Sprint { lines } => {
+                let splits = sprint_splits(game, lines);
+                let previous_best = records.sprint.get(&lines).cloned();
+                let record = SprintRecord { time: game.stats.elapsed(), splits: splits.clone() };
+                let is_best = records.submit_sprint(lines, record);
+
+                let mut result = vec![format!("Time:   {}", format_time(game.stats.elapsed()))];
+                for (i, split) in splits.iter().enumerate() {
+                    let best_split = previous_best.as_ref().and_then(|best| best.splits.get(i));
+                    result.push(split_line(i, lines, *split, best_split));
+                }
+                result.push(String::new());
+                match previous_best {
+                    _ if is_best => result.push("New personal best!".to_string()),
+                    Some(best) => result.push(format!("PB:     {}", format_time(best.time))),
+                    None => {},
+                }
+                result
+            },
+            GameMode::Ultra { duration } => {
+                let rank = records.submit_ultra(duration, game.score);
+                let mut result = vec![format!("Score:  {}", game.score), String::new()];
+                result.extend(high_score_lines(records.ultra_scores(duration), rank));
+                result
+            },
+            GameMode::Dig { lines, .. } => {
+                let time = game.stats.elapsed();
+                let previous_best = records.dig.get(&lines).copied();
+                let is_best = records.submit_dig(lines, time);
+                let mut result = vec![format!("Time:   {}", format_time(time))];
+                match previous_best {
+                    _ if is_best => result.push("New personal best!".to_string()),
+                    Some(best) => result.push(format!("PB:     {} {}", format_time(best), format_delta(time, best))),
+                    None => {},
+                }
+                result
+            },
+        }
+    }
+}
+
+fn finesse_lines(game: &Game) -> Vec<String> {
+    match &game.finesse {
+        Some(finesse) => vec![
+            format!("Faults: {}", finesse.faults),
+            format!("Acc.:   {:.1}%", finesse.accuracy() * 100.0),
+        ],
+        None => Vec::new(),
+    }
+}
+
+/// The top of a high score table, marking the entry at `rank` if the last game made it in.
+fn high_score_lines(scores: &[u32], rank: Option<usize>) -> Vec<String> {
+    let mut result = Vec::new();
+    if rank == Some(0) {
+        result.push("New high score!".to_string());
+        result.push(String::new());
+    }
+    result.push("High scores".to_string());
+    for (i, score) in scores.iter().take(5).enumerate() {
+        let marker = if rank == Some(i) { "<" } else { "" };
+        result.push(format!("{}. {} {}", i + 1, score, marker));
+    }
+    result
+}
+
+/// Times at every tenth line before the goal, followed by the final time once reached.
+fn sprint_splits(game: &Game, lines: u32) -> Vec<Duration> {
+    let mut splits: Vec<Duration> = game.stats.splits.iter()
+        .take((lines.saturating_sub(1) / 10) as usize)
+        .copied()
+        .collect();
+    if game.stats.lines >= lines {
+        splits.push(game.stats.elapsed());
+    }
+    splits
+}
+
+fn split_line(index: usize, lines: u32, split: Duration, best: Option<&Duration>) -> String {
+    let split_lines = ((index as u32 + 1) * 10).min(lines);
+    let label = format!("{}L:", split_lines);
+    match best {
+        Some(best) => format!("{:<8}{} {}", label, format_time(split), format_delta(split, *best)),
+        None => format!("{:<8}{}", label, format_time(split)),
+    }
+}
+
+/// Formats the difference to a personal best as `+s.mmm` or `-s.mmm`.
+fn format_delta(time: Duration, best: Duration) -> String {
+    if time >= best {
+        let delta = time - best;
+        format!("+{}.{:03}", delta.as_secs(), delta.subsec_millis())
+    }
+    else {
+        let delta = best - time;
+        format!("-{}.{:03}", delta.as_secs(), delta.subsec_millis())
+    }
+}
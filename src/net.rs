@@ -0,0 +1,452 @@
+use std::fmt;
+use std::io::{BufRead, BufReader, Error, ErrorKind, Result, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError};
+use std::thread;
+use std::time::Duration;
+use crate::{Square, BOARD_HEIGHT_IN_TILES, BOARD_WIDTH_IN_TILES};
+use crate::board::Board;
+use crate::choice::Choice;
+use crate::rotation::RotationSystemName;
+use crate::ruleset::RulesetName;
+use crate::shape::ShapeType;
+
+/// Version of the protocol below, clients and servers only play with the same version.
+pub const PROTOCOL_VERSION: u32 = 3;
+/// Address the server listens on when none is given.
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:7878";
+/// Players in a networked versus game.
+pub const PLAYERS: usize = 2;
+/// How long a client waits for the server to answer its `hello`.
+const WELCOME_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The messages of networked versus play, sent as one line of text each.
+///
+/// A client starts with `hello` and is answered with `welcome` or `error`. Players then send `ready`
+/// in the lobby, the server counts down and starts the match, during which the players' boards and
+/// garbage are passed on to the opponent. A player whose game ends says so and the server sends
+/// both players the result, after which they are back in the lobby.
+///
+/// A spectator starts with `watch` instead and is sent the rules of the game, the whole state once
+/// and then only what changed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Message {
+    /// From a client: the protocol version it speaks.
+    Hello(u32),
+    /// From a spectator: the protocol version it speaks.
+    Watch(u32),
+    /// From the server: the protocol version and the player's number, 0 or 1.
+    Welcome(u32, usize),
+    /// From the server: why the client was turned away.
+    Error(String),
+    /// From the server: how many players are connected and how many of them are ready.
+    Lobby { players: usize, ready: usize },
+    /// From a player: ready to start the next match.
+    Ready,
+    /// From the server: seconds left until the match starts.
+    Countdown(u32),
+    Start,
+    /// The board of a player with the falling shape on it.
+    Board(usize, Box<Board>),
+    /// Garbage rows sent to the opponent.
+    Garbage(u32),
+    /// From a player: its game ended by topping out, which loses the match.
+    ToppedOut,
+    /// From a player: its game ended by reaching the goal of the mode, which wins the match.
+    Finished,
+    /// From the server: whether the player won the match.
+    Result(bool),
+    /// To spectators: a new game starts with these rules, and with the named piece set instead of
+    /// the tetrominoes when there is one.
+    Rules(RulesetName, RotationSystemName, Option<String>),
+    /// To spectators: the squares of the board that changed.
+    Squares(Vec<(usize, usize, Option<Square>)>),
+    /// To spectators: the type, rotation state and position of the falling shape, `None` once the
+    /// game is over.
+    Falling(Option<(ShapeType, u8, i16, i16)>),
+    /// To spectators: the held shape and the next shapes.
+    Queue(Option<ShapeType>, Vec<ShapeType>),
+    /// To spectators: the time played and the numbers shown next to the board.
+    Stats(Duration, Box<Scoreboard>),
+    /// To spectators: the game ended with this title.
+    GameOver(String),
+}
+
+/// The numbers shown next to the board of a game.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Scoreboard {
+    pub score: u32,
+    pub lines: u32,
+    pub pieces: u32,
+    pub key_presses: u32,
+    pub clears: [u32; 4],
+    /// Pieces played of every shape type, indexed by `ShapeType::index`.
+    pub pieces_by_type: Vec<u32>,
+    /// Lines of the game mode shown above the statistics.
+    pub hud_lines: Vec<String>,
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Message::Hello(version) => write!(f, "hello {}", version),
+            Message::Welcome(version, player) => write!(f, "welcome {} {}", version, player),
+            Message::Error(reason) => write!(f, "error {}", reason),
+            Message::Lobby { players, ready } => write!(f, "lobby {} {}", players, ready),
+            Message::Ready => write!(f, "ready"),
+            Message::Countdown(seconds) => write!(f, "countdown {}", seconds),
+            Message::Start => write!(f, "start"),
+            Message::Board(player, board) => write!(f, "board {} {}", player, board_to_text(board)),
+            Message::Garbage(rows) => write!(f, "garbage {}", rows),
+            Message::ToppedOut => write!(f, "topped_out"),
+            Message::Finished => write!(f, "finished"),
+            Message::Result(won) => write!(f, "result {}", if *won { "win" } else { "lose" }),
+            Message::Watch(version) => write!(f, "watch {}", version),
+            Message::Rules(ruleset, rotation, None) => write!(f, "rules {} {}", ruleset.id(), rotation.id()),
+            Message::Rules(ruleset, rotation, Some(pieces)) => write!(f, "rules {} {} {}", ruleset.id(), rotation.id(), pieces),
+            Message::Squares(squares) => {
+                write!(f, "squares")?;
+                for &(x, y, square) in squares {
+                    write!(f, " {},{},{}", x, y, square_to_char(square))?;
+                }
+                Ok(())
+            },
+            Message::Falling(None) => write!(f, "falling -"),
+            Message::Falling(Some((shape_type, rotation, x, y))) => {
+                write!(f, "falling {} {} {} {}", shape_to_char(*shape_type), rotation, x, y)
+            },
+            Message::Queue(held, next) => {
+                let next: String = next.iter().map(|&shape_type| shape_to_char(shape_type)).collect();
+                write!(f, "queue {} {}", held.map_or('-', shape_to_char), next)
+            },
+            Message::Stats(elapsed, scoreboard) => {
+                let list = |numbers: &[u32]| match numbers {
+                    [] => "-".to_string(),
+                    _ => numbers.iter().map(|n| n.to_string()).collect::<Vec<_>>().join(","),
+                };
+                write!(f, "stats {} {} {} {} {} {} {}", elapsed.as_millis(), scoreboard.score, scoreboard.lines,
+                    scoreboard.pieces, scoreboard.key_presses, list(&scoreboard.clears), list(&scoreboard.pieces_by_type))?;
+                // The lines of the mode may contain spaces, they come last and are separated by `|`.
+                for line in &scoreboard.hud_lines {
+                    write!(f, " |{}", line)?;
+                }
+                Ok(())
+            },
+            Message::GameOver(title) => write!(f, "game_over {}", title),
+        }
+    }
+}
+
+impl Message {
+    pub fn parse(line: &str) -> std::result::Result<Message, String> {
+        let (name, rest) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+        let mut args = rest.split_whitespace();
+        let mut number = || -> std::result::Result<u32, String> {
+            let arg = args.next().ok_or_else(|| format!("`{}` is missing an argument", name))?;
+            arg.parse().map_err(|_| format!("expected a number, got `{}`", arg))
+        };
+        let message = match name {
+            "hello" => Message::Hello(number()?),
+            "welcome" => Message::Welcome(number()?, number()? as usize),
+            "error" => Message::Error(rest.to_string()),
+            "lobby" => Message::Lobby { players: number()? as usize, ready: number()? as usize },
+            "ready" => Message::Ready,
+            "countdown" => Message::Countdown(number()?),
+            "start" => Message::Start,
+            "board" => {
+                let player = number()? as usize;
+                let text = rest.split_whitespace().nth(1).ok_or("`board` is missing the squares")?;
+                Message::Board(player, Box::new(board_from_text(text)?))
+            },
+            "garbage" => Message::Garbage(number()?),
+            "topped_out" => Message::ToppedOut,
+            "finished" => Message::Finished,
+            "result" => match rest {
+                "win" => Message::Result(true),
+                "lose" => Message::Result(false),
+                _ => return Err(format!("unknown result `{}`", rest)),
+            },
+            "watch" => Message::Watch(number()?),
+            "rules" => {
+                let mut ids = rest.splitn(3, ' ');
+                let ruleset = ids.next().and_then(RulesetName::from_id).ok_or("unknown rule set")?;
+                let rotation = ids.next().and_then(RotationSystemName::from_id).ok_or("unknown rotation system")?;
+                Message::Rules(ruleset, rotation, ids.next().map(str::to_string))
+            },
+            "squares" => {
+                let squares = rest.split_whitespace()
+                    .map(|square| {
+                        let parts: Vec<&str> = square.split(',').collect();
+                        let [x, y, c] = parts[..] else { return Err(format!("expected `x,y,square`, got `{}`", square)) };
+                        let x: usize = x.parse().map_err(|_| format!("expected a column, got `{}`", x))?;
+                        let y: usize = y.parse().map_err(|_| format!("expected a row, got `{}`", y))?;
+                        if x >= BOARD_WIDTH_IN_TILES || y >= BOARD_HEIGHT_IN_TILES {
+                            return Err(format!("square {},{} is off the board", x, y));
+                        }
+                        let mut chars = c.chars();
+                        match (chars.next(), chars.next()) {
+                            (Some(c), None) => Ok((x, y, square_from_char(c)?)),
+                            _ => Err(format!("expected a single square, got `{}`", c)),
+                        }
+                    })
+                    .collect::<std::result::Result<_, String>>()?;
+                Message::Squares(squares)
+            },
+            "falling" if rest == "-" => Message::Falling(None),
+            "falling" => {
+                let parts: Vec<&str> = rest.split_whitespace().collect();
+                let [shape, rotation, x, y] = parts[..] else { return Err("`falling` expects 4 arguments".to_string()) };
+                let shape_type = shape.chars().next().map(shape_from_char).ok_or("`falling` is missing the shape")??;
+                let number = |arg: &str| arg.parse::<i16>().map_err(|_| format!("expected a number, got `{}`", arg));
+                let rotation = u8::try_from(number(rotation)?).map_err(|_| "rotation state out of range")?;
+                let (x, y) = (number(x)?, number(y)?);
+                Message::Falling(Some((shape_type, rotation, x, y)))
+            },
+            "queue" => {
+                let mut parts = rest.split_whitespace();
+                let held = match parts.next() {
+                    Some("-") => None,
+                    Some(held) if held.chars().count() == 1 => Some(shape_from_char(held.chars().next().unwrap())?),
+                    _ => return Err("`queue` is missing the held shape".to_string()),
+                };
+                let next = parts.next().unwrap_or("").chars().map(shape_from_char).collect::<std::result::Result<_, String>>()?;
+                Message::Queue(held, next)
+            },
+            "stats" => {
+                let (numbers, hud) = rest.split_once(" |").unwrap_or((rest, ""));
+                let parts: Vec<&str> = numbers.split_whitespace().collect();
+                let [elapsed, score, lines, pieces, key_presses, clears, pieces_by_type] = parts[..] else {
+                    return Err("`stats` expects 7 numbers".to_string());
+                };
+                let number = |arg: &str| arg.parse::<u32>().map_err(|_| format!("expected a number, got `{}`", arg));
+                let list = |arg: &str| arg.split(',').filter(|&n| n != "-").map(number).collect::<std::result::Result<Vec<u32>, String>>();
+                let elapsed: u64 = elapsed.parse().map_err(|_| format!("expected a number, got `{}`", elapsed))?;
+                let scoreboard = Scoreboard {
+                    score: number(score)?,
+                    lines: number(lines)?,
+                    pieces: number(pieces)?,
+                    key_presses: number(key_presses)?,
+                    clears: list(clears)?.try_into().map_err(|_| "expected 4 clear counts".to_string())?,
+                    pieces_by_type: list(pieces_by_type)?,
+                    hud_lines: if numbers.len() == rest.len() { Vec::new() } else { hud.split(" |").map(str::to_string).collect() },
+                };
+                Message::Stats(Duration::from_millis(elapsed), Box::new(scoreboard))
+            },
+            "game_over" => Message::GameOver(rest.to_string()),
+            _ => return Err(format!("unknown message `{}`", name)),
+        };
+        Ok(message)
+    }
+}
+
+pub fn square_to_char(square: Option<Square>) -> char {
+    match square {
+        None => '.',
+        Some(Square::Garbage) => 'G',
+        Some(Square::Shape(shape_type)) => shape_to_char(shape_type),
+    }
+}
+
+pub fn square_from_char(c: char) -> std::result::Result<Option<Square>, String> {
+    match c {
+        '.' => Ok(None),
+        'G' => Ok(Some(Square::Garbage)),
+        _ => Ok(Some(Square::Shape(shape_from_char(c)?))),
+    }
+}
+
+/// The letter of a standard piece or a digit or lower case letter for the pieces of a piece set.
+fn shape_to_char(shape_type: ShapeType) -> char {
+    match shape_type {
+        ShapeType::Custom(i) => char::from_digit(i as u32, 36).unwrap_or('G'),
+        _ => "IJLOSTZ".as_bytes()[shape_type.index()] as char,
+    }
+}
+
+pub fn shape_from_char(c: char) -> std::result::Result<ShapeType, String> {
+    match "IJLOSTZ".find(c) {
+        Some(index) => Ok(ShapeType::ALL[index]),
+        None if c.is_ascii_digit() || c.is_ascii_lowercase() => Ok(ShapeType::Custom(c.to_digit(36).unwrap() as u8)),
+        None => Err(format!("unknown shape `{}`", c)),
+    }
+}
+
+/// Writes the board row by row from the top, one character per square: `.` for empty, `G` for
+/// garbage and the character of the shape otherwise.
+pub fn board_to_text(board: &Board) -> String {
+    let mut text = String::with_capacity(BOARD_WIDTH_IN_TILES * BOARD_HEIGHT_IN_TILES);
+    for y in 0..BOARD_HEIGHT_IN_TILES {
+        for x in 0..BOARD_WIDTH_IN_TILES {
+            text.push(square_to_char(board.get(x, y)));
+        }
+    }
+    text
+}
+
+pub fn board_from_text(text: &str) -> std::result::Result<Board, String> {
+    let chars: Vec<char> = text.chars().collect();
+    if chars.len() != BOARD_WIDTH_IN_TILES * BOARD_HEIGHT_IN_TILES {
+        return Err(format!("expected {} squares, got {}", BOARD_WIDTH_IN_TILES * BOARD_HEIGHT_IN_TILES, chars.len()));
+    }
+    let mut board = Board::new();
+    for (i, &c) in chars.iter().enumerate() {
+        board.set(i % BOARD_WIDTH_IN_TILES, i / BOARD_WIDTH_IN_TILES, square_from_char(c)?);
+    }
+    Ok(board)
+}
+
+/// Sends a message on its own line.
+pub fn send(stream: &mut TcpStream, message: &Message) -> Result<()> {
+    writeln!(stream, "{}", message)?;
+    stream.flush()
+}
+
+/// Reads the lines of a stream on a thread of their own and passes the messages on. Lines that
+/// are not messages are skipped. The receiver is closed when the stream is.
+pub fn receive_messages(stream: TcpStream) -> Receiver<Message> {
+    let (sender, messages) = mpsc::channel();
+    thread::spawn(move || {
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else { break };
+            if let Ok(message) = Message::parse(&line)
+                && sender.send(message).is_err() {
+                break;
+            }
+        }
+    });
+    messages
+}
+
+/// A client's connection to a server.
+pub struct Connection {
+    stream: TcpStream,
+    messages: Receiver<Message>,
+    /// The player's number on the server, 0 or 1.
+    pub player: usize,
+}
+
+impl Connection {
+    /// Connects to the server as a player and waits to be welcomed.
+    pub fn open(address: &str) -> Result<Connection> {
+        Connection::connect(address, Message::Hello(PROTOCOL_VERSION))
+    }
+
+    /// Connects to a game as a spectator and waits to be welcomed.
+    pub fn watch(address: &str) -> Result<Connection> {
+        Connection::connect(address, Message::Watch(PROTOCOL_VERSION))
+    }
+
+    fn connect(address: &str, hello: Message) -> Result<Connection> {
+        let mut stream = TcpStream::connect(address)?;
+        stream.set_nodelay(true)?;
+        send(&mut stream, &hello)?;
+        let messages = receive_messages(stream.try_clone()?);
+        match messages.recv_timeout(WELCOME_TIMEOUT) {
+            Ok(Message::Welcome(_, player)) if player < PLAYERS => Ok(Connection { stream, messages, player }),
+            Ok(Message::Welcome(_, player)) => Err(Error::new(ErrorKind::InvalidData, format!("welcomed as unknown player {}", player))),
+            Ok(Message::Error(reason)) => Err(Error::new(ErrorKind::ConnectionRefused, reason)),
+            Ok(message) => Err(Error::new(ErrorKind::InvalidData, format!("expected `welcome`, got `{}`", message))),
+            Err(RecvTimeoutError::Timeout) => Err(Error::new(ErrorKind::TimedOut, "no answer to `hello`")),
+            Err(RecvTimeoutError::Disconnected) => Err(closed()),
+        }
+    }
+
+    pub fn send(&mut self, message: &Message) -> Result<()> {
+        send(&mut self.stream, message)
+    }
+
+    /// The next message from the server if one has arrived, without waiting for it.
+    pub fn try_receive(&mut self) -> Result<Option<Message>> {
+        match self.messages.try_recv() {
+            Ok(message) => Ok(Some(message)),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err(closed()),
+        }
+    }
+}
+
+impl Drop for Connection {
+    /// Closes the connection for the thread reading from it as well.
+    fn drop(&mut self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+fn closed() -> Error {
+    Error::new(ErrorKind::ConnectionAborted, "the other side closed the connection")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_survive_a_round_trip() {
+        let mut board = Board::new();
+        board.set(0, 19, Some(Square::Garbage));
+        board.set(3, 18, Some(Square::Shape(ShapeType::T)));
+        board.set(9, 0, Some(Square::Shape(ShapeType::Custom(12))));
+        let messages = [
+            Message::Hello(1),
+            Message::Welcome(1, 1),
+            Message::Error("the game is full".to_string()),
+            Message::Lobby { players: 2, ready: 1 },
+            Message::Ready,
+            Message::Countdown(3),
+            Message::Start,
+            Message::Board(1, Box::new(board)),
+            Message::Garbage(4),
+            Message::ToppedOut,
+            Message::Finished,
+            Message::Result(true),
+            Message::Result(false),
+            Message::Watch(2),
+            Message::Rules(RulesetName::Guideline, RotationSystemName::Srs, None),
+            Message::Rules(RulesetName::Nes, RotationSystemName::Ars, Some("Pentomino mix".to_string())),
+            Message::Squares(vec![(0, 19, Some(Square::Garbage)), (4, 3, None), (9, 0, Some(Square::Shape(ShapeType::S)))]),
+            Message::Squares(Vec::new()),
+            Message::Falling(Some((ShapeType::T, 2, -1, 18))),
+            Message::Falling(None),
+            Message::Queue(None, vec![ShapeType::I, ShapeType::Custom(20)]),
+            Message::Queue(Some(ShapeType::O), Vec::new()),
+            Message::Stats(Duration::from_millis(61_234), Box::new(Scoreboard {
+                score: 1200,
+                lines: 12,
+                pieces: 40,
+                key_presses: 130,
+                clears: [2, 1, 0, 2],
+                pieces_by_type: vec![6, 5, 6, 6, 5, 6, 6],
+                hud_lines: vec!["Lines:  28 left".to_string(), String::new()],
+            })),
+            Message::Stats(Duration::ZERO, Box::default()),
+            Message::GameOver("GAME OVER".to_string()),
+        ];
+        for message in messages {
+            assert_eq!(Message::parse(&message.to_string()), Ok(message));
+        }
+    }
+
+    #[test]
+    fn rejects_broken_messages() {
+        assert!(Message::parse("hello").is_err());
+        assert!(Message::parse("garbage many").is_err());
+        assert!(Message::parse("board 0 ...").is_err());
+        assert!(Message::parse("dance").is_err());
+        assert!(Message::parse("squares 10,0,I").is_err());
+        assert!(Message::parse("falling T 0 0").is_err());
+        assert!(Message::parse("stats 0 0 0 0 0 1,2 7").is_err());
+    }
+
+    #[test]
+    fn unknown_player_numbers_are_refused() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            send(&mut stream, &Message::Welcome(PROTOCOL_VERSION, PLAYERS)).unwrap();
+            thread::sleep(Duration::from_secs(1));
+        });
+        let error = Connection::open(&address).err().expect("player 3 should be refused");
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,287 @@
+use std::fs;
+use std::io::{self, Error, ErrorKind};
+use std::path::Path;
+use crate::{Square, BOARD_WIDTH_IN_TILES};
+use crate::board::Board;
+use crate::config::{data_files, Config};
+use crate::game::Game;
+use crate::input::{receive_menu_input, MenuAction};
+use crate::mode::GameMode;
+use crate::puzzle::{board_from_rows, parse_pieces, parse_row, play_until_back, Row};
+use crate::randomizer::{Randomizer, RandomizerKind};
+use crate::records::Records;
+use crate::render;
+use crate::ruleset::Ruleset;
+use crate::shape::ShapeType;
+use crate::theme::Theme;
+
+/// The openers that come with the game.
+const BUILT_IN_OPENERS: &str = include_str!("../openers/basics");
+
+/// The stack an opener builds, kept by the game while the opener is trained.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Template {
+    /// Every square of the stack, in the colour of the piece that goes there.
+    pub board: Board,
+    /// Pieces it takes to build the stack.
+    pub pieces: u32,
+}
+
+impl Template {
+    pub fn size(&self) -> u32 {
+        self.board.squares().count() as u32
+    }
+
+    /// Squares of the template that hold a square of the same piece on the board.
+    pub fn matched(&self, board: &Board) -> u32 {
+        self.board.squares().filter(|&(x, y, square)| board.get(x, y) == Some(square)).count() as u32
+    }
+
+    pub fn is_matched(&self, board: &Board) -> bool {
+        self.matched(board) == self.size()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Opener {
+    pub name: String,
+    /// The first bags, dealt in this order.
+    pub pieces: Vec<ShapeType>,
+    pub template: Board,
+}
+
+impl Opener {
+    /// The same opener built on the other side of the board, with the mirror image of every piece.
+    pub fn mirrored(&self) -> Opener {
+        let mut template = Board::new();
+        for (x, y, square) in self.template.squares() {
+            let square = match square {
+                Square::Shape(shape_type) => Square::Shape(mirror(shape_type)),
+                Square::Garbage => Square::Garbage,
+            };
+            template.set(BOARD_WIDTH_IN_TILES - 1 - x, y, Some(square));
+        }
+        Opener { name: self.name.clone(), pieces: self.pieces.iter().map(|&shape_type| mirror(shape_type)).collect(), template }
+    }
+
+    /// A game on an empty board that deals the bag of the opener first.
+    pub fn start(&self, mut ruleset: Ruleset) -> Game {
+        // Openers are built with hold, whatever the rule set.
+        ruleset.hold = true;
+        let mut game = Game::new(GameMode::Opener, ruleset);
+        let mut randomizer = Randomizer::new(RandomizerKind::Bag, game.ruleset.shape_types());
+        randomizer.deal_first(&self.pieces);
+        game.set_up(Board::new(), randomizer);
+        let pieces = self.template.squares().count() as u32 / 4;
+        game.template = Some(Template { board: self.template, pieces });
+        game
+    }
+}
+
+/// An opener as read from the file, before it is checked.
+struct OpenerSource {
+    name: String,
+    pieces: Vec<ShapeType>,
+    rows: Vec<Row>,
+}
+
+impl OpenerSource {
+    fn into_opener(self) -> Result<Opener, String> {
+        let error = |message: &str| format!("opener `{}`: {}", self.name, message);
+        if self.pieces.is_empty() {
+            return Err(error("missing `pieces`"));
+        }
+        let template = board_from_rows(&self.rows).map_err(|e| error(&e))?;
+        let size = template.squares().count();
+        if size == 0 || !size.is_multiple_of(4) {
+            return Err(error("the stack is not made of whole pieces"));
+        }
+        Ok(Opener { name: self.name, pieces: self.pieces, template })
+    }
+}
+
+/// The shape type that is the mirror image of the given one.
+fn mirror(shape_type: ShapeType) -> ShapeType {
+    match shape_type {
+        ShapeType::J => ShapeType::L,
+        ShapeType::L => ShapeType::J,
+        ShapeType::S => ShapeType::Z,
+        ShapeType::Z => ShapeType::S,
+        _ => shape_type,
+    }
+}
+
+/// Parses openers from a data file. Every opener has a name, the order of its first bags and the
+/// stack it builds, drawn from the top down like the board of a puzzle with the letter of the
+/// piece that goes on every square. Lines starting with `#` are comments.
+///
+/// ```text
+/// opener Tetris stack
+/// pieces = TILJOSZ
+/// JJJZZ...L.
+/// OOJTZZLLL.
+/// OOTTTIIII.
+/// ```
+pub fn parse_openers(contents: &str) -> Result<Vec<Opener>, String> {
+    let mut sources: Vec<OpenerSource> = Vec::new();
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = |message: String| format!("line {}: {}", number + 1, message);
+
+        if let Some(name) = line.strip_prefix("opener ") {
+            sources.push(OpenerSource { name: name.trim().to_string(), pieces: Vec::new(), rows: Vec::new() });
+        }
+        else if let Some((key, value)) = line.split_once('=') {
+            match (key.trim(), sources.last_mut()) {
+                ("pieces", Some(opener)) => opener.pieces = parse_pieces(value).map_err(error)?,
+                (key, _) => return Err(error(format!("unexpected key `{}`", key))),
+            }
+        }
+        else {
+            let opener = sources.last_mut().ok_or_else(|| error("board outside of an opener".to_string()))?;
+            opener.rows.push(parse_row(line).map_err(error)?);
+        }
+    }
+
+    let openers = sources.into_iter().map(OpenerSource::into_opener).collect::<Result<Vec<_>, _>>()?;
+    if openers.is_empty() {
+        return Err("no openers".to_string());
+    }
+    Ok(openers)
+}
+
+fn load_openers_file(path: &Path) -> Result<Vec<Opener>, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+    parse_openers(&contents).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// The openers that come with the game, followed by those in the `openers` directory of the data
+/// directory and those in `openers`.
+fn load_openers(config: &Config) -> Result<Vec<Opener>, String> {
+    let mut openers = parse_openers(BUILT_IN_OPENERS)?;
+    for path in data_files("openers", config.openers.as_ref()) {
+        openers.extend(load_openers_file(&path)?);
+    }
+    Ok(openers)
+}
+
+/// Lets the player pick openers, on either side of the board, and train them until they go back
+/// to the menu.
+pub fn play(theme: &Theme, config: &Config, records: &mut Records) -> io::Result<()> {
+    let openers = load_openers(config).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    let (mut mirrored, mut selected) = (false, 1);
+    loop {
+        let side = if mirrored { "Mirrored" } else { "Normal" };
+        let mut items = vec![format!("Side:  < {} >", side)];
+        items.extend(openers.iter().map(|opener| opener.name.clone()));
+        items.push("Back".to_string());
+        render::render_menu(theme, &items, selected)?;
+
+        match receive_menu_input()? {
+            MenuAction::Up => selected = (selected + items.len() - 1) % items.len(),
+            MenuAction::Down => selected = (selected + 1) % items.len(),
+            MenuAction::Left | MenuAction::Right if selected == 0 => mirrored = !mirrored,
+            MenuAction::Select if selected == items.len() - 1 => break,
+            MenuAction::Select if selected > 0 => {
+                let opener = &openers[selected - 1];
+                train(theme, config, records, &if mirrored { opener.mirrored() } else { opener.clone() })?;
+            },
+            MenuAction::Quit => break,
+            _ => {},
+        }
+    }
+    render::clear_screen()
+}
+
+/// Plays the opener over and over until the player goes back, grading the stack every time its
+/// pieces are placed.
+fn train(theme: &Theme, config: &Config, records: &mut Records, opener: &Opener) -> io::Result<()> {
+    play_until_back(theme, records, &opener.name, false, || opener.start(config.game_ruleset()), |game, _| {
+        Ok(match game.template {
+            Some(template) if template.is_matched(&game.locked_squares) => "PERFECT",
+            _ => "MISSED",
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::GameStatus;
+    use crate::input::Action;
+    use crate::placement;
+    use crate::ruleset::RulesetName;
+
+    /// Whether the pieces can be placed, holding any of them, so that the stack matches the
+    /// template. Only placements on squares of their own piece in the template are tried.
+    fn can_build(game: &Game) -> bool {
+        let Some(template) = game.template else {
+            return false;
+        };
+        if game.status != GameStatus::Playing {
+            return template.is_matched(&game.locked_squares);
+        }
+        let mut starts = vec![game.clone()];
+        if !game.hold_used {
+            let mut held = game.clone();
+            held.apply_action(Action::Hold);
+            starts.push(held);
+        }
+        starts.iter().any(|start| {
+            placement::reachable_landings(&start.falling_shape, &start.locked_squares, start.rotation_system())
+                .into_iter()
+                .filter(|(landing, _)| landing.get_occupied_squares().iter()
+                    .all(|&(x, y)| template.board.get(x as usize, y as usize) == Some(Square::Shape(landing.shape_type))))
+                .any(|(_, actions)| {
+                    let mut game = start.clone();
+                    for action in actions {
+                        game.apply_action(action);
+                    }
+                    game.apply_action(Action::HardDrop);
+                    can_build(&game)
+                })
+        })
+    }
+
+    #[test]
+    fn every_built_in_opener_can_be_built_on_both_sides() {
+        for opener in parse_openers(BUILT_IN_OPENERS).unwrap() {
+            for opener in [opener.mirrored(), opener] {
+                for name in [RulesetName::Guideline, RulesetName::Standard] {
+                    let game = opener.start(Ruleset::preset(name));
+                    assert!(can_build(&game), "{} cannot be built under {:?} rules", opener.name, name);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn grades_the_stack() {
+        let openers = parse_openers("opener Square\npieces = OI\n..........\nOO........\nOO........\n").unwrap();
+        let mut game = openers[0].start(Ruleset::preset(RulesetName::Guideline));
+        assert_eq!(game.falling_shape.shape_type, ShapeType::O);
+        game.apply_action(Action::HardDrop);
+        assert_eq!(game.status, GameStatus::Finished);
+        let template = game.template.unwrap();
+        assert_eq!((template.matched(&game.locked_squares), template.size()), (0, 4));
+
+        let mut game = openers[0].mirrored().start(Ruleset::preset(RulesetName::Guideline));
+        for _ in 0..5 {
+            game.apply_action(Action::MoveRight);
+        }
+        game.apply_action(Action::HardDrop);
+        assert!(game.template.unwrap().is_matched(&game.locked_squares));
+    }
+
+    #[test]
+    fn reports_broken_openers() {
+        assert_eq!(parse_openers("opener A\nOO........\n"), Err("opener `A`: missing `pieces`".to_string()));
+        assert_eq!(parse_openers("opener A\npieces = O\nO.........\n"),
+            Err("opener `A`: the stack is not made of whole pieces".to_string()));
+        assert_eq!(parse_openers("pieces = O\n"), Err("line 1: unexpected key `pieces`".to_string()));
+    }
+}
@@ -0,0 +1,280 @@
+use std::collections::HashSet;
+use rand::prelude::*;
+use crate::{BOARD_HEIGHT_IN_TILES, BOARD_WIDTH_IN_TILES};
+use crate::board::Board;
+use crate::game::{Game, GameStatus};
+use crate::placement::reachable_landings;
+use crate::puzzle::{board_from_rows, parse_row};
+use crate::rotation::RotationSystem;
+use crate::shape::{Shape, ShapeType};
+use crate::update::put_shape_on_board_and_check_collision;
+
+/// Placements the solver tries before it gives up on an answer.
+const SEARCH_BUDGET: u32 = 2000;
+/// Rows cleared by a round started on an empty board, when no setup suits the pieces.
+const EMPTY_BOARD_HEIGHT: usize = 4;
+
+/// Stacks the perfect clear mode starts rounds from, drawn from the top down like puzzle boards.
+/// Each leaves a number of empty squares in its rows that pieces can fill.
+const SETUPS: [&[&str]; 7] = [
+    &["GGGGGGG...", "GGGGGGG...", "GGGGGGG...", "GGGGGGG..."],
+    &["GGGGGG....", "GGGGGG....", "GGGGGG....", "GGGGGG...."],
+    &["GGGGG.....", "GGGGG.....", "GGGGG.....", "GGGGG....."],
+    &["GGGG......", "GGGG......", "GGGG......", "GGGG......"],
+    &["GGG....GGG", "GGG....GGG", "GGG....GGG", "GGG....GGG"],
+    &["GG......GG", "GG......GG", "GG......GG", "GG......GG"],
+    &["GGGG......", "GGGGG.....", "GGGGG....."],
+];
+
+/// What the solver found out about clearing the board.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outlook {
+    /// The shapes to lock, in order, each resting on the board left by the ones before.
+    Solution(Vec<Shape>),
+    Impossible,
+    /// Not enough pieces are known, or the search took too long.
+    Unknown,
+}
+
+impl Outlook {
+    pub fn label(&self) -> String {
+        match self {
+            Outlook::Solution(shapes) => format!("PC in {}", shapes.len()),
+            Outlook::Impossible => "No PC".to_string(),
+            Outlook::Unknown => "PC?".to_string(),
+        }
+    }
+}
+
+/// A perfect clear being worked towards, kept by the game in the perfect clear mode.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Round {
+    /// Rows the stack may take up, all of which have to be cleared.
+    pub height: usize,
+    /// Lines the game had cleared when the round was last judged.
+    lines: u32,
+    pub outlook: Outlook,
+}
+
+/// Looks for a way to clear the bottom `height` rows of the board, without placing anything above
+/// them, with the pieces of the queue in order. `hold` is the held piece and, with `can_hold`, any
+/// piece can be swapped with it.
+pub fn solve(board: &Board, height: usize, queue: &[ShapeType], hold: Option<ShapeType>, can_hold: bool,
+        rotation_system: &dyn RotationSystem) -> Outlook {
+    let mut solver = Solver { rotation_system, queue, can_hold, failed: HashSet::new(), budget: SEARCH_BUDGET };
+    let mut path = Vec::new();
+    match solver.search(board, height, 0, hold, &mut path) {
+        Some(true) => Outlook::Solution(path),
+        Some(false) => Outlook::Impossible,
+        None => Outlook::Unknown,
+    }
+}
+
+/// The pieces known to come, starting with the falling one when it is still to be placed.
+pub fn known_pieces(game: &Game, with_falling: bool) -> Vec<ShapeType> {
+    let mut pieces = Vec::new();
+    if with_falling {
+        pieces.push(game.falling_shape.shape_type);
+    }
+    pieces.extend(game.next_shapes.iter().map(|shape| shape.shape_type));
+    pieces.extend(game.randomizer.upcoming());
+    pieces
+}
+
+/// Puts one of the setups on the empty board, one the pieces to come can clear if there is such a
+/// setup, and starts a round of the perfect clear mode on it.
+pub fn start_round(game: &mut Game, with_falling: bool) {
+    let pieces = known_pieces(game, with_falling);
+    let mut setups: Vec<&[&str]> = SETUPS.to_vec();
+    setups.shuffle(&mut thread_rng());
+    let chosen = setups.iter()
+        .map(|rows| {
+            let board = setup_board(rows);
+            let outlook = solve(&board, rows.len(), &pieces, game.held_shape, game.ruleset.hold, game.rotation_system());
+            (board, Round { height: rows.len(), lines: game.stats.lines, outlook })
+        })
+        .find(|(_, round)| matches!(round.outlook, Outlook::Solution(_)));
+    let empty = Round { height: EMPTY_BOARD_HEIGHT, lines: game.stats.lines, outlook: Outlook::Unknown };
+    let (board, round) = chosen.unwrap_or((Board::new(), empty));
+    game.locked_squares = board;
+    game.round = Some(round);
+}
+
+/// Judges the board after a lock in the perfect clear mode, ending the game once the round can no
+/// longer be won and starting a new round once it was.
+pub fn after_lock(game: &mut Game) {
+    let Some(round) = &game.round else {
+        return;
+    };
+    if game.locked_squares.is_empty() {
+        start_round(game, false);
+        return;
+    }
+    let height = round.height.saturating_sub((game.stats.lines - round.lines) as usize);
+    let pieces = known_pieces(game, false);
+    let outlook = solve(&game.locked_squares, height, &pieces, game.held_shape, game.ruleset.hold, game.rotation_system());
+    if outlook == Outlook::Impossible {
+        game.status = GameStatus::ToppedOut;
+        game.stats.stop();
+    }
+    game.round = Some(Round { height, lines: game.stats.lines, outlook });
+}
+
+fn setup_board(rows: &[&str]) -> Board {
+    let rows: Vec<_> = rows.iter().map(|row| parse_row(row).expect("Invalid setup")).collect();
+    board_from_rows(&rows).expect("Invalid setup")
+}
+
+struct Solver<'a> {
+    rotation_system: &'a dyn RotationSystem,
+    queue: &'a [ShapeType],
+    can_hold: bool,
+    /// Boards, by their rows, with the next piece and the held one, known not to lead anywhere.
+    failed: HashSet<(Vec<u16>, usize, Option<usize>)>,
+    budget: u32,
+}
+
+impl Solver<'_> {
+    /// Whether the bottom `height` rows can be cleared with the pieces from `next` on, adding the
+    /// placements to `path` if so. `None` if that is not known.
+    fn search(&mut self, board: &Board, height: usize, next: usize, hold: Option<ShapeType>,
+            path: &mut Vec<Shape>) -> Option<bool> {
+        if !path.is_empty() && board.is_empty() {
+            return Some(true);
+        }
+        let top = BOARD_HEIGHT_IN_TILES - height;
+        if height == 0 || (0..top).any(|y| board.row(y) != 0) || !regions_fit(board, top) {
+            return Some(false);
+        }
+        let empty = (top..BOARD_HEIGHT_IN_TILES).map(|y| BOARD_WIDTH_IN_TILES - board.row(y).count_ones() as usize).sum::<usize>();
+        let available = self.queue.len().saturating_sub(next) + hold.is_some() as usize;
+        if empty / 4 > available || self.budget == 0 {
+            return None;
+        }
+        self.budget -= 1;
+        let key = ((top..BOARD_HEIGHT_IN_TILES).map(|y| board.row(y)).collect(), next, hold.map(ShapeType::index));
+        if self.failed.contains(&key) {
+            return Some(false);
+        }
+
+        // The held piece alone cannot be played, it has to be swapped with one from the queue.
+        let &current = self.queue.get(next)?;
+        let mut choices = vec![(current, next + 1, hold)];
+        if self.can_hold {
+            match hold {
+                Some(held) if held != current => choices.push((held, next + 1, Some(current))),
+                None if next + 1 < self.queue.len() => choices.push((self.queue[next + 1], next + 2, Some(current))),
+                _ => {},
+            }
+        }
+        let mut unknown = false;
+        for (shape_type, next, hold) in choices {
+            let (start, is_colliding) = put_shape_on_board_and_check_collision(shape_type, self.rotation_system, board);
+            if is_colliding {
+                continue;
+            }
+            for (landing, _) in reachable_landings(&start, board, self.rotation_system) {
+                if landing.get_occupied_squares().iter().any(|&(_, y)| (y as usize) < top) {
+                    continue;
+                }
+                let mut after = *board;
+                after.lock(&landing);
+                let cleared = after.delete_full_rows() as usize;
+                path.push(landing);
+                match self.search(&after, height - cleared, next, hold, path) {
+                    Some(true) => return Some(true),
+                    Some(false) => {},
+                    None => unknown = true,
+                }
+                path.pop();
+            }
+        }
+        if unknown {
+            return None;
+        }
+        self.failed.insert(key);
+        Some(false)
+    }
+}
+
+/// Whether every area of connected empty squares from row `top` down can take whole pieces. The
+/// mode only runs with the tetrominoes, so those are areas of a multiple of four squares.
+fn regions_fit(board: &Board, top: usize) -> bool {
+    let mut seen = [0u16; BOARD_HEIGHT_IN_TILES];
+    for y in top..BOARD_HEIGHT_IN_TILES {
+        for x in 0..BOARD_WIDTH_IN_TILES {
+            if (board.row(y) | seen[y]) & (1 << x) != 0 {
+                continue;
+            }
+            let mut size = 0;
+            let mut stack = vec![(x, y)];
+            seen[y] |= 1 << x;
+            while let Some((x, y)) = stack.pop() {
+                size += 1;
+                let neighbours = [(x.wrapping_sub(1), y), (x + 1, y), (x, y.wrapping_sub(1)), (x, y + 1)];
+                for (nx, ny) in neighbours {
+                    if nx < BOARD_WIDTH_IN_TILES && ny >= top && ny < BOARD_HEIGHT_IN_TILES
+                        && (board.row(ny) | seen[ny]) & (1 << nx) == 0 {
+                        seen[ny] |= 1 << nx;
+                        stack.push((nx, ny));
+                    }
+                }
+            }
+            if size % 4 != 0 {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rotation::RotationSystemName;
+
+    fn solve_srs(rows: &[&str], queue: &[ShapeType], hold: Option<ShapeType>, can_hold: bool) -> Outlook {
+        solve(&setup_board(rows), rows.len(), queue, hold, can_hold, RotationSystemName::Srs.system())
+    }
+
+    const SQUARE_HOLE: &[&str] = &["GGGGGG....", "GGGGGG...."];
+
+    #[test]
+    fn finds_perfect_clears() {
+        let Outlook::Solution(shapes) = solve_srs(SQUARE_HOLE, &[ShapeType::O, ShapeType::O], None, false) else {
+            panic!("no solution");
+        };
+        let mut board = setup_board(SQUARE_HOLE);
+        for shape in &shapes {
+            assert!(!board.collides(shape));
+            board.lock(shape);
+            board.delete_full_rows();
+        }
+        assert!(board.is_empty());
+    }
+
+    #[test]
+    fn uses_the_held_piece() {
+        let queue = [ShapeType::I, ShapeType::O];
+        assert_eq!(solve_srs(SQUARE_HOLE, &queue, None, false), Outlook::Impossible);
+        assert!(matches!(solve_srs(SQUARE_HOLE, &queue, Some(ShapeType::O), true), Outlook::Solution(_)));
+    }
+
+    #[test]
+    fn needs_enough_known_pieces() {
+        assert_eq!(solve_srs(SQUARE_HOLE, &[ShapeType::O], None, false), Outlook::Unknown);
+        assert_eq!(solve_srs(&["GGGGGGGG..", "GGGGGGG..."], &[ShapeType::O], None, false), Outlook::Impossible);
+    }
+
+    #[test]
+    fn every_setup_can_be_cleared_from_some_bag() {
+        let bag = [ShapeType::T, ShapeType::I, ShapeType::L, ShapeType::J, ShapeType::S, ShapeType::Z, ShapeType::O];
+        let queue: Vec<ShapeType> = bag.iter().cycle().take(16).copied().collect();
+        for rows in SETUPS {
+            let found = (0..bag.len()).any(|start| {
+                matches!(solve_srs(rows, &queue[start..start + 9], None, true), Outlook::Solution(_))
+            });
+            assert!(found, "{:?} cannot be cleared", rows);
+        }
+    }
+}
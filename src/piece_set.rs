@@ -0,0 +1,241 @@
+use std::fs;
+use std::path::Path;
+use crate::rotation::{Kick, RotationSystem};
+use crate::shape::{Cell, ShapeCells, ShapeType, MAX_SHAPE_SIZE};
+
+/// Most pieces a single piece set may define.
+pub const MAX_PIECES: usize = 32;
+
+/// Pieces loaded from a data file, replacing the seven tetrominoes.
+///
+/// The file names the set and then lists its pieces. Every piece has a name, a colour and
+/// either one or four rotation states drawn with `X` for filled and `.` for empty cells,
+/// separated by blank lines. With a single state the others are made by rotating it clockwise.
+/// Lines starting with `#` are comments.
+///
+/// ```text
+/// name = Triomino
+///
+/// piece I3
+/// color = #00ffff
+/// XXX
+///
+/// piece L3
+/// color = #ff8700
+/// X.
+/// XX
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PieceSet {
+    pub name: String,
+    pub pieces: Vec<PieceDefinition>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PieceDefinition {
+    pub name: String,
+    pub color: (u8, u8, u8),
+    pub states: [ShapeCells; 4],
+}
+
+impl PieceSet {
+    pub fn load(path: &Path) -> Result<PieceSet, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+        PieceSet::parse(&contents).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn parse(contents: &str) -> Result<PieceSet, String> {
+        let mut name = None;
+        let mut pieces: Vec<PieceSource> = Vec::new();
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.starts_with('#') {
+                continue;
+            }
+            let error = |message: String| format!("line {}: {}", number + 1, message);
+
+            if let Some(piece_name) = line.strip_prefix("piece ") {
+                pieces.push(PieceSource { name: piece_name.trim().to_string(), color: None, grids: vec![Vec::new()] });
+            }
+            else if let Some((key, value)) = line.split_once('=') {
+                let value = value.trim();
+                match (key.trim(), pieces.last_mut()) {
+                    ("name", None) => name = Some(value.to_string()),
+                    ("color", Some(piece)) => piece.color = Some(parse_color(value).map_err(error)?),
+                    (key, _) => return Err(error(format!("unexpected key `{}`", key))),
+                }
+            }
+            else if line.is_empty() {
+                if let Some(piece) = pieces.last_mut()
+                    && piece.grids.last().is_some_and(|rows| !rows.is_empty()) {
+                    piece.grids.push(Vec::new());
+                }
+            }
+            else if is_grid_row(line) {
+                let piece = pieces.last_mut().ok_or_else(|| error("cells outside of a piece".to_string()))?;
+                piece.grids.last_mut().unwrap().push(line.to_string());
+            }
+            else {
+                return Err(error(format!("could not parse `{}`", line)));
+            }
+        }
+
+        if pieces.is_empty() {
+            return Err("no pieces defined".to_string());
+        }
+        if pieces.len() > MAX_PIECES {
+            return Err(format!("at most {} pieces are supported", MAX_PIECES));
+        }
+        let pieces = pieces.into_iter()
+            .map(|PieceSource { name, color, grids }| {
+                let color = color.ok_or_else(|| format!("piece {} has no color", name))?;
+                let states = parse_states(&grids).map_err(|e| format!("piece {}: {}", name, e))?;
+                Ok(PieceDefinition { name, color, states })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(PieceSet { name: name.unwrap_or_else(|| "Custom".to_string()), pieces })
+    }
+
+    pub fn shape_types(&self) -> Vec<ShapeType> {
+        (0..self.pieces.len()).map(|i| ShapeType::Custom(i as u8)).collect()
+    }
+
+    pub fn piece(&self, shape_type: ShapeType) -> Option<&PieceDefinition> {
+        match shape_type {
+            ShapeType::Custom(i) => self.pieces.get(i as usize),
+            _ => None,
+        }
+    }
+}
+
+/// A piece as written in the file, before its grids are turned into rotation states.
+struct PieceSource {
+    name: String,
+    color: Option<(u8, u8, u8)>,
+    grids: Vec<Vec<String>>,
+}
+
+/// Custom pieces have no kick table of their own, they may only nudge sideways or up.
+const PIECE_SET_KICKS: [Kick; 4] = [(0, 0), (1, 0), (-1, 0), (0, -1)];
+
+impl RotationSystem for PieceSet {
+    fn states(&self, shape_type: ShapeType) -> [ShapeCells; 4] {
+        self.piece(shape_type).expect("shape type is not part of the piece set").states
+    }
+
+    fn kicks(&self, _shape_type: ShapeType, _from: u8, _to: u8) -> Vec<Kick> {
+        PIECE_SET_KICKS.to_vec()
+    }
+}
+
+fn is_grid_row(line: &str) -> bool {
+    !line.is_empty() && line.chars().all(|c| c == 'X' || c == '.')
+}
+
+/// Turns one or four grids of rows into the matrices of the four rotation states.
+fn parse_states(grids: &[Vec<String>]) -> Result<[ShapeCells; 4], String> {
+    let grids: Vec<&Vec<String>> = grids.iter().filter(|rows| !rows.is_empty()).collect();
+    let mut states = grids.iter().map(|rows| parse_grid(rows)).collect::<Result<Vec<_>, String>>()?;
+    match states.len() {
+        1 => {
+            let size = grids[0].len().max(grids[0].iter().map(|row| row.len()).max().unwrap_or(0));
+            for i in 1..4 {
+                let rotated: Vec<Cell> = states[i - 1].iter().map(|&(x, y)| (size as i8 - 1 - y, x)).collect();
+                states.push(rotated);
+            }
+        },
+        4 => {},
+        n => return Err(format!("expected 1 or 4 rotation states, found {}", n)),
+    }
+    Ok([0, 1, 2, 3].map(|i| ShapeCells::new(&states[i])))
+}
+
+fn parse_grid(rows: &[String]) -> Result<Vec<Cell>, String> {
+    if rows.len() > MAX_SHAPE_SIZE || rows.iter().any(|row| row.len() > MAX_SHAPE_SIZE) {
+        return Err(format!("pieces may be at most {0}x{0} cells", MAX_SHAPE_SIZE));
+    }
+    let cells: Vec<Cell> = rows.iter().enumerate()
+        .flat_map(|(y, row)| row.chars().enumerate()
+            .filter(|&(_, cell)| cell == 'X')
+            .map(move |(x, _)| (x as i8, y as i8)))
+        .collect();
+    if cells.is_empty() {
+        return Err("a rotation state has no cells".to_string());
+    }
+    Ok(cells)
+}
+
+/// Parses `#rrggbb` or three numbers separated by spaces.
+fn parse_color(value: &str) -> Result<(u8, u8, u8), String> {
+    let invalid = || format!("invalid color `{}`", value);
+    if let Some(hex) = value.strip_prefix('#') {
+        if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid());
+        return Ok((channel(0)?, channel(2)?, channel(4)?));
+    }
+    let channels = value.split_whitespace()
+        .map(|channel| channel.parse::<u8>().map_err(|_| invalid()))
+        .collect::<Result<Vec<_>, String>>()?;
+    match channels[..] {
+        [r, g, b] => Ok((r, g, b)),
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted_cells(cells: &ShapeCells) -> Vec<Cell> {
+        let mut cells: Vec<Cell> = cells.iter().copied().collect();
+        cells.sort_by_key(|&(x, y)| (y, x));
+        cells
+    }
+
+    #[test]
+    fn single_state_is_rotated_clockwise() {
+        let piece_set = PieceSet::parse("name = Test\n\npiece L3\ncolor = #ff8700\nX.\nXX\n").unwrap();
+        let states = piece_set.pieces[0].states;
+        assert_eq!(sorted_cells(&states[0]), [(0, 0), (0, 1), (1, 1)]);
+        assert_eq!(sorted_cells(&states[1]), [(0, 0), (1, 0), (0, 1)]);
+        assert_eq!(sorted_cells(&states[2]), [(0, 0), (1, 0), (1, 1)]);
+        assert_eq!(sorted_cells(&states[3]), [(1, 0), (0, 1), (1, 1)]);
+        assert_eq!(piece_set.pieces[0].color, (255, 135, 0));
+    }
+
+    #[test]
+    fn spawn_is_centred() {
+        let piece_set = PieceSet::parse("piece I3\ncolor = 0 255 255\nXXX\n").unwrap();
+        assert_eq!(piece_set.name, "Custom");
+        assert_eq!(piece_set.spawn_position(ShapeType::Custom(0)), (3, 0));
+    }
+
+    #[test]
+    fn invalid_files_are_rejected() {
+        assert!(PieceSet::parse("").is_err());
+        assert!(PieceSet::parse("piece A\nXX\n").is_err());
+        assert!(PieceSet::parse("piece A\ncolor = #fff\nXX\n").is_err());
+        assert!(PieceSet::parse("piece A\ncolor = #0é000\nXX\n").is_err());
+        assert!(PieceSet::parse("piece A\ncolor = #+fffff\nXX\n").is_err());
+        assert!(PieceSet::parse("piece A\ncolor = #ffffff\nXXXXXX\n").is_err());
+        assert!(PieceSet::parse("piece A\ncolor = #ffffff\nX\n\nX\n").is_err());
+    }
+
+    #[test]
+    fn bundled_sets_load() {
+        for (contents, count, cells) in [
+            (include_str!("../pieces/triomino"), 2, 3),
+            (include_str!("../pieces/pentomino"), 18, 5),
+        ] {
+            let piece_set = PieceSet::parse(contents).unwrap();
+            assert_eq!(piece_set.pieces.len(), count);
+            for piece in &piece_set.pieces {
+                assert!(piece.states.iter().all(|state| state.len() == cells), "{}", piece.name);
+            }
+        }
+        assert_eq!(PieceSet::parse(include_str!("../pieces/pentomix")).unwrap().pieces.len(), 25);
+    }
+}
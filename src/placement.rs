@@ -0,0 +1,323 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use crate::board::Board;
+use crate::input::Action;
+use crate::rotation::RotationSystem;
+use crate::shape::Shape;
+use crate::update::{fall_instantly, try_fall, try_move_left, try_move_right, try_rotate};
+
+/// Actions that move the falling shape without locking it, in the order they are tried. Moves
+/// come before rotations and soft drops, so of two equally short paths the one that drops last wins.
+const MOVES: [Action; 5] = [
+    Action::MoveLeft, Action::MoveRight, Action::Rotate, Action::RotateCounterClockwise, Action::SoftDrop,
+];
+
+/// Column, row and rotation state of a shape, which is all that changes while it is moved.
+type Position = (i16, i16, u8);
+
+fn position(shape: &Shape) -> Position {
+    (shape.x, shape.y, shape.rotation_state())
+}
+
+/// Every position the shape can be brought to from `start`, in the order a breadth first search
+/// reaches them, with the action each one was first reached by.
+struct Search {
+    shapes: Vec<Shape>,
+    reached_by: HashMap<Position, (Position, Action)>,
+}
+
+impl Search {
+    fn run(start: &Shape, board: &Board, rotation_system: &dyn RotationSystem) -> Search {
+        let mut search = Search { shapes: Vec::new(), reached_by: HashMap::new() };
+        let mut seen = HashSet::from([position(start)]);
+        let mut queue = VecDeque::from([*start]);
+        while let Some(shape) = queue.pop_front() {
+            search.shapes.push(shape);
+            for action in MOVES {
+                let mut moved = shape;
+                if apply_move(&mut moved, action, board, rotation_system) && seen.insert(position(&moved)) {
+                    search.reached_by.insert(position(&moved), (position(&shape), action));
+                    queue.push_back(moved);
+                }
+            }
+        }
+        search
+    }
+
+    /// The shortest list of actions from the start to the shape.
+    fn path(&self, shape: &Shape) -> Vec<Action> {
+        let mut path = Vec::new();
+        let mut current = position(shape);
+        while let Some(&(previous, action)) = self.reached_by.get(&current) {
+            path.push(action);
+            current = previous;
+        }
+        path.reverse();
+        path
+    }
+}
+
+/// Applies one of the moves to the shape, returns `false` if it was blocked.
+pub fn apply_move(shape: &mut Shape, action: Action, board: &Board, rotation_system: &dyn RotationSystem) -> bool {
+    match action {
+        Action::MoveLeft => try_move_left(shape, board),
+        Action::MoveRight => try_move_right(shape, board),
+        Action::Rotate => try_rotate(shape, board, rotation_system, 1),
+        Action::RotateCounterClockwise => try_rotate(shape, board, rotation_system, -1),
+        Action::SoftDrop => try_fall(shape, board),
+        _ => false,
+    }
+}
+
+/// Every resting position the shape can reach, each with the shortest list of actions that
+/// brings it right above that position, so a hard drop finishes it. Positions that cover the same
+/// cells in different rotation states are only listed once.
+pub fn reachable_landings(start: &Shape, board: &Board, rotation_system: &dyn RotationSystem) -> Vec<(Shape, Vec<Action>)> {
+    let search = Search::run(start, board, rotation_system);
+    let mut landed = HashSet::new();
+    let mut landed_cells = HashSet::new();
+    let mut landings = Vec::new();
+    for shape in &search.shapes {
+        let mut resting = *shape;
+        fall_instantly(&mut resting, board);
+        if landed.insert(position(&resting)) && landed_cells.insert(sorted_cells(&resting)) {
+            landings.push((resting, search.path(shape)));
+        }
+    }
+    landings
+}
+
+/// The shortest list of actions that locks the shape where `target` is, with the moves, rotations,
+/// kicks and soft drops of the rules, or `None` if it cannot get there. The shape is finished with
+/// a hard drop when `hard_drop` is allowed, otherwise it is soft dropped all the way and left to
+/// gravity. Shapes that cover the same cells count as the same target whatever their rotation.
+pub fn find_path(start: &Shape, target: &Shape, board: &Board, rotation_system: &dyn RotationSystem,
+        hard_drop: bool) -> Option<Vec<Action>> {
+    let target_cells = sorted_cells(target);
+    let search = Search::run(start, board, rotation_system);
+    let mut best: Option<Vec<Action>> = None;
+    for shape in &search.shapes {
+        let mut resting = *shape;
+        let rows = fall_instantly(&mut resting, board);
+        if sorted_cells(&resting) != target_cells {
+            continue;
+        }
+        let mut path = search.path(shape);
+        if hard_drop {
+            path.push(Action::HardDrop);
+        }
+        else {
+            path.extend((0..rows).map(|_| Action::SoftDrop));
+        }
+        if best.as_ref().is_none_or(|best| path.len() < best.len()) {
+            best = Some(path);
+        }
+    }
+    best
+}
+
+/// The path that locks the shape where `target` is with the fewest key presses, or `None` if it
+/// cannot get there. Unlike `find_path` it counts presses rather than actions: a move held until
+/// the shape is blocked is one press, like a tap, and so is a soft drop held for any number of
+/// rows. Without hard drop the final fall is left to gravity and costs nothing.
+pub fn find_press_path(start: &Shape, target: &Shape, board: &Board, rotation_system: &dyn RotationSystem,
+        hard_drop: bool) -> Option<Vec<Action>> {
+    let target_cells = sorted_cells(target);
+    let mut reached_by: HashMap<Position, (Position, Vec<Action>)> = HashMap::new();
+    let mut seen = HashSet::from([position(start)]);
+    let mut queue = VecDeque::from([*start]);
+    while let Some(shape) = queue.pop_front() {
+        let mut resting = shape;
+        let rows = fall_instantly(&mut resting, board);
+        if sorted_cells(&resting) == target_cells {
+            let mut presses = Vec::new();
+            let mut current = position(&shape);
+            while let Some((previous, actions)) = reached_by.get(&current) {
+                presses.push(actions.clone());
+                current = *previous;
+            }
+            let mut path: Vec<Action> = presses.into_iter().rev().flatten().collect();
+            if hard_drop {
+                path.push(Action::HardDrop);
+            }
+            else {
+                path.extend((0..rows).map(|_| Action::SoftDrop));
+            }
+            return Some(path);
+        }
+        for (pressed, actions) in presses(&shape, board, rotation_system) {
+            if seen.insert(position(&pressed)) {
+                reached_by.insert(position(&pressed), (position(&shape), actions));
+                queue.push_back(pressed);
+            }
+        }
+    }
+    None
+}
+
+/// Where a single key press can bring the shape, with the actions the press repeats: a tap or a
+/// move held to where the shape is blocked, a rotation, or a soft drop held for some rows.
+fn presses(shape: &Shape, board: &Board, rotation_system: &dyn RotationSystem) -> Vec<(Shape, Vec<Action>)> {
+    let mut presses = Vec::new();
+    for action in MOVES {
+        let mut pressed = *shape;
+        let mut actions = Vec::new();
+        while apply_move(&mut pressed, action, board, rotation_system) {
+            actions.push(action);
+            let mut further = pressed;
+            let held_to_the_end = matches!(action, Action::MoveLeft | Action::MoveRight)
+                && !apply_move(&mut further, action, board, rotation_system);
+            if actions.len() == 1 || held_to_the_end || action == Action::SoftDrop {
+                presses.push((pressed, actions.clone()));
+            }
+            if matches!(action, Action::Rotate | Action::RotateCounterClockwise) {
+                break;
+            }
+        }
+    }
+    presses
+}
+
+fn sorted_cells(shape: &Shape) -> Vec<(i16, i16)> {
+    let mut cells = shape.get_occupied_squares();
+    cells.sort();
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Square, BOARD_HEIGHT_IN_TILES};
+    use crate::rotation::RotationSystemName;
+    use crate::shape::ShapeType;
+    use Action::{HardDrop, MoveLeft, Rotate, RotateCounterClockwise, SoftDrop};
+
+    fn srs() -> &'static dyn RotationSystem {
+        RotationSystemName::Srs.system()
+    }
+
+    /// A board whose bottom rows are drawn with `X` for filled and `.` for empty squares.
+    fn board(rows: &[&str]) -> Board {
+        let mut board = Board::new();
+        let top = BOARD_HEIGHT_IN_TILES - rows.len();
+        for (y, row) in rows.iter().enumerate() {
+            for (x, square) in row.chars().enumerate() {
+                if square == 'X' {
+                    board.set(x, top + y, Some(Square::Garbage));
+                }
+            }
+        }
+        board
+    }
+
+    fn spawn(shape_type: ShapeType) -> Shape {
+        let (x, y) = srs().spawn_position(shape_type);
+        Shape::new(srs(), shape_type, x, y)
+    }
+
+    fn shape(shape_type: ShapeType, rotation_state: i8, x: i16, y: i16) -> Shape {
+        let mut shape = Shape::new(srs(), shape_type, x, y);
+        shape.rotate(rotation_state);
+        shape
+    }
+
+    /// Plays the path and checks that the shape ends up on the target.
+    fn assert_reaches(start: &Shape, path: &[Action], target: &Shape, board: &Board) {
+        let mut shape = *start;
+        for &action in path {
+            match action {
+                Action::HardDrop => { fall_instantly(&mut shape, board); },
+                action => assert!(apply_move(&mut shape, action, board, srs()), "{:?} was blocked", action),
+            }
+        }
+        assert_eq!(sorted_cells(&shape), sorted_cells(target));
+    }
+
+    #[test]
+    fn moves_and_drops_on_an_empty_board() {
+        let board = Board::new();
+        let start = spawn(ShapeType::T);
+        let target = shape(ShapeType::T, 0, 0, 18);
+        assert_eq!(find_path(&start, &target, &board, srs(), true), Some(vec![MoveLeft, MoveLeft, MoveLeft, HardDrop]));
+    }
+
+    #[test]
+    fn rotates_the_shorter_way() {
+        let board = Board::new();
+        let start = spawn(ShapeType::J);
+        let target = shape(ShapeType::J, 3, 3, 17);
+        assert_eq!(find_path(&start, &target, &board, srs(), true), Some(vec![RotateCounterClockwise, HardDrop]));
+    }
+
+    #[test]
+    fn same_cells_in_another_rotation_are_the_same_target() {
+        let board = Board::new();
+        let start = spawn(ShapeType::S);
+        // The S covers the same cells in states 1 and 3, one column apart.
+        let target = shape(ShapeType::S, 3, 4, 17);
+        let path = find_path(&start, &target, &board, srs(), true).unwrap();
+        assert_eq!(path.len(), 2);
+        assert_reaches(&start, &path, &target, &board);
+    }
+
+    #[test]
+    fn tucks_under_an_overhang() {
+        let board = board(&[
+            "XXXXX.....",
+            "..........",
+            "..........",
+        ]);
+        let start = spawn(ShapeType::O);
+        let target = shape(ShapeType::O, 0, -1, 18);
+        let path = find_path(&start, &target, &board, srs(), true).unwrap();
+        assert!(path.contains(&SoftDrop));
+        assert_eq!(path.iter().rev().skip(1).take(4).collect::<Vec<_>>(), vec![&MoveLeft; 4]);
+        assert_reaches(&start, &path, &target, &board);
+    }
+
+    #[test]
+    fn spins_into_a_t_slot() {
+        let board = board(&[
+            "XXX.......",
+            "XX...XXXXX",
+            "XXX.XXXXXX",
+        ]);
+        let start = spawn(ShapeType::T);
+        let target = shape(ShapeType::T, 2, 2, 17);
+        let path = find_path(&start, &target, &board, srs(), true).unwrap();
+        let last_move = path[path.len() - 2];
+        assert!(matches!(last_move, Rotate | RotateCounterClockwise), "{:?} does not end with a spin", path);
+        assert_reaches(&start, &path, &target, &board);
+    }
+
+    #[test]
+    fn soft_drops_all_the_way_without_hard_drop() {
+        let board = Board::new();
+        let start = spawn(ShapeType::O);
+        let target = shape(ShapeType::O, 0, 2, 18);
+        let path = find_path(&start, &target, &board, srs(), false).unwrap();
+        assert_eq!(path, [vec![MoveLeft], vec![SoftDrop; 18]].concat());
+        assert_reaches(&start, &path, &target, &board);
+    }
+
+    #[test]
+    fn closed_holes_cannot_be_reached() {
+        let board = board(&[
+            "XXXXXXXXX.",
+            ".....XXXX.",
+        ]);
+        let start = spawn(ShapeType::I);
+        let target = shape(ShapeType::I, 0, 0, 18);
+        assert_eq!(find_path(&start, &target, &board, srs(), true), None);
+    }
+
+    #[test]
+    fn lists_every_landing_once() {
+        let board = Board::new();
+        let landings = reachable_landings(&spawn(ShapeType::O), &board, srs());
+        // The O rests in nine columns, its rotation states all cover the same cells.
+        assert_eq!(landings.len(), 9);
+        for (shape, path) in &landings {
+            assert_reaches(&spawn(ShapeType::O), &[path.clone(), vec![HardDrop]].concat(), shape, &board);
+        }
+    }
+}
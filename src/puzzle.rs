@@ -0,0 +1,509 @@
+use std::fs;
+use std::io::{self, Error, ErrorKind};
+use std::path::Path;
+use std::time::SystemTime;
+use crate::{Square, BOARD_HEIGHT_IN_TILES, BOARD_WIDTH_IN_TILES};
+use crate::board::Board;
+use crate::config::{data_files, Config};
+use crate::game::{Game, GameStatus, Step};
+use crate::input::{receive_input, receive_menu_input, Action, MenuAction};
+use crate::mode::GameMode;
+use crate::net::{shape_from_char, square_to_char};
+use crate::randomizer::Randomizer;
+use crate::records::{PuzzleRecord, Records};
+use crate::render::{self, Layout};
+use crate::ruleset::Ruleset;
+use crate::shape::ShapeType;
+use crate::theme::Theme;
+
+/// The pack that comes with the game.
+const BUILT_IN_PACK: &str = include_str!("../puzzles/basics");
+
+/// What has to be done to solve a puzzle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PuzzleGoal {
+    /// Clear at least the given number of lines.
+    Lines(u32),
+    TSpinDouble,
+    /// Clear lines until the board is empty.
+    PerfectClear,
+}
+
+impl PuzzleGoal {
+    /// Parses `lines <count>`, `tsd` or `pc`.
+    fn parse(text: &str) -> Result<PuzzleGoal, String> {
+        match text.split_whitespace().collect::<Vec<_>>().as_slice() {
+            ["lines", count] => match count.parse() {
+                Ok(count) if count > 0 => Ok(PuzzleGoal::Lines(count)),
+                _ => Err(format!("expected a positive number of lines, got `{}`", count)),
+            },
+            ["tsd"] => Ok(PuzzleGoal::TSpinDouble),
+            ["pc"] => Ok(PuzzleGoal::PerfectClear),
+            _ => Err(format!("unknown goal `{}`, expected lines <count>, tsd or pc", text)),
+        }
+    }
+
+    /// The goal as written in puzzle packs, read back by `parse`.
+    pub fn id(self) -> String {
+        match self {
+            PuzzleGoal::Lines(lines) => format!("lines {}", lines),
+            PuzzleGoal::TSpinDouble => "tsd".to_string(),
+            PuzzleGoal::PerfectClear => "pc".to_string(),
+        }
+    }
+
+    pub fn label(self) -> String {
+        match self {
+            PuzzleGoal::Lines(1) => "Clear a line".to_string(),
+            PuzzleGoal::Lines(lines) => format!("Clear {} lines", lines),
+            PuzzleGoal::TSpinDouble => "T-spin double".to_string(),
+            PuzzleGoal::PerfectClear => "Perfect clear".to_string(),
+        }
+    }
+
+    pub fn is_reached(self, game: &Game) -> bool {
+        match self {
+            PuzzleGoal::Lines(lines) => game.stats.lines >= lines,
+            PuzzleGoal::TSpinDouble => game.stats.t_spins[2] > 0,
+            PuzzleGoal::PerfectClear => game.stats.perfect_clears > 0,
+        }
+    }
+}
+
+/// The goal of a puzzle and the pieces it gives, kept by the game while the puzzle is played.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Challenge {
+    pub goal: PuzzleGoal,
+    /// Pieces of the puzzle still in the queue, the held piece is played once they run out.
+    pub queued: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Puzzle {
+    pub name: String,
+    pub goal: PuzzleGoal,
+    /// Dealt once in this order, the puzzle is failed when they and the held piece are all locked.
+    pub pieces: Vec<ShapeType>,
+    /// Held from the start, it can be locked on top of the pieces and turns hold on.
+    pub hold: Option<ShapeType>,
+    pub board: Board,
+}
+
+impl Puzzle {
+    /// A game on the board of the puzzle with its pieces.
+    pub fn start(&self, mut ruleset: Ruleset) -> Game {
+        ruleset.hold |= self.hold.is_some();
+        let mut game = Game::new(GameMode::Puzzle, ruleset);
+        // The randomizer fills the queue past the pieces, `Game::preview_count` keeps that hidden.
+        let mut randomizer = Randomizer::new(game.ruleset.randomizer, game.ruleset.shape_types());
+        randomizer.deal_first(&self.pieces);
+        game.set_up(self.board, randomizer);
+        game.held_shape = self.hold;
+        game.challenge = Some(Challenge { goal: self.goal, queued: self.pieces.len() as u32 - 1 });
+        game
+    }
+
+    /// The puzzle as written in pack files, read back by `PuzzlePack::parse`.
+    pub fn to_text(&self) -> String {
+        let pieces: String = self.pieces.iter().map(|&shape_type| square_to_char(Some(Square::Shape(shape_type)))).collect();
+        let mut text = format!("puzzle {}\ngoal = {}\npieces = {}\n", self.name, self.goal.id(), pieces);
+        if let Some(hold) = self.hold {
+            text.push_str(&format!("hold = {}\n", square_to_char(Some(Square::Shape(hold)))));
+        }
+        for row in board_rows(&self.board) {
+            text.push_str(&row);
+            text.push('\n');
+        }
+        text
+    }
+}
+
+/// Puzzles loaded from a data file.
+///
+/// The file names the pack and then lists its puzzles. Every puzzle has a name, a goal, the
+/// pieces it gives, optionally a piece held from the start, and the bottom rows of its board,
+/// drawn from the top down with `.` for empty, `G` or `X` for garbage and the letter of a
+/// tetromino for a square of its colour. Lines starting with `#` are comments.
+///
+/// ```text
+/// name = Basics
+///
+/// puzzle First tetris
+/// goal = lines 4
+/// pieces = I
+/// GGGGGGGGG.
+/// GGGGGGGGG.
+/// GGGGGGGGG.
+/// GGGGGGGGG.
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PuzzlePack {
+    /// Identifies the pack in the records file, the name of its file.
+    pub id: String,
+    pub name: String,
+    pub puzzles: Vec<Puzzle>,
+}
+
+/// A puzzle as read from the file, before it is checked.
+struct PuzzleSource {
+    name: String,
+    goal: Option<PuzzleGoal>,
+    pieces: Vec<ShapeType>,
+    hold: Option<ShapeType>,
+    rows: Vec<Row>,
+}
+
+impl PuzzlePack {
+    pub fn load(path: &Path) -> Result<PuzzlePack, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+        let id = path.file_stem().map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+        PuzzlePack::parse(&id, &contents).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn parse(id: &str, contents: &str) -> Result<PuzzlePack, String> {
+        let mut name = None;
+        let mut sources: Vec<PuzzleSource> = Vec::new();
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: String| format!("line {}: {}", number + 1, message);
+
+            if let Some(puzzle_name) = line.strip_prefix("puzzle ") {
+                sources.push(PuzzleSource { name: puzzle_name.trim().to_string(), goal: None, pieces: Vec::new(), hold: None,
+                    rows: Vec::new() });
+            }
+            else if let Some((key, value)) = line.split_once('=') {
+                let value = value.trim();
+                match (key.trim(), sources.last_mut()) {
+                    ("name", None) => name = Some(value.to_string()),
+                    ("goal", Some(puzzle)) => puzzle.goal = Some(PuzzleGoal::parse(value).map_err(error)?),
+                    ("pieces", Some(puzzle)) => puzzle.pieces = parse_pieces(value).map_err(error)?,
+                    ("hold", Some(puzzle)) => puzzle.hold = Some(parse_hold(value).map_err(error)?),
+                    (key, _) => return Err(error(format!("unexpected key `{}`", key))),
+                }
+            }
+            else {
+                let puzzle = sources.last_mut().ok_or_else(|| error("board outside of a puzzle".to_string()))?;
+                puzzle.rows.push(parse_row(line).map_err(error)?);
+            }
+        }
+
+        let name = name.ok_or("missing `name`")?;
+        let puzzles = sources.into_iter().map(PuzzleSource::into_puzzle).collect::<Result<Vec<_>, _>>()?;
+        if puzzles.is_empty() {
+            return Err("the pack has no puzzles".to_string());
+        }
+        Ok(PuzzlePack { id: id.to_string(), name, puzzles })
+    }
+
+    /// Identifies the puzzle in the records file.
+    pub fn puzzle_id(&self, puzzle: &Puzzle) -> String {
+        format!("{}/{}", self.id, puzzle.name.to_lowercase().split_whitespace().collect::<Vec<_>>().join("-"))
+    }
+}
+
+impl PuzzleSource {
+    fn into_puzzle(self) -> Result<Puzzle, String> {
+        let error = |message: &str| format!("puzzle `{}`: {}", self.name, message);
+        let goal = self.goal.ok_or_else(|| error("missing `goal`"))?;
+        if self.pieces.is_empty() {
+            return Err(error("missing `pieces`"));
+        }
+        let board = board_from_rows(&self.rows).map_err(|e| error(&e))?;
+        Ok(Puzzle { name: self.name, goal, pieces: self.pieces, hold: self.hold, board })
+    }
+}
+
+/// Puzzles and positions are made for the tetrominoes, written as their letters.
+fn tetromino_from_char(c: char) -> Result<ShapeType, String> {
+    match shape_from_char(c) {
+        Ok(shape_type) if ShapeType::ALL.contains(&shape_type) => Ok(shape_type),
+        _ => Err(format!("unknown piece `{}`, expected one of IJLOSTZ", c)),
+    }
+}
+
+pub fn parse_pieces(value: &str) -> Result<Vec<ShapeType>, String> {
+    value.chars().filter(|c| !c.is_whitespace()).map(tetromino_from_char).collect()
+}
+
+pub fn parse_hold(value: &str) -> Result<ShapeType, String> {
+    match parse_pieces(value)?.as_slice() {
+        &[shape_type] => Ok(shape_type),
+        _ => Err(format!("expected a single held piece, got `{}`", value)),
+    }
+}
+
+/// A row of a board, from the left.
+pub type Row = [Option<Square>; BOARD_WIDTH_IN_TILES];
+
+pub fn parse_row(line: &str) -> Result<Row, String> {
+    let mut row = [None; BOARD_WIDTH_IN_TILES];
+    if line.chars().count() != BOARD_WIDTH_IN_TILES {
+        return Err(format!("expected rows of {} squares, got `{}`", BOARD_WIDTH_IN_TILES, line));
+    }
+    for (x, c) in line.chars().enumerate() {
+        row[x] = match c {
+            '.' => None,
+            'G' | 'X' => Some(Square::Garbage),
+            _ => Some(Square::Shape(tetromino_from_char(c)?)),
+        };
+    }
+    Ok(row)
+}
+
+/// The board with the given rows at the bottom, the first of them the highest.
+pub fn board_from_rows(rows: &[Row]) -> Result<Board, String> {
+    if rows.len() > BOARD_HEIGHT_IN_TILES {
+        return Err(format!("the board is higher than {} rows", BOARD_HEIGHT_IN_TILES));
+    }
+    let mut board = Board::new();
+    let top = BOARD_HEIGHT_IN_TILES - rows.len();
+    for (i, row) in rows.iter().enumerate() {
+        for (x, &square) in row.iter().enumerate() {
+            board.set(x, top + i, square);
+        }
+    }
+    Ok(board)
+}
+
+/// The rows of the board from the highest one with a square in it down, drawn like in pack files.
+pub fn board_rows(board: &Board) -> Vec<String> {
+    let top = (0..BOARD_HEIGHT_IN_TILES).find(|&y| board.row(y) != 0).unwrap_or(BOARD_HEIGHT_IN_TILES);
+    (top..BOARD_HEIGHT_IN_TILES)
+        .map(|y| (0..BOARD_WIDTH_IN_TILES).map(|x| square_to_char(board.get(x, y))).collect())
+        .collect()
+}
+
+/// The pack that comes with the game, followed by those in the `puzzles` directory of the data
+/// directory and the one given by `puzzles`.
+fn load_packs(config: &Config) -> Result<Vec<PuzzlePack>, String> {
+    let mut packs = vec![PuzzlePack::parse("basics", BUILT_IN_PACK)?];
+    for path in data_files("puzzles", config.puzzles.as_ref()) {
+        packs.push(PuzzlePack::load(&path)?);
+    }
+    Ok(packs)
+}
+
+/// Lets the player pick puzzles from the packs and play them until they go back to the menu.
+pub fn play(theme: &Theme, config: &Config, records: &mut Records) -> io::Result<()> {
+    let packs = load_packs(config).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    let (mut pack, mut selected) = (0, 1);
+    loop {
+        let puzzles = &packs[pack].puzzles;
+        let mut items = vec![format!("Pack:  < {} >", packs[pack].name)];
+        for puzzle in puzzles {
+            let mark = match records.puzzles.get(&packs[pack].puzzle_id(puzzle)) {
+                Some(PuzzleRecord { solved: true, .. }) => "[x]",
+                Some(_) => "[-]",
+                None => "[ ]",
+            };
+            items.push(format!("{} {}", mark, puzzle.name));
+        }
+        items.push("Back".to_string());
+        render::render_menu(theme, &items, selected)?;
+
+        match receive_menu_input()? {
+            MenuAction::Up => selected = (selected + items.len() - 1) % items.len(),
+            MenuAction::Down => selected = (selected + 1) % items.len(),
+            MenuAction::Left if selected == 0 => pack = (pack + packs.len() - 1) % packs.len(),
+            MenuAction::Right if selected == 0 => pack = (pack + 1) % packs.len(),
+            MenuAction::Select if selected == items.len() - 1 => break,
+            MenuAction::Select if selected > 0 => {
+                let puzzle = &puzzles[selected - 1];
+                solve(theme, config, records, &packs[pack].puzzle_id(puzzle), puzzle)?;
+            },
+            MenuAction::Quit => break,
+            _ => {},
+        }
+    }
+    render::clear_screen()
+}
+
+/// Plays a puzzle until the player goes back, counting an attempt every time it ends. Locked
+/// shapes can be taken back one by one, even once the puzzle ended.
+fn solve(theme: &Theme, config: &Config, records: &mut Records, id: &str, puzzle: &Puzzle) -> io::Result<()> {
+    play_until_back(theme, records, &puzzle.name, true, || puzzle.start(config.game_ruleset()), |game, records| {
+        let solved = game.status == GameStatus::Finished;
+        records.submit_puzzle(id, solved);
+        records.save()?;
+        Ok(if solved { "SOLVED" } else { "FAILED" })
+    })
+}
+
+/// Plays games made by `start` until the player goes back, starting over on retry. `title` is
+/// called once a game ends and heads its popup. With `undo`, locked shapes can be taken back one
+/// by one, even once the game ended, otherwise no history is kept and undo does nothing.
+pub fn play_until_back(theme: &Theme, records: &mut Records, name: &str, undo: bool, start: impl Fn() -> Game,
+        mut title: impl FnMut(&Game, &mut Records) -> io::Result<&'static str>) -> io::Result<()> {
+    let layout = Layout::single();
+    let mut game = start();
+    // The game as the current shape came in, and as every locked shape came in before it.
+    let mut spawned = game.clone();
+    let mut history: Vec<Game> = Vec::new();
+    let mut next_fall = SystemTime::now() + game.gravity_interval();
+    render::clear_screen()?;
+    render::render_game(theme, &layout, &mut game)?;
+
+    loop {
+        let mut before = game.clone();
+        let playing = game.status == GameStatus::Playing;
+
+        let step = if playing && next_fall < SystemTime::now() {
+            next_fall += game.gravity_interval();
+            game.apply_gravity()
+        }
+        else {
+            match receive_input()? {
+                Action::Quit => return Ok(()),
+                action @ (Action::Undo | Action::Retry) => {
+                    let previous = match action {
+                        Action::Undo => history.pop(),
+                        _ => {
+                            history.clear();
+                            Some(start())
+                        },
+                    };
+                    if let Some(previous) = previous {
+                        game = previous;
+                        spawned = game.clone();
+                        next_fall = SystemTime::now() + game.gravity_interval();
+                        render::clear_screen()?;
+                        render::render_game(theme, &layout, &mut game)?;
+                        // Nothing held before makes the held shape drawn again.
+                        before = game.clone();
+                        before.held_shape = None;
+                    }
+                    Step::default()
+                },
+                Action::None => Step::default(),
+                _ if !playing => return Ok(()),
+                action => game.apply_action(action),
+            }
+        };
+        if undo && step.locked {
+            history.push(spawned);
+            spawned = game.clone();
+        }
+
+        game.update_clock();
+        let mut hud_lines = vec![name.to_string()];
+        hud_lines.extend(game.mode.hud_lines(&game, records));
+        render::render_game_changes(theme, &layout, &mut before, &mut game, step, &hud_lines)?;
+        if let Some(template) = &game.template && game.status == GameStatus::Playing {
+            render::render_template(theme, &layout, &template.board, &game.locked_squares, &game.falling_shape)?;
+        }
+
+        if playing && game.status != GameStatus::Playing {
+            let mut lines = vec![title(&game, records)?.to_string(), String::new()];
+            let summary = game.mode.finish(&game, records);
+            if !summary.is_empty() {
+                lines.extend(summary);
+                lines.push(String::new());
+            }
+            if undo {
+                lines.push("u  Undo".to_string());
+            }
+            lines.extend(["r  Retry".to_string(), "q  Back".to_string()]);
+            render::render_popup(theme, &layout, &lines)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use crate::placement;
+    use crate::ruleset::RulesetName;
+
+    /// Whether some order of placements, without hold, solves the puzzle. The same board reached
+    /// in another order is only tried once.
+    fn is_solvable(game: &Game, tried: &mut HashSet<(Vec<u16>, u32, u32)>) -> bool {
+        let rows = (0..BOARD_HEIGHT_IN_TILES).map(|y| game.locked_squares.row(y)).collect();
+        if !tried.insert((rows, game.stats.pieces, game.stats.lines)) {
+            return false;
+        }
+        match game.status {
+            GameStatus::Finished => true,
+            GameStatus::ToppedOut => false,
+            GameStatus::Playing => {
+                placement::reachable_landings(&game.falling_shape, &game.locked_squares, game.rotation_system())
+                    .into_iter()
+                    .any(|(_, actions)| {
+                        let mut game = game.clone();
+                        for action in actions {
+                            game.apply_action(action);
+                        }
+                        game.apply_action(Action::HardDrop);
+                        is_solvable(&game, tried)
+                    })
+            },
+        }
+    }
+
+    #[test]
+    fn every_built_in_puzzle_can_be_solved() {
+        let pack = PuzzlePack::parse("basics", BUILT_IN_PACK).unwrap();
+        for puzzle in &pack.puzzles {
+            let game = puzzle.start(Ruleset::preset(RulesetName::Guideline));
+            assert!(is_solvable(&game, &mut HashSet::new()), "{} cannot be solved", puzzle.name);
+        }
+    }
+
+    #[test]
+    fn fails_once_the_pieces_run_out() {
+        let pack = PuzzlePack::parse("basics", BUILT_IN_PACK).unwrap();
+        let mut game = pack.puzzles[0].start(Ruleset::preset(RulesetName::Guideline));
+        assert_eq!(game.falling_shape.shape_type, ShapeType::I);
+        game.apply_action(Action::HardDrop);
+        assert_eq!(game.status, GameStatus::ToppedOut);
+        assert_eq!(pack.puzzle_id(&pack.puzzles[0]), "basics/first-tetris");
+    }
+
+    #[test]
+    fn the_held_piece_is_played_last_and_the_pieces_are_dealt_once() {
+        let puzzle = Puzzle {
+            name: "Held".to_string(),
+            goal: PuzzleGoal::Lines(1),
+            pieces: vec![ShapeType::I, ShapeType::T],
+            hold: Some(ShapeType::O),
+            board: Board::new(),
+        };
+        let mut game = puzzle.start(Ruleset::preset(RulesetName::Standard));
+        assert!(game.ruleset.hold);
+        assert_eq!((game.falling_shape.shape_type, game.preview_count()), (ShapeType::I, 1));
+        game.apply_action(Action::HardDrop);
+        assert_eq!((game.falling_shape.shape_type, game.preview_count()), (ShapeType::T, 0));
+        game.apply_action(Action::HardDrop);
+        assert_eq!((game.falling_shape.shape_type, game.held_shape), (ShapeType::O, None));
+        game.apply_action(Action::Hold);
+        assert_eq!(game.falling_shape.shape_type, ShapeType::O);
+        game.apply_action(Action::HardDrop);
+        assert_eq!(game.status, GameStatus::ToppedOut);
+    }
+
+    #[test]
+    fn reports_broken_packs() {
+        assert_eq!(PuzzlePack::parse("x", "puzzle A\n"), Err("missing `name`".to_string()));
+        assert_eq!(PuzzlePack::parse("x", "name = P\npuzzle A\npieces = I\n"), Err("puzzle `A`: missing `goal`".to_string()));
+        assert_eq!(PuzzlePack::parse("x", "name = P\npuzzle A\ngoal = lines 1\npieces = I\nGGGG\n"),
+            Err("line 5: expected rows of 10 squares, got `GGGG`".to_string()));
+        assert_eq!(PuzzlePack::parse("x", "name = P\npuzzle A\ngoal = tst\n"),
+            Err("line 3: unknown goal `tst`, expected lines <count>, tsd or pc".to_string()));
+        assert_eq!(PuzzlePack::parse("x", "name = P\npuzzle A\nhold = OI\n"),
+            Err("line 3: expected a single held piece, got `OI`".to_string()));
+    }
+
+    #[test]
+    fn only_tetrominoes_are_accepted() {
+        assert_eq!(PuzzlePack::parse("x", "name = P\npuzzle A\ngoal = lines 1\npieces = i\n"),
+            Err("line 4: unknown piece `i`, expected one of IJLOSTZ".to_string()));
+        assert_eq!(PuzzlePack::parse("x", "name = P\npuzzle A\ngoal = lines 1\npieces = I\nhold = 3\n"),
+            Err("line 5: unknown piece `3`, expected one of IJLOSTZ".to_string()));
+        assert_eq!(PuzzlePack::parse("x", "name = P\npuzzle A\ngoal = lines 1\npieces = I\nGGGGGaGGG.\n"),
+            Err("line 5: unknown piece `a`, expected one of IJLOSTZ".to_string()));
+    }
+}
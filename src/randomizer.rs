@@ -0,0 +1,97 @@
+use rand::prelude::*;
+use crate::shape::ShapeType;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RandomizerKind {
+    /// Every shape type is equally likely on every draw.
+    Random,
+    /// All shape types are dealt in a random order before any repeats.
+    Bag,
+    /// The NES generator: rerolls once when it draws the previous shape type.
+    Nes,
+}
+
+/// Decides the order in which shapes are dealt.
+#[derive(Clone, Debug)]
+pub struct Randomizer {
+    kind: RandomizerKind,
+    shape_types: Vec<ShapeType>,
+    /// Shape types to deal before any others, the next one last.
+    first: Vec<ShapeType>,
+    bag: Vec<ShapeType>,
+    last: Option<ShapeType>,
+}
+
+impl Randomizer {
+    pub fn new(kind: RandomizerKind, shape_types: Vec<ShapeType>) -> Randomizer {
+        Randomizer { kind, shape_types, first: Vec::new(), bag: Vec::new(), last: None }
+    }
+
+    pub fn next(&mut self) -> ShapeType {
+        if let Some(shape_type) = self.first.pop() {
+            self.last = Some(shape_type);
+            return shape_type;
+        }
+        let shape_type = match self.kind {
+            RandomizerKind::Random => self.random(),
+            RandomizerKind::Bag => {
+                if self.bag.is_empty() {
+                    self.bag = self.shape_types.clone();
+                    self.bag.shuffle(&mut thread_rng());
+                }
+                self.bag.pop().unwrap()
+            },
+            RandomizerKind::Nes => {
+                // The NES rolls one of eight values, the eighth and a repeat both cause a reroll.
+                let roll = thread_rng().gen_range(0..=self.shape_types.len());
+                match self.shape_types.get(roll) {
+                    Some(&shape_type) if Some(shape_type) != self.last => shape_type,
+                    _ => self.random(),
+                }
+            },
+        };
+        self.last = Some(shape_type);
+        shape_type
+    }
+
+    /// Deals the given shape types before any others, in order.
+    pub fn deal_first(&mut self, shape_types: &[ShapeType]) {
+        self.first.extend(shape_types.iter().rev());
+    }
+
+    /// Shape types already decided, in the order they will be dealt: those dealt first and the
+    /// rest of the bag.
+    pub fn upcoming(&self) -> Vec<ShapeType> {
+        self.first.iter().rev().chain(self.bag.iter().rev()).copied().collect()
+    }
+
+    fn random(&self) -> ShapeType {
+        *self.shape_types.choose(&mut thread_rng()).expect("Could not choose a shape type")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nes_rerolls_most_repeats() {
+        let mut randomizer = Randomizer::new(RandomizerKind::Nes, ShapeType::ALL.to_vec());
+        let shapes: Vec<ShapeType> = (0..7000).map(|_| randomizer.next()).collect();
+        let repeats = shapes.windows(2).filter(|pair| pair[0] == pair[1]).count();
+        // A repeat needs a roll of the same shape or of the eighth value, then the same shape
+        // again: 1 in 28 against 1 in 7 without the reroll.
+        assert!(repeats < 7000 / 14, "{} repeats", repeats);
+        assert!(ShapeType::ALL.iter().all(|shape_type| shapes.contains(shape_type)));
+    }
+
+    #[test]
+    fn shapes_dealt_first_come_before_the_randomizer() {
+        let mut randomizer = Randomizer::new(RandomizerKind::Nes, vec![ShapeType::I]);
+        randomizer.deal_first(&[ShapeType::T, ShapeType::O]);
+        assert_eq!(randomizer.upcoming(), vec![ShapeType::T, ShapeType::O]);
+        assert_eq!(randomizer.next(), ShapeType::T);
+        assert_eq!(randomizer.next(), ShapeType::O);
+        assert_eq!(randomizer.next(), ShapeType::I);
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{ErrorKind, Result};
+use std::path::PathBuf;
+use std::time::Duration;
+use crate::config::data_dir;
+use crate::mode::MarathonGoal;
+
+/// Best sprint run for a given line goal, with the time of every tenth line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SprintRecord {
+    pub time: Duration,
+    pub splits: Vec<Duration>,
+}
+
+/// Attempts at a single puzzle.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PuzzleRecord {
+    pub attempts: u32,
+    pub solved: bool,
+}
+
+/// Personal bests and high scores, kept between games in the data directory.
+///
+/// Every record is stored on its own line, starting with its category:
+///
+/// ```text
+/// sprint 40 83412 20311,41234,62000,83412
+/// ultra 120 31200,28450,17800
+/// dig 18 45123
+/// marathon lines-150 52000,31075
+/// puzzle basics/first-tetris 3 1
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Records {
+    /// Keyed by the number of lines to clear.
+    pub sprint: BTreeMap<u32, SprintRecord>,
+    /// Best scores in descending order, keyed by the time limit in seconds.
+    pub ultra: BTreeMap<u64, Vec<u32>>,
+    /// Best dig times, keyed by the number of garbage rows.
+    pub dig: BTreeMap<u32, Duration>,
+    /// Best marathon scores in descending order, keyed by the id of the goal.
+    pub marathon: BTreeMap<String, Vec<u32>>,
+    /// Attempts at every puzzle played, keyed by the id of the puzzle.
+    pub puzzles: BTreeMap<String, PuzzleRecord>,
+}
+
+const HIGH_SCORE_TABLE_SIZE: usize = 10;
+
+impl Records {
+    pub fn load() -> Result<Records> {
+        let mut records = Records::default();
+        let Some(path) = records_path() else {
+            return Ok(records);
+        };
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(records),
+            Err(e) => return Err(e),
+        };
+        for line in contents.lines() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                ["sprint", lines, time, splits] => {
+                    let (Ok(lines), Some(time)) = (lines.parse(), parse_millis(time)) else {
+                        continue;
+                    };
+                    let splits = splits.split(',').filter_map(parse_millis).collect();
+                    records.sprint.insert(lines, SprintRecord { time, splits });
+                },
+                ["ultra", seconds, scores] => {
+                    let Ok(seconds) = seconds.parse() else {
+                        continue;
+                    };
+                    records.ultra.insert(seconds, parse_scores(scores));
+                },
+                ["marathon", goal, scores] => {
+                    records.marathon.insert(goal.to_string(), parse_scores(scores));
+                },
+                ["dig", lines, time] => {
+                    let (Ok(lines), Some(time)) = (lines.parse(), parse_millis(time)) else {
+                        continue;
+                    };
+                    records.dig.insert(lines, time);
+                },
+                ["puzzle", id, attempts, solved] => {
+                    let Ok(attempts) = attempts.parse() else {
+                        continue;
+                    };
+                    records.puzzles.insert(id.to_string(), PuzzleRecord { attempts, solved: *solved == "1" });
+                },
+                _ => {},
+            }
+        }
+        Ok(records)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = records_path() else {
+            return Ok(());
+        };
+        let mut contents = String::new();
+        for (lines, record) in &self.sprint {
+            let splits: Vec<String> = record.splits.iter()
+                .map(|split| split.as_millis().to_string())
+                .collect();
+            contents += &format!("sprint {} {} {}\n", lines, record.time.as_millis(), splits.join(","));
+        }
+        for (seconds, scores) in &self.ultra {
+            contents += &format!("ultra {} {}\n", seconds, format_scores(scores));
+        }
+        for (goal, scores) in &self.marathon {
+            contents += &format!("marathon {} {}\n", goal, format_scores(scores));
+        }
+        for (lines, time) in &self.dig {
+            contents += &format!("dig {} {}\n", lines, time.as_millis());
+        }
+        for (id, record) in &self.puzzles {
+            contents += &format!("puzzle {} {} {}\n", id, record.attempts, record.solved as u8);
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, contents)
+    }
+
+    /// Stores the run if it beats the current best. Returns whether it did.
+    pub fn submit_sprint(&mut self, lines: u32, record: SprintRecord) -> bool {
+        let is_best = self.sprint.get(&lines).is_none_or(|best| record.time < best.time);
+        if is_best {
+            self.sprint.insert(lines, record);
+        }
+        is_best
+    }
+
+    /// Stores the dig time if it beats the current best. Returns whether it did.
+    pub fn submit_dig(&mut self, lines: u32, time: Duration) -> bool {
+        let is_best = self.dig.get(&lines).is_none_or(|best| time < *best);
+        if is_best {
+            self.dig.insert(lines, time);
+        }
+        is_best
+    }
+
+    /// Counts an attempt at the puzzle, which stays solved once it was.
+    pub fn submit_puzzle(&mut self, id: &str, solved: bool) {
+        let record = self.puzzles.entry(id.to_string()).or_default();
+        record.attempts += 1;
+        record.solved |= solved;
+    }
+
+    pub fn ultra_scores(&self, duration: Duration) -> &[u32] {
+        self.ultra.get(&duration.as_secs()).map_or(&[], |scores| scores.as_slice())
+    }
+
+    /// Adds the score to the high score table. Returns its position if it made it into the table.
+    pub fn submit_ultra(&mut self, duration: Duration, score: u32) -> Option<usize> {
+        submit_high_score(self.ultra.entry(duration.as_secs()).or_default(), score)
+    }
+
+    pub fn marathon_scores(&self, goal: MarathonGoal) -> &[u32] {
+        self.marathon.get(&goal.id()).map_or(&[], |scores| scores.as_slice())
+    }
+
+    /// Adds the score to the high score table. Returns its position if it made it into the table.
+    pub fn submit_marathon(&mut self, goal: MarathonGoal, score: u32) -> Option<usize> {
+        submit_high_score(self.marathon.entry(goal.id()).or_default(), score)
+    }
+}
+
+fn submit_high_score(scores: &mut Vec<u32>, score: u32) -> Option<usize> {
+    let rank = scores.iter().position(|&other| score > other).unwrap_or(scores.len());
+    if rank >= HIGH_SCORE_TABLE_SIZE {
+        return None;
+    }
+    scores.insert(rank, score);
+    scores.truncate(HIGH_SCORE_TABLE_SIZE);
+    Some(rank)
+}
+
+fn parse_scores(text: &str) -> Vec<u32> {
+    text.split(',').filter_map(|score| score.parse().ok()).collect()
+}
+
+fn format_scores(scores: &[u32]) -> String {
+    let scores: Vec<String> = scores.iter().map(|score| score.to_string()).collect();
+    scores.join(",")
+}
+
+fn parse_millis(text: &str) -> Option<Duration> {
+    text.parse().ok().map(Duration::from_millis)
+}
+
+fn records_path() -> Option<PathBuf> {
+    Some(data_dir()?.join("records"))
+}
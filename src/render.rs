@@ -1,12 +1,17 @@
 use std::io::{stdout, Write, Result};
+use std::time::Duration;
 use crossterm::{
     execute,
-    style::{Color, Print, ResetColor, SetForegroundColor},
+    style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor},
     cursor::{Hide, MoveTo, Show},
     terminal::{Clear, ClearType, size as terminal_size, enable_raw_mode, disable_raw_mode},
 };
-use crate::{LockedSquareMatrix, NextShapes};
-use crate::shape::Shape;
+use crate::{NextShapes, Square};
+use crate::board::Board;
+use crate::game::{Game, GameStatus, Step};
+use crate::shape::{Shape, ShapeType, MAX_SHAPE_SIZE};
+use crate::stats::{format_time, Stats};
+use crate::theme::Theme;
 
 const BOARD_WIDTH_IN_TILES: u16 = 10;
 const BOARD_HEIGHT_IN_TILES: u16 = 20;
@@ -14,6 +19,69 @@ const TILE_WIDTH: u16 = 4;
 const TILE_HEIGHT: u16 = 2;
 const BOARD_WIDTH: u16 = BOARD_WIDTH_IN_TILES * TILE_WIDTH;
 const BOARD_HEIGHT: u16 = BOARD_HEIGHT_IN_TILES * TILE_HEIGHT;
+const STATS_PANEL_WIDTH: u16 = 26;
+/// Row, in tiles, at which the held shape is drawn below the next shapes.
+const HOLD_Y: i16 = 16;
+/// Height, in tiles, of the box with the next shapes.
+const NEXT_SHAPES_HEIGHT: u16 = 15;
+/// Width of a mini board with its borders and a space next to it.
+const MINI_BOARD_WIDTH: u16 = BOARD_WIDTH_IN_TILES + 3;
+/// Height of a mini board with its title, borders and a line below it.
+const MINI_BOARD_HEIGHT: u16 = BOARD_HEIGHT_IN_TILES / 2 + 4;
+/// A square of an opener's stack that is still to be filled.
+const TARGET_GLYPH: [&str; 2] = [" ·· ", " ·· "];
+/// Corners drawn around the square under the cursor of the board editor.
+const CURSOR_CORNERS: [(&str, &str); 2] = [("┌", "┐"), ("└", "┘")];
+
+/// Where the board of one player is drawn and how large its tiles are. The panels with the
+/// statistics, next shapes and held shape are placed around the board.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Layout {
+    board_left: u16,
+    board_top: u16,
+    tile_width: u16,
+    tile_height: u16,
+}
+
+impl Layout {
+    /// A single board with full size tiles in the middle of the terminal.
+    pub fn single() -> Layout {
+        let (terminal_width, terminal_height) = terminal_size().unwrap();
+        Layout {
+            board_left: (terminal_width / 2).saturating_sub(BOARD_WIDTH / 2),
+            board_top: terminal_height.saturating_sub(3 + BOARD_HEIGHT),
+            tile_width: TILE_WIDTH,
+            tile_height: TILE_HEIGHT,
+        }
+    }
+
+    /// Board `index` of `count` boards side by side, with half size tiles so they fit next to
+    /// each other. Every board gets an equal share of the terminal's width.
+    pub fn side_by_side(index: u16, count: u16) -> Layout {
+        let (terminal_width, terminal_height) = terminal_size().unwrap();
+        let (tile_width, tile_height) = (TILE_WIDTH / 2, TILE_HEIGHT / 2);
+        let board_width = BOARD_WIDTH_IN_TILES * tile_width;
+        let next_width = 2 + MAX_SHAPE_SIZE as u16 * tile_width;
+        // Stats panel and its borders, the board with its borders and the next shapes box.
+        let group_width = STATS_PANEL_WIDTH + 4 + board_width + 4 + next_width;
+        let share = terminal_width / count;
+        let group_left = share * index + share.saturating_sub(group_width) / 2;
+        Layout {
+            board_left: group_left + STATS_PANEL_WIDTH + 4,
+            board_top: terminal_height.saturating_sub(3 + BOARD_HEIGHT_IN_TILES * tile_height),
+            tile_width,
+            tile_height,
+        }
+    }
+
+    fn board_width(&self) -> u16 {
+        BOARD_WIDTH_IN_TILES * self.tile_width
+    }
+
+    fn board_height(&self) -> u16 {
+        BOARD_HEIGHT_IN_TILES * self.tile_height
+    }
+}
 
 pub fn start() -> Result<()> {
     enable_raw_mode()?;
@@ -22,180 +90,486 @@ pub fn start() -> Result<()> {
 }
 
 pub fn stop() -> Result<()> {
-    execute!(stdout(), ResetColor, Show)?;
+    execute!(stdout(), ResetColor, Clear(ClearType::All), Show)?;
     disable_raw_mode()?;
     Ok(())
 }
 
-pub fn clear_shape(shape: &Shape) -> Result<()> {
-    set_color(Color::Black)?;
-    render_shape_no_color(shape)?;
-    Ok(())
+pub fn clear_screen() -> Result<()> {
+    execute!(stdout(), ResetColor, Clear(ClearType::All))
 }
 
-pub fn render_borders() -> Result<()> {
-    let (top, right, bottom, left) = get_board_bounds();
-
-    let mut stdout = stdout();
-    set_color(Color::White)?;
-    execute!(
-        stdout,
-        MoveTo(left - 1, top - 1),
-        Print("_".repeat(BOARD_WIDTH as usize + 2))
-    )?;
-    for row in top..bottom {
-        execute!(stdout, MoveTo(left - 1, row), Print("|"))?;
-        execute!(stdout, MoveTo(right, row), Print("|"))?;
+pub fn clear_shape(theme: &Theme, layout: &Layout, shape: &Shape) -> Result<()> {
+    for (x, y) in shape.get_occupied_squares() {
+        clear_square(theme, layout, x as u16, y as u16)?;
     }
-    execute!(
-        stdout,
-        MoveTo(left - 1, bottom),
-        Print("‾".repeat(BOARD_WIDTH as usize + 2)))?;
-
-    stdout.flush()?;
     Ok(())
 }
 
-pub fn render_shape(shape: &Shape) -> Result<()> {
-    set_color(shape.color)?;
-    render_shape_no_color(shape)?;
+pub fn render_borders(theme: &Theme, layout: &Layout) -> Result<()> {
+    let (top, right, bottom, left) = get_board_bounds(layout);
+    render_box(theme, top, right, bottom, left)?;
+    fill_background(theme, top, right, bottom, left)
+}
+
+pub fn render_shape(theme: &Theme, layout: &Layout, shape: &Shape) -> Result<()> {
+    for (x, y) in shape.get_occupied_squares() {
+        render_square(theme, layout, Square::Shape(shape.shape_type), x as u16, y as u16)?;
+    }
     Ok(())
 }
 
-pub fn render_locked_squares(locked_squares: &LockedSquareMatrix) -> Result<()> {
-    for x in 0..locked_squares.len() {
-        for y in 0..locked_squares[x].len() {
-            if locked_squares[x][y].is_some() {
-                set_color(locked_squares[x][y].unwrap())?;
-                render_square(x as u16, y as u16)?;
-            }
-        }
+pub fn render_locked_squares(theme: &Theme, layout: &Layout, locked_squares: &Board) -> Result<()> {
+    for (x, y, square) in locked_squares.squares() {
+        render_square(theme, layout, square, x as u16, y as u16)?;
     }
     Ok(())
 }
 
-pub fn clear_locked_squares(locked_squares: &LockedSquareMatrix) -> Result<()> {
-    set_color(Color::Black)?;
-    for x in 0..locked_squares.len() {
-        for y in 0..locked_squares[x].len() {
-            if locked_squares[x][y].is_some() {
-                render_square(x as u16, y as u16)?;
+/// Redraws only the squares that differ between `before` and `after`.
+pub fn render_locked_squares_changes(theme: &Theme, layout: &Layout, before: &Board, after: &Board) -> Result<()> {
+    for y in 0..BOARD_HEIGHT_IN_TILES as usize {
+        for x in 0..BOARD_WIDTH_IN_TILES as usize {
+            if before.get(x, y) == after.get(x, y) {
+                continue;
+            }
+            match after.get(x, y) {
+                Some(square) => render_square(theme, layout, square, x as u16, y as u16)?,
+                None => clear_square(theme, layout, x as u16, y as u16)?,
             }
         }
     }
     Ok(())
 }
 
-pub fn render_next_shapes_borders() -> Result<()> {
-    let (top, right, bottom, left) = get_next_shapes_bounds();
-    let mut stdout = stdout();
-    set_color(Color::White)?;
-    execute!(
-        stdout,
-        MoveTo(left - 1, top - 1),
-        Print("_".repeat((right - left + 2) as usize))
-    )?;
-    for row in top..bottom {
-        execute!(stdout, MoveTo(left - 1, row), Print("|"))?;
-        execute!(stdout, MoveTo(right, row), Print("|"))?;
-    }
-    execute!(
-        stdout,
-        MoveTo(left - 1, bottom),
-        Print("‾".repeat((right - left + 2) as usize))
-    )?;
-    stdout.flush()?;
-    Ok(())
+pub fn render_next_shapes_borders(theme: &Theme, layout: &Layout) -> Result<()> {
+    let (top, right, bottom, left) = get_next_shapes_bounds(layout);
+    render_box(theme, top, right, bottom, left)?;
+    fill_background(theme, top, right, bottom, left)
 }
 
-pub fn clear_next_shapes(next_shapes: &mut NextShapes) -> Result<()> {
+pub fn clear_next_shapes(theme: &Theme, layout: &Layout, next_shapes: &mut NextShapes, preview_count: usize) -> Result<()> {
     let x = BOARD_WIDTH_IN_TILES + 1;
     let mut y = 1;
 
-    for s in next_shapes {
+    for s in next_shapes.iter_mut().take(preview_count) {
         s.x = x as i16;
         s.y = y as i16;
-        clear_shape(s)?;
+        clear_shape(theme, layout, s)?;
         y += 5;
     }
 
     Ok(())
 }
 
-pub fn render_next_shapes(next_shapes: &mut NextShapes) -> Result<()> {
+pub fn render_next_shapes(theme: &Theme, layout: &Layout, next_shapes: &mut NextShapes, preview_count: usize) -> Result<()> {
     let x = BOARD_WIDTH_IN_TILES + 1;
     let mut y = 1;
 
-    for s in next_shapes {
+    for s in next_shapes.iter_mut().take(preview_count) {
         s.x = x as i16;
         s.y = y as i16;
-        render_shape(s)?;
+        render_shape(theme, layout, s)?;
         y += 5;
     }
 
     Ok(())
 }
 
-pub fn render_score(score: u32) -> Result<()> {
-    set_color(Color::Green)?;
-    let (top, right, _, _) = get_board_bounds();
-    let x = right + 3;
-    let y = top + 31;
+pub fn render_hold_borders(theme: &Theme, layout: &Layout) -> Result<()> {
+    let (top, right, bottom, left) = get_hold_bounds(layout);
+    render_box(theme, top, right, bottom, left)?;
+    fill_background(theme, top, right, bottom, left)
+}
+
+pub fn clear_held_shape(theme: &Theme, layout: &Layout, mut held_shape: Shape) -> Result<()> {
+    held_shape.x = BOARD_WIDTH_IN_TILES as i16 + 1;
+    held_shape.y = HOLD_Y;
+    clear_shape(theme, layout, &held_shape)
+}
+
+pub fn render_held_shape(theme: &Theme, layout: &Layout, mut held_shape: Shape) -> Result<()> {
+    held_shape.x = BOARD_WIDTH_IN_TILES as i16 + 1;
+    held_shape.y = HOLD_Y;
+    render_shape(theme, layout, &held_shape)
+}
+
+pub fn render_stats_borders(theme: &Theme, layout: &Layout) -> Result<()> {
+    let Some((top, right, bottom, left)) = get_stats_bounds(layout) else {
+        return Ok(());
+    };
+    render_box(theme, top, right, bottom, left)?;
+    fill_background(theme, top, right, bottom, left)
+}
+
+/// Draws the side panel: `mode_lines` coming from the game mode followed by the statistics.
+pub fn render_stats(theme: &Theme, layout: &Layout, mode_lines: &[String], stats: &Stats, score: u32) -> Result<()> {
+    let mut lines = mode_lines.to_vec();
+    lines.extend(stats_lines(stats, score));
+    render_panel(theme, layout, &lines)
+}
+
+/// Draws the lines in the side panel, where the statistics go during games.
+pub fn render_panel(theme: &Theme, layout: &Layout, lines: &[String]) -> Result<()> {
+    let Some((top, right, bottom, left)) = get_stats_bounds(layout) else {
+        return Ok(());
+    };
+    let width = (right - left - 2) as usize;
     let mut stdout = stdout();
+    set_colors(theme.text_color, theme.background)?;
+    // Smaller layouts only show the lines that fit.
+    for (i, line) in lines.iter().take((bottom - top - 1) as usize).enumerate() {
+        execute!(stdout, MoveTo(left + 1, top + 1 + i as u16), Print(format!("{:<width$}", line)))?;
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Draws the board of a game that is about to start with the boxes around it.
+pub fn render_game(theme: &Theme, layout: &Layout, game: &mut Game) -> Result<()> {
+    render_borders(theme, layout)?;
+    render_next_shapes_borders(theme, layout)?;
+    render_stats_borders(theme, layout)?;
+    if game.ruleset.hold {
+        render_hold_borders(theme, layout)?;
+    }
+    // Puzzles and positions can start with a held shape.
+    if let Some(shape_type) = game.held_shape {
+        render_held_shape(theme, layout, Shape::new(game.rotation_system(), shape_type, 0, 0))?;
+    }
+    render_locked_squares(theme, layout, &game.locked_squares)?;
+    let preview_count = game.preview_count();
+    render_next_shapes(theme, layout, &mut game.next_shapes, preview_count)
+}
+
+/// Redraws what a single update of the game changed, `before` is the game from before the update.
+/// `hud_lines` are shown above the statistics.
+pub fn render_game_changes(theme: &Theme, layout: &Layout, before: &mut Game, game: &mut Game, step: Step,
+        hud_lines: &[String]) -> Result<()> {
+    if before.falling_shape != game.falling_shape {
+        clear_shape(theme, layout, &before.falling_shape)?;
+    }
+
+    if step.locked || before.locked_squares != game.locked_squares {
+        render_locked_squares_changes(theme, layout, &before.locked_squares, &game.locked_squares)?;
+    }
+
+    if game.status == GameStatus::Playing {
+        render_shape(theme, layout, &game.falling_shape)?;
+    }
+
+    render_stats(theme, layout, hud_lines, &game.stats, game.score)?;
+
+    if step.locked || before.held_shape != game.held_shape {
+        let (before_count, preview_count) = (before.preview_count(), game.preview_count());
+        clear_next_shapes(theme, layout, &mut before.next_shapes, before_count)?;
+        render_next_shapes(theme, layout, &mut game.next_shapes, preview_count)?;
+    }
+
+    if before.held_shape != game.held_shape {
+        if let Some(shape_type) = before.held_shape {
+            clear_held_shape(theme, layout, Shape::new(game.rotation_system(), shape_type, 0, 0))?;
+        }
+        if let Some(shape_type) = game.held_shape {
+            render_held_shape(theme, layout, Shape::new(game.rotation_system(), shape_type, 0, 0))?;
+        }
+    }
+    Ok(())
+}
+
+/// Marks the squares of an opener's stack that are still empty in the colour of their piece,
+/// leaving out those the falling shape covers.
+pub fn render_template(theme: &Theme, layout: &Layout, template: &Board, board: &Board, falling_shape: &Shape) -> Result<()> {
+    let covered = falling_shape.get_occupied_squares();
+    for (x, y, square) in template.squares() {
+        if board.get(x, y).is_some() || covered.contains(&(x as i16, y as i16)) {
+            continue;
+        }
+        set_colors(theme.square_color(square), theme.background)?;
+        print_square(layout, x as u16, y as u16, TARGET_GLYPH)?;
+    }
+    Ok(())
+}
+
+/// Draws the corners of the board editor's cursor around the square at `x`, `y`, in the colour of
+/// the `brush` it paints with.
+pub fn render_cursor(theme: &Theme, layout: &Layout, brush: Square, x: u16, y: u16) -> Result<()> {
+    let (top, _, _, left) = get_board_bounds(layout);
+    let square_x = x * layout.tile_width + left;
+    let square_y = y * layout.tile_height + top;
+    let mut stdout = stdout();
+    set_colors(theme.square_color(brush), theme.background)?;
+    for (i, (left_corner, right_corner)) in CURSOR_CORNERS.iter().enumerate().take(layout.tile_height as usize) {
+        let row = square_y + i as u16;
+        execute!(stdout, MoveTo(square_x, row), Print(left_corner))?;
+        execute!(stdout, MoveTo(square_x + layout.tile_width - 1, row), Print(right_corner))?;
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Locks the shapes one by one on the board, clearing the rows they fill, waiting `delay` after
+/// every change. Returns the board the shapes leave behind.
+pub fn render_locks(theme: &Theme, layout: &Layout, board: &Board, shapes: &[Shape], delay: Duration) -> Result<Board> {
+    let mut board = *board;
+    for shape in shapes {
+        let mut locked = board;
+        locked.lock(shape);
+        render_locked_squares_changes(theme, layout, &board, &locked)?;
+        std::thread::sleep(delay);
+        board = locked;
+        locked.delete_full_rows();
+        render_locked_squares_changes(theme, layout, &board, &locked)?;
+        board = locked;
+    }
+    Ok(board)
+}
+
+/// Draws a bar left of the board that rises with the garbage rows waiting to come in, solid for
+/// the `arrived` rows that come in with the next lock and shaded for those still delayed.
+pub fn render_garbage_meter(theme: &Theme, layout: &Layout, rows: u32, arrived: u32) -> Result<()> {
+    let (top, _, bottom, left) = get_board_bounds(layout);
+    let height = |rows: u32| (rows * layout.tile_height as u32).min(layout.board_height() as u32) as u16;
+    let (filled, solid) = (height(rows), height(arrived));
+    let mut stdout = stdout();
+    set_colors(theme.square_color(Square::Garbage), theme.background)?;
+    for row in top..bottom {
+        let glyph = if row >= bottom - solid { "█" } else if row >= bottom - filled { "▒" } else { " " };
+        execute!(stdout, MoveTo(left.saturating_sub(2), row), Print(glyph))?;
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Where mini board `index` goes, filling the right half of the terminal row by row, or `None`
+/// if it does not fit.
+pub fn mini_board_position(index: usize) -> Option<(u16, u16)> {
+    let (terminal_width, terminal_height) = terminal_size().ok()?;
+    let columns = (terminal_width - terminal_width / 2) / MINI_BOARD_WIDTH;
+    let rows = terminal_height / MINI_BOARD_HEIGHT;
+    if columns == 0 || index >= (columns * rows) as usize {
+        return None;
+    }
+    let (column, row) = (index as u16 % columns, index as u16 / columns);
+    Some((terminal_width / 2 + column * MINI_BOARD_WIDTH, row * MINI_BOARD_HEIGHT))
+}
+
+/// Draws a board at a character per two squares, one above the other, with its borders and a
+/// title above. Only the characters that differ from `before` are drawn, or all of them and the
+/// borders without it.
+pub fn render_mini_board(theme: &Theme, (left, top): (u16, u16), title: &str, before: Option<&Board>, board: &Board) -> Result<()> {
+    let (board_top, board_left) = (top + 2, left + 1);
+    if before.is_none() {
+        render_box(theme, board_top, board_left + BOARD_WIDTH_IN_TILES, board_top + BOARD_HEIGHT_IN_TILES / 2, board_left)?;
+    }
+    let mut stdout = stdout();
+    set_colors(theme.text_color, theme.background)?;
+    let title: String = title.chars().take(MINI_BOARD_WIDTH as usize - 1).collect();
+    execute!(stdout, MoveTo(left, top), Print(format!("{:<width$}", title, width = MINI_BOARD_WIDTH as usize - 1)))?;
+    let color = |square: Option<Square>| square.map_or(theme.background, |square| theme.square_color(square));
+    for y in (0..BOARD_HEIGHT_IN_TILES as usize).step_by(2) {
+        for x in 0..BOARD_WIDTH_IN_TILES as usize {
+            let squares = (board.get(x, y), board.get(x, y + 1));
+            if before.is_some_and(|before| (before.get(x, y), before.get(x, y + 1)) == squares) {
+                continue;
+            }
+            set_colors(color(squares.0), color(squares.1))?;
+            execute!(stdout, MoveTo(board_left + x as u16, board_top + y as u16 / 2), Print("▀"))?;
+        }
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Draws the end of game summary over the board. `mode_lines` are shown above the statistics.
+pub fn render_game_over(theme: &Theme, layout: &Layout, title: &str, mode_lines: &[String], stats: &Stats, score: u32) -> Result<()> {
+    let mut lines = vec![title.to_string(), String::new()];
+    if !mode_lines.is_empty() {
+        lines.extend_from_slice(mode_lines);
+        lines.push(String::new());
+    }
+    lines.extend(stats_lines(stats, score));
+    lines.push(String::new());
+    lines.push("Press any key".to_string());
+    render_popup(theme, layout, &lines)
+}
+
+/// Draws the start menu. `items` are the lines of the menu, the one at `selected`
+/// is highlighted. A row of pieces is shown below as a preview of the theme.
+pub fn render_menu(theme: &Theme, items: &[String], selected: usize) -> Result<()> {
+    clear_screen()?;
+    let (terminal_width, terminal_height) = terminal_size()?;
+    let menu_width = items.iter().map(|item| item.chars().count()).max().unwrap_or(0) as u16 + 4;
+    let left = (terminal_width / 2).saturating_sub(menu_width / 2);
+    let top = (terminal_height / 2).saturating_sub(items.len() as u16 + 3);
+    let mut stdout = stdout();
+
+    set_colors(theme.text_color, theme.background)?;
+    execute!(stdout, MoveTo(left, top), Print("TETRIS"))?;
+    for (i, item) in items.iter().enumerate() {
+        let marker = if i == selected { "> " } else { "  " };
+        execute!(stdout, MoveTo(left, top + 2 + i as u16), Print(marker), Print(item))?;
+    }
+
+    let preview_top = top + 3 + items.len() as u16;
+    let preview_left = (terminal_width / 2).saturating_sub(ShapeType::ALL.len() as u16 * (TILE_WIDTH + 1) / 2);
+    for (i, shape_type) in ShapeType::ALL.into_iter().enumerate() {
+        let glyph = theme.piece_glyph(shape_type);
+        set_colors(theme.piece_color(shape_type), theme.background)?;
+        let x = preview_left + i as u16 * (TILE_WIDTH + 1);
+        execute!(stdout, MoveTo(x, preview_top), Print(glyph[0]))?;
+        execute!(stdout, MoveTo(x, preview_top + 1), Print(glyph[1]))?;
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+fn stats_lines(stats: &Stats, score: u32) -> Vec<String> {
+    let mut lines = vec![
+        format!("Score:  {}", score),
+        format!("Lines:  {}", stats.lines),
+        format!("Level:  {}", stats.level()),
+        format!("Time:   {}", format_time(stats.elapsed())),
+        String::new(),
+        format!("Pieces: {}", stats.pieces),
+        format!("PPS:    {:.2}", stats.pieces_per_second()),
+        format!("KPP:    {:.2}", stats.keys_per_piece()),
+        String::new(),
+    ];
+    // Larger piece sets are listed in two columns to fit the panel.
+    let columns = if stats.pieces_by_type.len() > 7 { 2 } else { 1 };
+    for row in stats.pieces_by_type.chunks(columns) {
+        let counts: Vec<String> = row.iter().map(|(name, count)| format!("{:<12}", format!("{}: {}", name, count))).collect();
+        lines.push(counts.concat().trim_end().to_string());
+    }
+    lines.push(String::new());
+    for (name, count) in ["Singles", "Doubles", "Triples", "Tetrises"].iter().zip(stats.clears) {
+        lines.push(format!("{:<9}{}", format!("{}:", name), count));
+    }
+    lines
+}
+
+/// Draws a box with the given lines centred over the board.
+pub fn render_popup(theme: &Theme, layout: &Layout, lines: &[String]) -> Result<()> {
+    let (board_top, board_right, board_bottom, board_left) = get_board_bounds(layout);
+    let width = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0) as u16 + 4;
+    let height = lines.len() as u16 + 2;
+    let left = (board_left + board_right) / 2 - width / 2;
+    let top = (board_top + board_bottom) / 2 - height / 2;
+    let (right, bottom) = (left + width, top + height);
+    render_box(theme, top, right, bottom, left)?;
+    fill_background(theme, top, right, bottom, left)?;
+    let mut stdout = stdout();
+    set_colors(theme.text_color, theme.background)?;
+    for (i, line) in lines.iter().enumerate() {
+        execute!(stdout, MoveTo(left + 2, top + 1 + i as u16), Print(line))?;
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+fn render_box(theme: &Theme, top: u16, right: u16, bottom: u16, left: u16) -> Result<()> {
+    let border = theme.border;
+    let horizontal = border.horizontal.to_string().repeat((right - left) as usize);
+    let mut stdout = stdout();
+    set_colors(theme.border_color, theme.background)?;
+    execute!(
+        stdout,
+        MoveTo(left.saturating_sub(1), top.saturating_sub(1)),
+        Print(border.top_left),
+        Print(&horizontal),
+        Print(border.top_right)
+    )?;
+    for row in top..bottom {
+        execute!(stdout, MoveTo(left.saturating_sub(1), row), Print(border.vertical))?;
+        execute!(stdout, MoveTo(right, row), Print(border.vertical))?;
+    }
     execute!(
         stdout,
-        MoveTo(x, y),
-        Print("Score: "),
-        Print(score.to_string())
+        MoveTo(left.saturating_sub(1), bottom),
+        Print(border.bottom_left),
+        Print(&horizontal),
+        Print(border.bottom_right)
     )?;
+    stdout.flush()?;
     Ok(())
 }
 
-fn render_shape_no_color(shape: &Shape) -> Result<()> {
-    for dx in 0..4 {
-        for dy in 0..4 {
-            if shape.get_matrix()[dx][dy] == 1 {
-                render_square((shape.x + dx as i16) as u16, (shape.y + dy as i16) as u16)?;
-            }
-        }
+fn fill_background(theme: &Theme, top: u16, right: u16, bottom: u16, left: u16) -> Result<()> {
+    let blank = " ".repeat((right - left) as usize);
+    let mut stdout = stdout();
+    set_colors(theme.text_color, theme.background)?;
+    for row in top..bottom {
+        execute!(stdout, MoveTo(left, row), Print(&blank))?;
     }
+    stdout.flush()?;
     Ok(())
 }
 
-fn get_board_bounds() -> (u16, u16, u16, u16) {
-    let (terminal_width, terminal_height) = terminal_size().unwrap();
-    let bottom = terminal_height - 3;
-    let top = bottom - BOARD_HEIGHT;
-    let left = terminal_width / 2 - BOARD_WIDTH / 2;
-    let right = left + BOARD_WIDTH;
-    (top, right, bottom, left)
+fn get_board_bounds(layout: &Layout) -> (u16, u16, u16, u16) {
+    let top = layout.board_top;
+    let left = layout.board_left;
+    (top, left + layout.board_width(), top + layout.board_height(), left)
 }
 
-fn get_next_shapes_bounds() -> (u16, u16, u16, u16) {
-    let (board_top, board_right, _, _) = get_board_bounds();
+fn get_next_shapes_bounds(layout: &Layout) -> (u16, u16, u16, u16) {
+    let (board_top, board_right, _, _) = get_board_bounds(layout);
     let top = board_top;
-    let bottom = top + 30;
+    let bottom = top + NEXT_SHAPES_HEIGHT * layout.tile_height;
     let left = board_right + 3;
-    let right = left + 10;
+    let right = left + 2 + MAX_SHAPE_SIZE as u16 * layout.tile_width;
     (top, right, bottom, left)
 }
 
-fn render_square(x: u16, y: u16) -> Result<()> {
-    let (top, _, _, left) = get_board_bounds();
-    let board_x = x * TILE_WIDTH + left;
-    let board_y = y * TILE_HEIGHT + top;
+fn get_hold_bounds(layout: &Layout) -> (u16, u16, u16, u16) {
+    let (_, next_right, next_bottom, next_left) = get_next_shapes_bounds(layout);
+    let (_, _, board_bottom, _) = get_board_bounds(layout);
+    (next_bottom + layout.tile_height, next_right, board_bottom, next_left)
+}
+
+/// The panel left of the board, or `None` when the terminal is too narrow to fit it.
+fn get_stats_bounds(layout: &Layout) -> Option<(u16, u16, u16, u16)> {
+    let (board_top, _, board_bottom, board_left) = get_board_bounds(layout);
+    let top = board_top;
+    let bottom = board_bottom;
+    let right = board_left.checked_sub(3)?;
+    // The box is drawn one column left of the panel.
+    let left = right.checked_sub(STATS_PANEL_WIDTH + 1)? + 1;
+    Some((top, right, bottom, left))
+}
+
+fn render_square(theme: &Theme, layout: &Layout, square: Square, x: u16, y: u16) -> Result<()> {
+    set_colors(theme.square_color(square), theme.background)?;
+    print_square(layout, x, y, theme.square_glyph(square))
+}
+
+fn clear_square(theme: &Theme, layout: &Layout, x: u16, y: u16) -> Result<()> {
+    set_colors(theme.background, theme.background)?;
+    print_square(layout, x, y, ["    ", "    "])
+}
+
+fn print_square(layout: &Layout, x: u16, y: u16, glyph: [&str; 2]) -> Result<()> {
+    let (top, _, _, left) = get_board_bounds(layout);
+    let board_x = x * layout.tile_width + left;
+    let board_y = y * layout.tile_height + top;
     let mut stdout = stdout();
+    if layout.tile_height == 1 {
+        // Half size tiles keep the outer characters of the glyph's top row, e.g. `[]` of `[##]`.
+        let mut chars = glyph[0].chars();
+        let ends: String = chars.next().into_iter().chain(chars.next_back()).collect();
+        return execute!(stdout, MoveTo(board_x, board_y), Print(ends));
+    }
     execute!(
         stdout,
         MoveTo(board_x, board_y),
-        Print("░░░░"))?;
+        Print(glyph[0]))?;
     execute!(
         stdout,
         MoveTo(board_x, board_y + 1),
-        Print("░░░░"))?;
+        Print(glyph[1]))?;
     Ok(())
 }
 
-fn set_color(color: Color) -> Result<()> {
-    execute!(stdout(), SetForegroundColor(color))
+fn set_colors(foreground: Color, background: Color) -> Result<()> {
+    execute!(stdout(), SetForegroundColor(foreground), SetBackgroundColor(background))
 }
@@ -0,0 +1,603 @@
+use crate::{BOARD_HEIGHT_IN_TILES, BOARD_WIDTH_IN_TILES};
+use crate::board::Board;
+use crate::choice::Choice;
+use crate::shape::{Cell, Shape, ShapeCells, ShapeType};
+
+/// The four cells of a tetromino in each rotation state.
+type TetrominoStates = [[Cell; 4]; 4];
+
+/// A kick is an offset tried when a rotated shape does not fit where it is.
+pub type Kick = (i16, i16);
+
+/// Describes how shapes look in each rotation state and how they move when rotated.
+///
+/// Rotation states are numbered clockwise from 0 to 3, each is a list of cells inside the
+/// shape's box. Kicks use board coordinates, so a positive `y` moves the shape down.
+pub trait RotationSystem {
+    /// The four rotation states of `shape_type`, which must be one the system deals: the
+    /// tetrominoes for the built-in systems and the set's own pieces for a piece set. Shape types
+    /// read from files or the network are checked against the rule set before they get here.
+    fn states(&self, shape_type: ShapeType) -> [ShapeCells; 4];
+
+    /// Rotation state a new shape starts in.
+    fn spawn_state(&self, _shape_type: ShapeType) -> u8 {
+        0
+    }
+
+    /// Board position of the top left corner of a new shape's box. The box starts at the top
+    /// of the board with the spawn state centred, rounding to the left, so SRS pieces spawn in
+    /// the guideline columns and leave room above the flat side to rotate.
+    fn spawn_position(&self, shape_type: ShapeType) -> (i16, i16) {
+        let cells = self.states(shape_type)[self.spawn_state(shape_type) as usize];
+        let min_x = cells.iter().map(|&(x, _)| x).min().unwrap_or(0) as i16;
+        let max_x = cells.iter().map(|&(x, _)| x).max().unwrap_or(0) as i16;
+        let width = max_x - min_x + 1;
+        ((BOARD_WIDTH_IN_TILES as i16 - width) / 2 - min_x, 0)
+    }
+
+    /// Offsets tried in order when rotating from state `from` to state `to`.
+    /// The first one that fits is used, if none fits the rotation fails.
+    fn kicks(&self, shape_type: ShapeType, from: u8, to: u8) -> Vec<Kick>;
+
+    /// Whether the kicks after the first may be tried for the rotated `shape`, which does not
+    /// fit on `board` where it is.
+    fn can_kick(&self, _shape: &Shape, _board: &Board) -> bool {
+        true
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RotationSystemName {
+    Srs,
+    Ars,
+    Nrs,
+}
+
+impl Choice for RotationSystemName {
+    const ALL: &'static [RotationSystemName] = &[RotationSystemName::Srs, RotationSystemName::Ars, RotationSystemName::Nrs];
+
+    fn id(self) -> &'static str {
+        match self {
+            RotationSystemName::Srs => "srs",
+            RotationSystemName::Ars => "ars",
+            RotationSystemName::Nrs => "nrs",
+        }
+    }
+}
+
+impl RotationSystemName {
+    pub fn label(self) -> &'static str {
+        match self {
+            RotationSystemName::Srs => "SRS",
+            RotationSystemName::Ars => "Arika",
+            RotationSystemName::Nrs => "Nintendo",
+        }
+    }
+
+    pub fn system(self) -> &'static dyn RotationSystem {
+        match self {
+            RotationSystemName::Srs => &Srs,
+            RotationSystemName::Ars => &Ars,
+            RotationSystemName::Nrs => &Nrs,
+        }
+    }
+}
+
+/// The Super Rotation System used by guideline games.
+pub struct Srs;
+
+/// The Arika Rotation System from the Tetris The Grand Master series.
+pub struct Ars;
+
+/// The rotation of the NES and Game Boy games, without any kicks.
+pub struct Nrs;
+
+impl RotationSystem for Srs {
+    fn states(&self, shape_type: ShapeType) -> [ShapeCells; 4] {
+        srs_states(shape_type).map(|cells| ShapeCells::new(&cells))
+    }
+
+    fn kicks(&self, shape_type: ShapeType, from: u8, to: u8) -> Vec<Kick> {
+        let table = match shape_type {
+            ShapeType::O => return vec![(0, 0)],
+            ShapeType::I => &SRS_I_KICKS,
+            _ => &SRS_JLSTZ_KICKS,
+        };
+        let clockwise = (from + 1) % 4 == to;
+        let index = if clockwise { from as usize * 2 } else { (from as usize * 2 + 7) % 8 };
+        // The tables are written with `y` pointing up, as in the guideline.
+        table[index].iter().map(|&(x, y)| (x, -y)).collect()
+    }
+}
+
+impl RotationSystem for Ars {
+    fn states(&self, shape_type: ShapeType) -> [ShapeCells; 4] {
+        ars_states(shape_type).map(|cells| ShapeCells::new(&cells))
+    }
+
+    fn kicks(&self, shape_type: ShapeType, _from: u8, _to: u8) -> Vec<Kick> {
+        match shape_type {
+            ShapeType::I | ShapeType::O => vec![(0, 0)],
+            _ => vec![(0, 0), (1, 0), (-1, 0)],
+        }
+    }
+
+    /// The centre column rule of TGM: J, L and T do not kick when the first of their cells
+    /// that is blocked, in reading order, is in the centre column of their box.
+    fn can_kick(&self, shape: &Shape, board: &Board) -> bool {
+        if !matches!(shape.shape_type, ShapeType::J | ShapeType::L | ShapeType::T) {
+            return true;
+        }
+        let mut cells: Vec<Cell> = shape.get_cells().iter().copied().collect();
+        cells.sort_by_key(|&(x, y)| (y, x));
+        let first_blocked = cells.into_iter().find(|&(x, y)| {
+            let (x, y) = (shape.x + x as i16, shape.y + y as i16);
+            if x < 0 || x >= BOARD_WIDTH_IN_TILES as i16 || y < 0 || y >= BOARD_HEIGHT_IN_TILES as i16 {
+                return true;
+            }
+            board.get(x as usize, y as usize).is_some()
+        });
+        !matches!(first_blocked, Some((1, _)))
+    }
+}
+
+impl RotationSystem for Nrs {
+    fn states(&self, shape_type: ShapeType) -> [ShapeCells; 4] {
+        nrs_states(shape_type).map(|cells| ShapeCells::new(&cells))
+    }
+
+    fn kicks(&self, _shape_type: ShapeType, _from: u8, _to: u8) -> Vec<Kick> {
+        vec![(0, 0)]
+    }
+}
+
+// SRS kick tables, rows ordered 0->R, R->0, R->2, 2->R, 2->L, L->2, L->0, 0->L.
+const SRS_JLSTZ_KICKS: [[Kick; 5]; 8] = [
+    [(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)],
+    [(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)],
+    [(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)],
+    [(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)],
+    [(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)],
+    [(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)],
+    [(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)],
+    [(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)],
+];
+
+const SRS_I_KICKS: [[Kick; 5]; 8] = [
+    [(0, 0), (-2, 0), (1, 0), (-2, -1), (1, 2)],
+    [(0, 0), (2, 0), (-1, 0), (2, 1), (-1, -2)],
+    [(0, 0), (-1, 0), (2, 0), (-1, 2), (2, -1)],
+    [(0, 0), (1, 0), (-2, 0), (1, -2), (-2, 1)],
+    [(0, 0), (2, 0), (-1, 0), (2, 1), (-1, -2)],
+    [(0, 0), (-2, 0), (1, 0), (-2, -1), (1, 2)],
+    [(0, 0), (1, 0), (-2, 0), (1, -2), (-2, 1)],
+    [(0, 0), (-1, 0), (2, 0), (-1, 2), (2, -1)],
+];
+
+// SRS states as read from the guideline, the O sits in the middle of a 4 wide box.
+const SRS_I: TetrominoStates = [
+    [(0, 1), (1, 1), (2, 1), (3, 1)],
+    [(2, 0), (2, 1), (2, 2), (2, 3)],
+    [(0, 2), (1, 2), (2, 2), (3, 2)],
+    [(1, 0), (1, 1), (1, 2), (1, 3)],
+];
+
+const SRS_J: TetrominoStates = [
+    [(0, 0), (0, 1), (1, 1), (2, 1)],
+    [(1, 0), (2, 0), (1, 1), (1, 2)],
+    [(0, 1), (1, 1), (2, 1), (2, 2)],
+    [(1, 0), (1, 1), (0, 2), (1, 2)],
+];
+
+const SRS_L: TetrominoStates = [
+    [(2, 0), (0, 1), (1, 1), (2, 1)],
+    [(1, 0), (1, 1), (1, 2), (2, 2)],
+    [(0, 1), (1, 1), (2, 1), (0, 2)],
+    [(0, 0), (1, 0), (1, 1), (1, 2)],
+];
+
+const SRS_O: TetrominoStates = [
+    [(1, 0), (2, 0), (1, 1), (2, 1)],
+    [(1, 0), (2, 0), (1, 1), (2, 1)],
+    [(1, 0), (2, 0), (1, 1), (2, 1)],
+    [(1, 0), (2, 0), (1, 1), (2, 1)],
+];
+
+const SRS_S: TetrominoStates = [
+    [(1, 0), (2, 0), (0, 1), (1, 1)],
+    [(1, 0), (1, 1), (2, 1), (2, 2)],
+    [(1, 1), (2, 1), (0, 2), (1, 2)],
+    [(0, 0), (0, 1), (1, 1), (1, 2)],
+];
+
+const SRS_T: TetrominoStates = [
+    [(1, 0), (0, 1), (1, 1), (2, 1)],
+    [(1, 0), (1, 1), (2, 1), (1, 2)],
+    [(0, 1), (1, 1), (2, 1), (1, 2)],
+    [(1, 0), (0, 1), (1, 1), (1, 2)],
+];
+
+const SRS_Z: TetrominoStates = [
+    [(0, 0), (1, 0), (1, 1), (2, 1)],
+    [(2, 0), (1, 1), (2, 1), (1, 2)],
+    [(0, 1), (1, 1), (1, 2), (2, 2)],
+    [(1, 0), (0, 1), (1, 1), (0, 2)],
+];
+
+fn srs_states(shape_type: ShapeType) -> TetrominoStates {
+    match shape_type {
+        ShapeType::I => SRS_I,
+        ShapeType::J => SRS_J,
+        ShapeType::L => SRS_L,
+        ShapeType::O => SRS_O,
+        ShapeType::S => SRS_S,
+        ShapeType::T => SRS_T,
+        ShapeType::Z => SRS_Z,
+        ShapeType::Custom(_) => unreachable!("custom pieces are rotated by their piece set"),
+    }
+}
+
+// ARS states: pieces spawn pointing down and rest on the bottom of their box.
+const ARS_I: TetrominoStates = [
+    [(0, 1), (1, 1), (2, 1), (3, 1)],
+    [(2, 0), (2, 1), (2, 2), (2, 3)],
+    [(0, 1), (1, 1), (2, 1), (3, 1)],
+    [(2, 0), (2, 1), (2, 2), (2, 3)],
+];
+
+const ARS_J: TetrominoStates = [
+    [(0, 1), (1, 1), (2, 1), (2, 2)],
+    [(1, 0), (1, 1), (0, 2), (1, 2)],
+    [(0, 1), (0, 2), (1, 2), (2, 2)],
+    [(1, 0), (2, 0), (1, 1), (1, 2)],
+];
+
+const ARS_L: TetrominoStates = [
+    [(0, 1), (1, 1), (2, 1), (0, 2)],
+    [(0, 0), (1, 0), (1, 1), (1, 2)],
+    [(2, 1), (0, 2), (1, 2), (2, 2)],
+    [(1, 0), (1, 1), (1, 2), (2, 2)],
+];
+
+const ARS_O: TetrominoStates = [
+    [(1, 1), (2, 1), (1, 2), (2, 2)],
+    [(1, 1), (2, 1), (1, 2), (2, 2)],
+    [(1, 1), (2, 1), (1, 2), (2, 2)],
+    [(1, 1), (2, 1), (1, 2), (2, 2)],
+];
+
+const ARS_S: TetrominoStates = [
+    [(1, 1), (2, 1), (0, 2), (1, 2)],
+    [(0, 0), (0, 1), (1, 1), (1, 2)],
+    [(1, 1), (2, 1), (0, 2), (1, 2)],
+    [(0, 0), (0, 1), (1, 1), (1, 2)],
+];
+
+const ARS_T: TetrominoStates = [
+    [(0, 1), (1, 1), (2, 1), (1, 2)],
+    [(1, 0), (0, 1), (1, 1), (1, 2)],
+    [(1, 1), (0, 2), (1, 2), (2, 2)],
+    [(1, 0), (1, 1), (2, 1), (1, 2)],
+];
+
+const ARS_Z: TetrominoStates = [
+    [(0, 1), (1, 1), (1, 2), (2, 2)],
+    [(2, 0), (1, 1), (2, 1), (1, 2)],
+    [(0, 1), (1, 1), (1, 2), (2, 2)],
+    [(2, 0), (1, 1), (2, 1), (1, 2)],
+];
+
+fn ars_states(shape_type: ShapeType) -> TetrominoStates {
+    match shape_type {
+        ShapeType::I => ARS_I,
+        ShapeType::J => ARS_J,
+        ShapeType::L => ARS_L,
+        ShapeType::O => ARS_O,
+        ShapeType::S => ARS_S,
+        ShapeType::T => ARS_T,
+        ShapeType::Z => ARS_Z,
+        ShapeType::Custom(_) => unreachable!("custom pieces are rotated by their piece set"),
+    }
+}
+
+// NRS states: like ARS, but S, Z and I rotate around a different centre.
+const NRS_I: TetrominoStates = [
+    [(0, 2), (1, 2), (2, 2), (3, 2)],
+    [(2, 0), (2, 1), (2, 2), (2, 3)],
+    [(0, 2), (1, 2), (2, 2), (3, 2)],
+    [(2, 0), (2, 1), (2, 2), (2, 3)],
+];
+
+const NRS_J: TetrominoStates = [
+    [(0, 1), (1, 1), (2, 1), (2, 2)],
+    [(1, 0), (1, 1), (0, 2), (1, 2)],
+    [(0, 0), (0, 1), (1, 1), (2, 1)],
+    [(1, 0), (2, 0), (1, 1), (1, 2)],
+];
+
+const NRS_L: TetrominoStates = [
+    [(0, 1), (1, 1), (2, 1), (0, 2)],
+    [(0, 0), (1, 0), (1, 1), (1, 2)],
+    [(2, 0), (0, 1), (1, 1), (2, 1)],
+    [(1, 0), (1, 1), (1, 2), (2, 2)],
+];
+
+const NRS_O: TetrominoStates = [
+    [(1, 1), (2, 1), (1, 2), (2, 2)],
+    [(1, 1), (2, 1), (1, 2), (2, 2)],
+    [(1, 1), (2, 1), (1, 2), (2, 2)],
+    [(1, 1), (2, 1), (1, 2), (2, 2)],
+];
+
+const NRS_S: TetrominoStates = [
+    [(1, 1), (2, 1), (0, 2), (1, 2)],
+    [(1, 0), (1, 1), (2, 1), (2, 2)],
+    [(1, 1), (2, 1), (0, 2), (1, 2)],
+    [(1, 0), (1, 1), (2, 1), (2, 2)],
+];
+
+const NRS_T: TetrominoStates = [
+    [(0, 1), (1, 1), (2, 1), (1, 2)],
+    [(1, 0), (0, 1), (1, 1), (1, 2)],
+    [(1, 0), (0, 1), (1, 1), (2, 1)],
+    [(1, 0), (1, 1), (2, 1), (1, 2)],
+];
+
+const NRS_Z: TetrominoStates = [
+    [(0, 1), (1, 1), (1, 2), (2, 2)],
+    [(2, 0), (1, 1), (2, 1), (1, 2)],
+    [(0, 1), (1, 1), (1, 2), (2, 2)],
+    [(2, 0), (1, 1), (2, 1), (1, 2)],
+];
+
+fn nrs_states(shape_type: ShapeType) -> TetrominoStates {
+    match shape_type {
+        ShapeType::I => NRS_I,
+        ShapeType::J => NRS_J,
+        ShapeType::L => NRS_L,
+        ShapeType::O => NRS_O,
+        ShapeType::S => NRS_S,
+        ShapeType::T => NRS_T,
+        ShapeType::Z => NRS_Z,
+        ShapeType::Custom(_) => unreachable!("custom pieces are rotated by their piece set"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Square;
+
+    /// Draws the four rotation states side by side, one 4×4 box each.
+    fn draw_states(rotation_system: &dyn RotationSystem, shape_type: ShapeType) -> [String; 4] {
+        let states = rotation_system.states(shape_type);
+        [0, 1, 2, 3].map(|y| {
+            states.iter()
+                .map(|cells| (0..4).map(|x| if cells.iter().any(|&cell| cell == (x, y)) { 'X' } else { '.' }).collect())
+                .collect::<Vec<String>>()
+                .join(" ")
+        })
+    }
+
+    fn assert_states(rotation_system: &dyn RotationSystem, shape_type: ShapeType, expected: [&str; 4]) {
+        assert_eq!(draw_states(rotation_system, shape_type), expected, "{:?}", shape_type);
+    }
+
+    fn spawn(rotation_system: &dyn RotationSystem, shape_type: ShapeType) -> Vec<(i16, i16)> {
+        let (x, y) = rotation_system.spawn_position(shape_type);
+        let mut squares = Shape::new(rotation_system, shape_type, x, y).get_occupied_squares();
+        squares.sort_by_key(|&(x, y)| (y, x));
+        squares
+    }
+
+    #[test]
+    fn srs_states() {
+        assert_states(&Srs, ShapeType::I, [
+            ".... ..X. .... .X..",
+            "XXXX ..X. .... .X..",
+            ".... ..X. XXXX .X..",
+            ".... ..X. .... .X..",
+        ]);
+        assert_states(&Srs, ShapeType::J, [
+            "X... .XX. .... .X..",
+            "XXX. .X.. XXX. .X..",
+            ".... .X.. ..X. XX..",
+            ".... .... .... ....",
+        ]);
+        assert_states(&Srs, ShapeType::L, [
+            "..X. .X.. .... XX..",
+            "XXX. .X.. XXX. .X..",
+            ".... .XX. X... .X..",
+            ".... .... .... ....",
+        ]);
+        assert_states(&Srs, ShapeType::O, [
+            ".XX. .XX. .XX. .XX.",
+            ".XX. .XX. .XX. .XX.",
+            ".... .... .... ....",
+            ".... .... .... ....",
+        ]);
+        assert_states(&Srs, ShapeType::S, [
+            ".XX. .X.. .... X...",
+            "XX.. .XX. .XX. XX..",
+            ".... ..X. XX.. .X..",
+            ".... .... .... ....",
+        ]);
+        assert_states(&Srs, ShapeType::T, [
+            ".X.. .X.. .... .X..",
+            "XXX. .XX. XXX. XX..",
+            ".... .X.. .X.. .X..",
+            ".... .... .... ....",
+        ]);
+        assert_states(&Srs, ShapeType::Z, [
+            "XX.. ..X. .... .X..",
+            ".XX. .XX. XX.. XX..",
+            ".... .X.. .XX. X...",
+            ".... .... .... ....",
+        ]);
+    }
+
+    #[test]
+    fn ars_states() {
+        assert_states(&Ars, ShapeType::I, [
+            ".... ..X. .... ..X.",
+            "XXXX ..X. XXXX ..X.",
+            ".... ..X. .... ..X.",
+            ".... ..X. .... ..X.",
+        ]);
+        assert_states(&Ars, ShapeType::J, [
+            ".... .X.. .... .XX.",
+            "XXX. .X.. X... .X..",
+            "..X. XX.. XXX. .X..",
+            ".... .... .... ....",
+        ]);
+        assert_states(&Ars, ShapeType::L, [
+            ".... XX.. .... .X..",
+            "XXX. .X.. ..X. .X..",
+            "X... .X.. XXX. .XX.",
+            ".... .... .... ....",
+        ]);
+        assert_states(&Ars, ShapeType::O, [
+            ".... .... .... ....",
+            ".XX. .XX. .XX. .XX.",
+            ".XX. .XX. .XX. .XX.",
+            ".... .... .... ....",
+        ]);
+        assert_states(&Ars, ShapeType::S, [
+            ".... X... .... X...",
+            ".XX. XX.. .XX. XX..",
+            "XX.. .X.. XX.. .X..",
+            ".... .... .... ....",
+        ]);
+        assert_states(&Ars, ShapeType::T, [
+            ".... .X.. .... .X..",
+            "XXX. XX.. .X.. .XX.",
+            ".X.. .X.. XXX. .X..",
+            ".... .... .... ....",
+        ]);
+        assert_states(&Ars, ShapeType::Z, [
+            ".... ..X. .... ..X.",
+            "XX.. .XX. XX.. .XX.",
+            ".XX. .X.. .XX. .X..",
+            ".... .... .... ....",
+        ]);
+    }
+
+    #[test]
+    fn nrs_states() {
+        assert_states(&Nrs, ShapeType::I, [
+            ".... ..X. .... ..X.",
+            ".... ..X. .... ..X.",
+            "XXXX ..X. XXXX ..X.",
+            ".... ..X. .... ..X.",
+        ]);
+        assert_states(&Nrs, ShapeType::J, [
+            ".... .X.. X... .XX.",
+            "XXX. .X.. XXX. .X..",
+            "..X. XX.. .... .X..",
+            ".... .... .... ....",
+        ]);
+        assert_states(&Nrs, ShapeType::L, [
+            ".... XX.. ..X. .X..",
+            "XXX. .X.. XXX. .X..",
+            "X... .X.. .... .XX.",
+            ".... .... .... ....",
+        ]);
+        assert_states(&Nrs, ShapeType::O, [
+            ".... .... .... ....",
+            ".XX. .XX. .XX. .XX.",
+            ".XX. .XX. .XX. .XX.",
+            ".... .... .... ....",
+        ]);
+        assert_states(&Nrs, ShapeType::S, [
+            ".... .X.. .... .X..",
+            ".XX. .XX. .XX. .XX.",
+            "XX.. ..X. XX.. ..X.",
+            ".... .... .... ....",
+        ]);
+        assert_states(&Nrs, ShapeType::T, [
+            ".... .X.. .X.. .X..",
+            "XXX. XX.. XXX. .XX.",
+            ".X.. .X.. .... .X..",
+            ".... .... .... ....",
+        ]);
+        assert_states(&Nrs, ShapeType::Z, [
+            ".... ..X. .... ..X.",
+            "XX.. .XX. XX.. .XX.",
+            ".XX. .X.. .XX. .X..",
+            ".... .... .... ....",
+        ]);
+    }
+
+    #[test]
+    fn every_state_has_four_cells() {
+        for name in RotationSystemName::ALL {
+            for shape_type in ShapeType::ALL {
+                for cells in name.system().states(shape_type) {
+                    assert_eq!(cells.len(), 4, "{:?} {:?}", name, shape_type);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn srs_states_rotate_clockwise_in_their_box() {
+        // The O does not rotate at all, it keeps the same cells in every state.
+        for shape_type in ShapeType::ALL.into_iter().filter(|&shape_type| shape_type != ShapeType::O) {
+            let size = if shape_type == ShapeType::I { 4 } else { 3 };
+            let states = Srs.states(shape_type);
+            for from in 0..4 {
+                let mut rotated: Vec<Cell> = states[from].iter().map(|&(x, y)| (size - 1 - y, x)).collect();
+                let mut expected: Vec<Cell> = states[(from + 1) % 4].iter().copied().collect();
+                rotated.sort();
+                expected.sort();
+                assert_eq!(rotated, expected, "{:?} from state {}", shape_type, from);
+            }
+        }
+    }
+
+    #[test]
+    fn srs_spawns_in_guideline_columns() {
+        assert_eq!(spawn(&Srs, ShapeType::I), [(3, 1), (4, 1), (5, 1), (6, 1)]);
+        assert_eq!(spawn(&Srs, ShapeType::J), [(3, 0), (3, 1), (4, 1), (5, 1)]);
+        assert_eq!(spawn(&Srs, ShapeType::L), [(5, 0), (3, 1), (4, 1), (5, 1)]);
+        assert_eq!(spawn(&Srs, ShapeType::O), [(4, 0), (5, 0), (4, 1), (5, 1)]);
+        assert_eq!(spawn(&Srs, ShapeType::S), [(4, 0), (5, 0), (3, 1), (4, 1)]);
+        assert_eq!(spawn(&Srs, ShapeType::T), [(4, 0), (3, 1), (4, 1), (5, 1)]);
+        assert_eq!(spawn(&Srs, ShapeType::Z), [(3, 0), (4, 0), (4, 1), (5, 1)]);
+    }
+
+    #[test]
+    fn ars_spawns_pointing_down() {
+        assert_eq!(spawn(&Ars, ShapeType::T), [(3, 1), (4, 1), (5, 1), (4, 2)]);
+        assert_eq!(spawn(&Ars, ShapeType::O), [(4, 1), (5, 1), (4, 2), (5, 2)]);
+    }
+
+    #[test]
+    fn srs_kicks_are_in_board_coordinates() {
+        // 0->R of a T: the fourth test moves the shape two rows down the board.
+        assert_eq!(Srs.kicks(ShapeType::T, 0, 1), [(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)]);
+        assert_eq!(Srs.kicks(ShapeType::I, 1, 0), [(0, 0), (2, 0), (-1, 0), (2, -1), (-1, 2)]);
+        assert_eq!(Srs.kicks(ShapeType::O, 0, 3), [(0, 0)]);
+    }
+
+    fn rotate_on(board: &Board, shape_type: ShapeType, x: i16, y: i16) -> Option<i16> {
+        let mut shape = Shape::new(&Ars, shape_type, x, y);
+        crate::update::try_rotate(&mut shape, board, &Ars, 1).then_some(shape.x)
+    }
+
+    #[test]
+    fn ars_kicks_unless_blocked_in_the_centre_column() {
+        let mut board = Board::new();
+        // The L rotates into the left and centre columns of its box's top row.
+        board.set(3, 10, Some(Square::Garbage));
+        assert_eq!(rotate_on(&board, ShapeType::L, 3, 10), Some(4));
+        board.set(3, 10, None);
+        board.set(4, 10, Some(Square::Garbage));
+        assert_eq!(rotate_on(&board, ShapeType::L, 3, 10), None);
+        // A T pointing down cannot kick out of a notch in its centre column.
+        let mut board = Board::new();
+        board.set(4, 17, Some(Square::Garbage));
+        assert_eq!(rotate_on(&board, ShapeType::T, 3, 17), None);
+        assert_eq!(rotate_on(&Board::new(), ShapeType::T, 3, 17), Some(3));
+    }
+}
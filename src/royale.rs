@@ -0,0 +1,311 @@
+use std::io::Result;
+use std::time::SystemTime;
+use rand::prelude::*;
+use crate::BOARD_HEIGHT_IN_TILES;
+use crate::ai::Opponent;
+use crate::board::Board;
+use crate::choice::Choice;
+use crate::config::Config;
+use crate::game::{GameStatus, Step};
+use crate::input::{receive_input, wait_for_key, Action};
+use crate::mode::ModeName;
+use crate::render::{self, Layout};
+use crate::theme::Theme;
+use crate::versus::Player;
+
+/// Badges that count towards the attack bonus, each adds a quarter to the garbage sent.
+const MAX_BADGE_BONUS: u32 = 4;
+/// Lines of the standings shown at the end.
+const STANDINGS_SHOWN: usize = 10;
+
+/// Who a player of a battle royale sends garbage to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Targeting {
+    Random,
+    /// Players that target the sender, or a random one if nobody does.
+    Attackers,
+    /// The player closest to topping out.
+    Kos,
+    /// The player with the most badges.
+    Badges,
+}
+
+impl Choice for Targeting {
+    const ALL: &'static [Targeting] = &[Targeting::Random, Targeting::Attackers, Targeting::Kos, Targeting::Badges];
+
+    fn id(self) -> &'static str {
+        match self {
+            Targeting::Random => "random",
+            Targeting::Attackers => "attackers",
+            Targeting::Kos => "kos",
+            Targeting::Badges => "badges",
+        }
+    }
+}
+
+impl Targeting {
+    pub fn label(self) -> &'static str {
+        match self {
+            Targeting::Random => "Random",
+            Targeting::Attackers => "Attackers",
+            Targeting::Kos => "KOs",
+            Targeting::Badges => "Badges",
+        }
+    }
+}
+
+/// What targeting needs to know about every player of a battle royale.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct TargetInfo {
+    alive: bool,
+    target: Option<usize>,
+    badges: u32,
+    stack_height: usize,
+}
+
+/// Picks who player `attacker` sends garbage to, `None` if nobody else is left.
+fn choose_target(targeting: Targeting, attacker: usize, players: &[TargetInfo], rng: &mut impl Rng) -> Option<usize> {
+    let candidates: Vec<usize> = (0..players.len()).filter(|&i| i != attacker && players[i].alive).collect();
+    let chosen: Vec<usize> = match targeting {
+        Targeting::Random => candidates,
+        Targeting::Attackers => {
+            let attackers: Vec<usize> = candidates.iter().copied().filter(|&i| players[i].target == Some(attacker)).collect();
+            if attackers.is_empty() { candidates } else { attackers }
+        },
+        Targeting::Kos => highest(&candidates, |i| players[i].stack_height as u32),
+        Targeting::Badges => highest(&candidates, |i| players[i].badges),
+    };
+    chosen.choose(rng).copied()
+}
+
+/// The candidates for which `key` is highest.
+fn highest(candidates: &[usize], key: impl Fn(usize) -> u32) -> Vec<usize> {
+    let max = candidates.iter().map(|&i| key(i)).max();
+    candidates.iter().copied().filter(|&i| Some(key(i)) == max).collect()
+}
+
+/// Garbage sent for an attack of `lines` by a player with that many badges.
+fn with_badge_bonus(lines: u32, badges: u32) -> u32 {
+    lines + lines * badges.min(MAX_BADGE_BONUS) / 4
+}
+
+/// One of the players of a battle royale.
+struct Contender {
+    player: Player,
+    /// Plays for the contender, `None` for the one at the keyboard.
+    bot: Option<Opponent>,
+    targeting: Targeting,
+    target: Option<usize>,
+    /// Who sent garbage last, and gets the badge if the contender tops out.
+    last_attacker: Option<usize>,
+    badges: u32,
+    /// Place in the standings once out, the winner gets 1.
+    place: Option<usize>,
+    /// The mini board as last drawn.
+    shown: Option<Board>,
+}
+
+impl Contender {
+    fn is_alive(&self) -> bool {
+        self.place.is_none()
+    }
+
+    fn target_info(&self) -> TargetInfo {
+        let board = &self.player.game.locked_squares;
+        let top = (0..BOARD_HEIGHT_IN_TILES).find(|&y| board.row(y) != 0).unwrap_or(BOARD_HEIGHT_IN_TILES);
+        TargetInfo {
+            alive: self.is_alive(),
+            target: self.target,
+            badges: self.badges,
+            stack_height: BOARD_HEIGHT_IN_TILES - top,
+        }
+    }
+
+    /// The board with the falling shape on it.
+    fn board(&self) -> Board {
+        let mut board = self.player.game.locked_squares;
+        if self.player.game.status == GameStatus::Playing {
+            board.lock(&self.player.game.falling_shape);
+        }
+        board
+    }
+
+    fn title(&self) -> String {
+        match self.place {
+            Some(place) => format!("{} #{}", self.player.name, place),
+            None => format!("{} {}", self.player.name, "*".repeat(self.badges.min(MAX_BADGE_BONUS) as usize)),
+        }
+    }
+}
+
+/// Plays a battle royale of the player against `royale_players - 1` AI players until only one
+/// is left. Returns `false` if the player quit.
+pub fn play(theme: &Theme, config: &Config) -> Result<bool> {
+    // Everybody plays until they top out, whatever the goal of the chosen mode.
+    let config = Config { mode: ModeName::Endless, ..config.clone() };
+    let layout = Layout::side_by_side(0, 2);
+    let mut rng = thread_rng();
+    let mut contenders: Vec<Contender> = (0..config.royale_players as usize)
+        .map(|i| {
+            let (name, targeting) = match i {
+                0 => ("You".to_string(), config.royale_targeting),
+                // The AI players try every strategy, to see which fares best.
+                _ => (format!("AI {}", i), Targeting::ALL[i % Targeting::ALL.len()]),
+            };
+            let bot = (i > 0 || config.autoplay).then(|| Opponent::new(config.ai_difficulty, config.ai_weights));
+            Contender {
+                player: Player::new(name, &config, layout),
+                bot,
+                targeting,
+                target: None,
+                last_attacker: None,
+                badges: 0,
+                place: None,
+                shown: None,
+            }
+        })
+        .collect();
+
+    render::clear_screen()?;
+    render::render_game(theme, &layout, &mut contenders[0].player.game)?;
+    render::render_garbage_meter(theme, &layout, 0, 0)?;
+
+    let mut alive = contenders.len();
+    while alive > 1 {
+        let before = contenders[0].player.clone();
+        let input = receive_input()?;
+        if input == Action::Quit {
+            return Ok(false);
+        }
+
+        let mut locked = false;
+        for i in 0..contenders.len() {
+            if !contenders[i].is_alive() {
+                continue;
+            }
+            let contender = &mut contenders[i];
+            let player = &mut contender.player;
+            let mut steps = Vec::new();
+            if contender.bot.is_none() {
+                steps.push(player.game.apply_action(input));
+            }
+            if player.next_fall < SystemTime::now() {
+                player.next_fall += player.game.gravity_interval();
+                steps.push(player.game.apply_gravity());
+            }
+            if let Some(bot) = &mut contender.bot && player.game.status == GameStatus::Playing
+                && let Some(action) = bot.next_action(&player.game) {
+                steps.push(player.game.apply_action(action));
+            }
+            for step in steps {
+                locked |= i == 0 && step.locked;
+                attack(&mut contenders, i, &step, &config, &mut rng);
+            }
+        }
+        for i in 0..contenders.len() {
+            if contenders[i].is_alive() && contenders[i].player.game.status != GameStatus::Playing {
+                contenders[i].place = Some(alive);
+                alive -= 1;
+                if let Some(attacker) = contenders[i].last_attacker && contenders[attacker].is_alive() {
+                    contenders[attacker].badges += 1 + contenders[i].badges;
+                }
+            }
+        }
+        if alive == 1 && let Some(winner) = contenders.iter_mut().find(|contender| contender.is_alive()) {
+            winner.place = Some(1);
+        }
+
+        render_contenders(theme, &layout, &mut contenders, before, Step { locked, ..Step::default() }, alive)?;
+    }
+
+    let mut standings: Vec<&Contender> = contenders.iter().collect();
+    standings.sort_by_key(|contender| contender.place);
+    let mut lines = vec!["STANDINGS".to_string(), String::new()];
+    for contender in standings.iter().take(STANDINGS_SHOWN) {
+        lines.push(format!("{:>2}. {:<6} {:<9} {:>2} badges", contender.place.unwrap_or(0), contender.player.name,
+            contender.targeting.label(), contender.badges));
+    }
+    if contenders[0].place.is_some_and(|place| place > STANDINGS_SHOWN) {
+        lines.push(format!("{:>2}. You", contenders[0].place.unwrap()));
+    }
+    render::render_popup(theme, &layout, &lines)?;
+    wait_for_key()?;
+    Ok(true)
+}
+
+/// Sends the garbage of contender `i`'s step, after cancelling its own, to the contender its
+/// targeting picks.
+fn attack(contenders: &mut [Contender], i: usize, step: &Step, config: &Config, rng: &mut impl Rng) {
+    let lines = contenders[i].player.attack(step, &config.attack);
+    if lines == 0 {
+        return;
+    }
+    let infos: Vec<TargetInfo> = contenders.iter().map(Contender::target_info).collect();
+    let Some(target) = choose_target(contenders[i].targeting, i, &infos, rng) else { return };
+    contenders[i].target = Some(target);
+    contenders[target].player.game.garbage_queue.push(with_badge_bonus(lines, contenders[i].badges));
+    contenders[target].last_attacker = Some(i);
+}
+
+fn render_contenders(theme: &Theme, layout: &Layout, contenders: &mut [Contender], mut before: Player, step: Step,
+        alive: usize) -> Result<()> {
+    let target = contenders[0].target;
+    let you = &mut contenders[0];
+    you.player.game.update_clock();
+    let mut hud_lines = you.player.hud_lines();
+    hud_lines.truncate(3);
+    hud_lines.push(format!("Badges: {}", you.badges));
+    hud_lines.push(format!("Target: {}", you.targeting.label()));
+    hud_lines.push(format!("Alive:  {}", alive));
+    if let Some(place) = you.place {
+        hud_lines.push(format!("Place:  #{}", place));
+    }
+    hud_lines.push(String::new());
+    render::render_game_changes(theme, layout, &mut before.game, &mut you.player.game, step, &hud_lines)?;
+    you.player.render_garbage_meter(theme)?;
+
+    for (i, contender) in contenders.iter_mut().enumerate().skip(1) {
+        let Some(position) = render::mini_board_position(i - 1) else { break };
+        let board = contender.board();
+        let marker = if target == Some(i) { ">" } else { "" };
+        render::render_mini_board(theme, position, &format!("{}{}", marker, contender.title()), contender.shown.as_ref(), &board)?;
+        contender.shown = Some(board);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+
+    fn info(stack_height: usize, badges: u32, target: Option<usize>) -> TargetInfo {
+        TargetInfo { alive: true, target, badges, stack_height }
+    }
+
+    #[test]
+    fn targets_by_strategy() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let players = [
+            info(3, 0, Some(2)),
+            info(15, 0, Some(3)),
+            info(4, 5, Some(0)),
+            TargetInfo { alive: false, ..info(20, 9, Some(0)) },
+        ];
+        assert_eq!(choose_target(Targeting::Kos, 0, &players, &mut rng), Some(1));
+        assert_eq!(choose_target(Targeting::Badges, 0, &players, &mut rng), Some(2));
+        assert_eq!(choose_target(Targeting::Attackers, 0, &players, &mut rng), Some(2));
+        for _ in 0..20 {
+            let target = choose_target(Targeting::Random, 0, &players, &mut rng);
+            assert!(matches!(target, Some(1 | 2)));
+        }
+        assert_eq!(choose_target(Targeting::Random, 0, &players[..1], &mut rng), None);
+    }
+
+    #[test]
+    fn badges_add_to_attacks() {
+        assert_eq!(with_badge_bonus(4, 0), 4);
+        assert_eq!(with_badge_bonus(4, 1), 5);
+        assert_eq!(with_badge_bonus(4, 10), 8);
+    }
+}
@@ -0,0 +1,179 @@
+use std::sync::Arc;
+use std::time::Duration;
+use crate::choice::Choice;
+use crate::mode::GameMode;
+use crate::piece_set::PieceSet;
+use crate::randomizer::RandomizerKind;
+use crate::rotation::{RotationSystem, RotationSystemName};
+use crate::shape::ShapeType;
+use crate::stats::Stats;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RulesetName {
+    Standard,
+    Guideline,
+    Nes,
+}
+
+impl Choice for RulesetName {
+    const ALL: &'static [RulesetName] = &[RulesetName::Standard, RulesetName::Guideline, RulesetName::Nes];
+
+    fn id(self) -> &'static str {
+        match self {
+            RulesetName::Standard => "standard",
+            RulesetName::Guideline => "guideline",
+            RulesetName::Nes => "nes",
+        }
+    }
+}
+
+impl RulesetName {
+    pub fn label(self) -> &'static str {
+        match self {
+            RulesetName::Standard => "Standard",
+            RulesetName::Guideline => "Guideline",
+            RulesetName::Nes => "Classic NES",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Gravity {
+    /// Speed chosen by the game mode.
+    Mode,
+    /// Frames per row from the NES version, running at 60.0988 frames per second.
+    NesFrames,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scoring {
+    /// 25 points for every locked shape and 100 for every cleared row.
+    Standard,
+    /// 100/300/500/800 times the level, plus 1 point per soft dropped and 2 per hard dropped row.
+    Guideline,
+    /// 40/100/300/1200 times the level.
+    Nes,
+}
+
+/// Everything that differs between the supported rule sets.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ruleset {
+    pub name: RulesetName,
+    pub gravity: Gravity,
+    pub scoring: Scoring,
+    pub randomizer: RandomizerKind,
+    pub rotation: RotationSystemName,
+    /// Custom pieces played instead of the tetrominoes, they bring their own rotation.
+    pub piece_set: Option<Arc<PieceSet>>,
+    pub hold: bool,
+    pub hard_drop: bool,
+    /// Number of next shapes shown, at most the length of `NextShapes`.
+    pub preview_count: usize,
+}
+
+const NES_FRAMES_PER_SECOND: f64 = 60.0988;
+
+/// Frames per row for NES levels 0 to 29, every later level uses a single frame.
+const NES_GRAVITY_FRAMES: [u32; 30] = [
+    48, 43, 38, 33, 28, 23, 18, 13, 8, 6,
+    5, 5, 5, 4, 4, 4, 3, 3, 3, 2,
+    2, 2, 2, 2, 2, 2, 2, 2, 2, 1,
+];
+
+impl Ruleset {
+    pub fn preset(name: RulesetName) -> Ruleset {
+        match name {
+            RulesetName::Standard => Ruleset {
+                name,
+                gravity: Gravity::Mode,
+                scoring: Scoring::Standard,
+                randomizer: RandomizerKind::Random,
+                rotation: RotationSystemName::Srs,
+                piece_set: None,
+                hold: false,
+                hard_drop: true,
+                preview_count: 3,
+            },
+            RulesetName::Guideline => Ruleset {
+                name,
+                gravity: Gravity::Mode,
+                scoring: Scoring::Guideline,
+                randomizer: RandomizerKind::Bag,
+                rotation: RotationSystemName::Srs,
+                piece_set: None,
+                hold: true,
+                hard_drop: true,
+                preview_count: 3,
+            },
+            RulesetName::Nes => Ruleset {
+                name,
+                gravity: Gravity::NesFrames,
+                scoring: Scoring::Nes,
+                randomizer: RandomizerKind::Nes,
+                rotation: RotationSystemName::Nrs,
+                piece_set: None,
+                hold: false,
+                hard_drop: false,
+                preview_count: 1,
+            },
+        }
+    }
+
+    pub fn rotation_system(&self) -> &dyn RotationSystem {
+        match &self.piece_set {
+            Some(piece_set) => piece_set.as_ref(),
+            None => self.rotation.system(),
+        }
+    }
+
+    /// Shape types dealt by the randomizer.
+    pub fn shape_types(&self) -> Vec<ShapeType> {
+        match &self.piece_set {
+            Some(piece_set) => piece_set.shape_types(),
+            None => ShapeType::ALL.to_vec(),
+        }
+    }
+
+    pub fn shape_name(&self, shape_type: ShapeType) -> String {
+        match self.piece_set.as_ref().and_then(|piece_set| piece_set.piece(shape_type)) {
+            Some(piece) => piece.name.clone(),
+            None => format!("{:?}", shape_type),
+        }
+    }
+
+    /// Time between the falling shape moving one row down.
+    pub fn gravity_interval(&self, mode: &GameMode, stats: &Stats) -> Duration {
+        match self.gravity {
+            Gravity::Mode => mode.gravity_interval(stats),
+            Gravity::NesFrames => {
+                // Our levels start at 1, the NES ones at 0.
+                let nes_level = stats.level() as usize - 1;
+                let frames = NES_GRAVITY_FRAMES.get(nes_level).copied().unwrap_or(1);
+                Duration::from_secs_f64(frames as f64 / NES_FRAMES_PER_SECOND)
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mode::MarathonGoal;
+
+    fn nes_interval(lines: u32) -> Duration {
+        let mut stats = Stats::new(Vec::new());
+        stats.lines = lines;
+        let mode = GameMode::Marathon { goal: MarathonGoal::Lines(150), endless: false };
+        Ruleset::preset(RulesetName::Nes).gravity_interval(&mode, &stats)
+    }
+
+    #[test]
+    fn nes_gravity_follows_the_frame_table() {
+        let frames = |frames: f64| Duration::from_secs_f64(frames / NES_FRAMES_PER_SECOND);
+        assert_eq!(nes_interval(0), frames(48.0));
+        assert_eq!(nes_interval(90), frames(6.0));
+        assert_eq!(nes_interval(190), frames(2.0));
+        assert_eq!(nes_interval(290), frames(1.0));
+        assert_eq!(nes_interval(500), frames(1.0));
+    }
+}
@@ -0,0 +1,324 @@
+use std::io::Result;
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::net::{self, Message, PROTOCOL_VERSION};
+
+/// Seconds counted down before a match starts.
+const COUNTDOWN_SECONDS: u32 = 3;
+/// How often the server looks at the countdown when no messages come in.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Runs a server for two players on the address until it is killed, printing what happens.
+pub fn serve(address: &str) -> Result<()> {
+    let listener = TcpListener::bind(address)?;
+    println!("listening on {}", listener.local_addr()?);
+    Server::new(Duration::from_secs(1)).run(listener)
+}
+
+enum Event {
+    Connected(TcpStream),
+    Received(usize, Message),
+    Disconnected(usize),
+}
+
+/// A client of the server, which becomes player 0 or 1 once it said `hello`.
+struct Client {
+    id: usize,
+    stream: TcpStream,
+    player: Option<usize>,
+    ready: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum MatchState {
+    Lobby,
+    Countdown { remaining: u32, next_tick: Instant },
+    Playing,
+}
+
+struct Server {
+    /// How long a second of the countdown takes.
+    second: Duration,
+    clients: Vec<Client>,
+    next_id: usize,
+    state: MatchState,
+}
+
+impl Server {
+    fn new(second: Duration) -> Server {
+        Server { second, clients: Vec::new(), next_id: 0, state: MatchState::Lobby }
+    }
+
+    /// Accepts clients and runs their matches until the listener fails.
+    fn run(mut self, listener: TcpListener) -> Result<()> {
+        let (sender, events) = mpsc::channel();
+        let accept_sender = sender.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else { continue };
+                if accept_sender.send(Event::Connected(stream)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        loop {
+            match events.recv_timeout(POLL_INTERVAL) {
+                Ok(Event::Connected(stream)) => {
+                    if let Err(e) = self.connect(stream, &sender) {
+                        println!("could not accept a client: {}", e);
+                    }
+                },
+                Ok(Event::Received(id, message)) => self.receive(id, message),
+                Ok(Event::Disconnected(id)) => self.disconnect(id),
+                Err(RecvTimeoutError::Timeout) => {},
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+            self.count_down();
+        }
+    }
+
+    fn connect(&mut self, stream: TcpStream, sender: &Sender<Event>) -> Result<()> {
+        let id = self.next_id;
+        self.next_id += 1;
+        stream.set_nodelay(true)?;
+        let messages = net::receive_messages(stream.try_clone()?);
+        let sender = sender.clone();
+        thread::spawn(move || {
+            for message in messages {
+                if sender.send(Event::Received(id, message)).is_err() {
+                    return;
+                }
+            }
+            let _ = sender.send(Event::Disconnected(id));
+        });
+        self.clients.push(Client { id, stream, player: None, ready: false });
+        Ok(())
+    }
+
+    fn receive(&mut self, id: usize, message: Message) {
+        let Some(i) = self.clients.iter().position(|client| client.id == id) else { return };
+        let player = self.clients[i].player;
+        match (message, player) {
+            (Message::Hello(version), None) => self.welcome(i, version),
+            (Message::Watch(_), None) => self.refuse(i, "this server cannot be watched".to_string()),
+            (Message::Ready, Some(_)) if self.state == MatchState::Lobby => {
+                self.clients[i].ready = true;
+                self.send_lobby();
+                if self.players().count() == 2 && self.players().all(|client| client.ready) {
+                    println!("both players are ready");
+                    self.state = MatchState::Countdown { remaining: COUNTDOWN_SECONDS, next_tick: Instant::now() };
+                }
+            },
+            (Message::Board(_, board), Some(player)) if self.state == MatchState::Playing => {
+                self.send_to_player(1 - player, &Message::Board(player, board));
+            },
+            (Message::Garbage(rows), Some(player)) if self.state == MatchState::Playing => {
+                self.send_to_player(1 - player, &Message::Garbage(rows));
+            },
+            (Message::ToppedOut, Some(player)) if self.state == MatchState::Playing => self.end_match(1 - player),
+            (Message::Finished, Some(player)) if self.state == MatchState::Playing => self.end_match(player),
+            _ => {},
+        }
+    }
+
+    fn welcome(&mut self, i: usize, version: u32) {
+        let free_player = (0..net::PLAYERS).find(|&player| self.players().all(|client| client.player != Some(player)));
+        let client = &mut self.clients[i];
+        let refusal = if version != PROTOCOL_VERSION {
+            format!("this server speaks protocol version {}", PROTOCOL_VERSION)
+        }
+        else if let Some(player) = free_player {
+            client.player = Some(player);
+            let _ = net::send(&mut client.stream, &Message::Welcome(PROTOCOL_VERSION, player));
+            println!("player {} joined", player + 1);
+            self.send_lobby();
+            return;
+        }
+        else {
+            "the game is full".to_string()
+        };
+        self.refuse(i, refusal);
+    }
+
+    fn refuse(&mut self, i: usize, reason: String) {
+        let client = &mut self.clients[i];
+        let _ = net::send(&mut client.stream, &Message::Error(reason));
+        let _ = client.stream.shutdown(Shutdown::Both);
+    }
+
+    fn disconnect(&mut self, id: usize) {
+        let Some(i) = self.clients.iter().position(|client| client.id == id) else { return };
+        let client = self.clients.remove(i);
+        let Some(player) = client.player else { return };
+        println!("player {} left", player + 1);
+        match self.state {
+            MatchState::Playing => self.end_match(1 - player),
+            MatchState::Countdown { .. } => {
+                self.state = MatchState::Lobby;
+                for client in &mut self.clients {
+                    client.ready = false;
+                }
+                self.send_lobby();
+            },
+            MatchState::Lobby => self.send_lobby(),
+        }
+    }
+
+    fn count_down(&mut self) {
+        let MatchState::Countdown { remaining, next_tick } = self.state else { return };
+        if Instant::now() < next_tick {
+            return;
+        }
+        if remaining > 0 {
+            self.send_to_players(&Message::Countdown(remaining));
+            self.state = MatchState::Countdown { remaining: remaining - 1, next_tick: next_tick + self.second };
+        }
+        else {
+            println!("match started");
+            self.send_to_players(&Message::Start);
+            self.state = MatchState::Playing;
+        }
+    }
+
+    /// Tells both players who won and takes them back to the lobby.
+    fn end_match(&mut self, winner: usize) {
+        println!("player {} won", winner + 1);
+        for client in &mut self.clients {
+            if let Some(player) = client.player {
+                let _ = net::send(&mut client.stream, &Message::Result(player == winner));
+            }
+            client.ready = false;
+        }
+        self.state = MatchState::Lobby;
+        self.send_lobby();
+    }
+
+    fn players(&self) -> impl Iterator<Item = &Client> {
+        self.clients.iter().filter(|client| client.player.is_some())
+    }
+
+    fn send_lobby(&mut self) {
+        let players = self.players().count();
+        let ready = self.players().filter(|client| client.ready).count();
+        self.send_to_players(&Message::Lobby { players, ready });
+    }
+
+    fn send_to_players(&mut self, message: &Message) {
+        for client in self.clients.iter_mut().filter(|client| client.player.is_some()) {
+            let _ = net::send(&mut client.stream, message);
+        }
+    }
+
+    fn send_to_player(&mut self, player: usize, message: &Message) {
+        if let Some(client) = self.clients.iter_mut().find(|client| client.player == Some(player)) {
+            let _ = net::send(&mut client.stream, message);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Board;
+    use crate::game::{Game, GameStatus};
+    use crate::mode::GameMode;
+    use crate::net::Connection;
+    use crate::ruleset::{Ruleset, RulesetName};
+    use crate::versus;
+
+    fn start_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || Server::new(Duration::from_millis(10)).run(listener));
+        address
+    }
+
+    /// Waits for the next message that is not a lobby update.
+    fn next_message(connection: &mut Connection) -> Message {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            match connection.try_receive().unwrap() {
+                Some(Message::Lobby { .. }) | None => thread::sleep(Duration::from_millis(5)),
+                Some(message) => return message,
+            }
+        }
+        panic!("no message from the server");
+    }
+
+    #[test]
+    fn plays_a_match_over_loopback() {
+        let address = start_server();
+        let mut first = Connection::open(&address).unwrap();
+        let mut second = Connection::open(&address).unwrap();
+        assert_eq!((first.player, second.player), (0, 1));
+        assert!(Connection::open(&address).is_err());
+
+        first.send(&Message::Ready).unwrap();
+        second.send(&Message::Ready).unwrap();
+        for connection in [&mut first, &mut second] {
+            for seconds in (1..=COUNTDOWN_SECONDS).rev() {
+                assert_eq!(next_message(connection), Message::Countdown(seconds));
+            }
+            assert_eq!(next_message(connection), Message::Start);
+        }
+
+        first.send(&Message::Garbage(2)).unwrap();
+        assert_eq!(next_message(&mut second), Message::Garbage(2));
+        second.send(&Message::Board(1, Box::new(Board::new()))).unwrap();
+        assert_eq!(next_message(&mut first), Message::Board(1, Box::new(Board::new())));
+
+        second.send(&Message::ToppedOut).unwrap();
+        assert_eq!(next_message(&mut first), Message::Result(true));
+        assert_eq!(next_message(&mut second), Message::Result(false));
+    }
+
+    #[test]
+    fn a_timed_game_finishes_online() {
+        let address = start_server();
+        let mut first = Connection::open(&address).unwrap();
+        let mut second = Connection::open(&address).unwrap();
+        first.send(&Message::Ready).unwrap();
+        second.send(&Message::Ready).unwrap();
+        for connection in [&mut first, &mut second] {
+            for _ in 0..=COUNTDOWN_SECONDS {
+                next_message(connection);
+            }
+        }
+
+        // The time runs out between two passes of the match loop.
+        let mut game = Game::new(GameMode::Ultra { duration: Duration::ZERO }, Ruleset::preset(RulesetName::Guideline));
+        assert_eq!(game.status, GameStatus::Playing);
+        let message = versus::end_of_game_message(&mut game, true).unwrap();
+        assert_eq!(game.status, GameStatus::Finished);
+        first.send(&message).unwrap();
+        assert_eq!(next_message(&mut first), Message::Result(true));
+        assert_eq!(next_message(&mut second), Message::Result(false));
+        assert_eq!(versus::end_of_game_message(&mut game, false), None);
+    }
+
+    #[test]
+    fn refuses_other_protocol_versions() {
+        let address = start_server();
+        let mut stream = TcpStream::connect(&address).unwrap();
+        net::send(&mut stream, &Message::Hello(PROTOCOL_VERSION + 1)).unwrap();
+        let messages = net::receive_messages(stream);
+        assert!(matches!(messages.recv_timeout(Duration::from_secs(5)), Ok(Message::Error(_))));
+    }
+
+    #[test]
+    fn the_remaining_player_wins_when_the_other_leaves() {
+        let address = start_server();
+        let mut first = Connection::open(&address).unwrap();
+        let mut second = Connection::open(&address).unwrap();
+        first.send(&Message::Ready).unwrap();
+        second.send(&Message::Ready).unwrap();
+        for _ in 0..=COUNTDOWN_SECONDS {
+            next_message(&mut first);
+        }
+        drop(second);
+        assert_eq!(next_message(&mut first), Message::Result(true));
+    }
+}
@@ -1,7 +1,19 @@
-use crossterm::style::Color;
-use rand::prelude::*;
+use crate::rotation::RotationSystem;
 
-pub type ShapeMatrix = [[u8; 4]; 4];
+/// Side of the largest box a piece may have, big enough for pentominoes.
+pub const MAX_SHAPE_SIZE: usize = 5;
+
+/// Position of a filled cell inside a shape's box: `x` counts columns to the right and
+/// `y` counts rows down from the top left corner, like board coordinates.
+pub type Cell = (i8, i8);
+
+/// The filled cells of a shape in one rotation state.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ShapeCells {
+    cells: [Cell; MAX_SHAPE_SIZE * MAX_SHAPE_SIZE],
+    len: u8,
+    row_masks: [u16; MAX_SHAPE_SIZE],
+}
 
 #[derive(Clone, Debug)]
 #[derive(Copy)]
@@ -9,10 +21,9 @@ pub type ShapeMatrix = [[u8; 4]; 4];
 pub struct Shape {
     pub x: i16,
     pub y: i16,
-    pub color: Color,
     pub shape_type: ShapeType,
-    matrices: [ShapeMatrix; 4],
-    current_matrix: u8
+    states: [ShapeCells; 4],
+    rotation_state: u8
 }
 
 #[derive(Clone, Debug)]
@@ -26,252 +37,89 @@ pub enum ShapeType {
     S,
     T,
     Z,
+    /// A piece of a custom piece set, by its index in the set.
+    Custom(u8),
 }
 
 impl ShapeType {
-    
-    pub fn random() -> ShapeType {
-        let mut rng = thread_rng();
-        let types = [ShapeType::I, ShapeType::J, ShapeType::L, 
-            ShapeType::O, ShapeType::S, ShapeType::T, ShapeType::Z];
-        *types.choose(&mut rng).expect("Could not choose a shape type")
+    pub const ALL: [ShapeType; 7] = [ShapeType::I, ShapeType::J, ShapeType::L,
+        ShapeType::O, ShapeType::S, ShapeType::T, ShapeType::Z];
+
+    /// Position of the shape type in `ALL`, or in its piece set for custom pieces.
+    pub fn index(self) -> usize {
+        match self {
+            ShapeType::Custom(i) => i as usize,
+            _ => ShapeType::ALL.iter().position(|&shape_type| shape_type == self).unwrap(),
+        }
     }
-    
 }
 
-impl Shape {
-    pub fn new(shape_type: ShapeType, x: i16, y: i16) -> Shape {
-        let matrices: [ShapeMatrix; 4] = match shape_type {
-            ShapeType::I => [
-                [
-                    [0, 0, 0, 0],
-                    [1, 1, 1, 1],
-                    [0, 0, 0, 0],
-                    [0, 0, 0, 0],
-                ],
-                [
-                    [0, 0, 1, 0],
-                    [0, 0, 1, 0],
-                    [0, 0, 1, 0],
-                    [0, 0, 1, 0],
-                ],
-                [
-                    [0, 0, 0, 0],
-                    [0, 0, 0, 0],
-                    [1, 1, 1, 1],
-                    [0, 0, 0, 0],
-                ],
-                [
-                    [0, 1, 0, 0],
-                    [0, 1, 0, 0],
-                    [0, 1, 0, 0],
-                    [0, 1, 0, 0],
-                ],
-            ],
-            ShapeType::J => [
-                [
-                    [1, 0, 0, 0],
-                    [1, 1, 1, 0],
-                    [0, 0, 0, 0],
-                    [0, 0, 0, 0],
-                ],
-                [
-                    [0, 1, 1, 0],
-                    [0, 1, 0, 0],
-                    [0, 1, 0, 0],
-                    [0, 0, 0, 0],
-                ],
-                [
-                    [0, 0, 0, 0],
-                    [1, 1, 1, 0],
-                    [0, 0, 1, 0],
-                    [0, 0, 0, 0],
-                ],
-                [
-                    [0, 1, 0, 0],
-                    [0, 1, 0, 0],
-                    [1, 1, 0, 0],
-                    [0, 0, 0, 0],
-                ],
-            ],
-            ShapeType::L => [
-                [
-                    [0, 0, 1, 0],
-                    [1, 1, 1, 0],
-                    [0, 0, 0, 0],
-                    [0, 0, 0, 0],
-                ],
-                [
-                    [0, 1, 0, 0],
-                    [0, 1, 0, 0],
-                    [0, 1, 1, 0],
-                    [0, 0, 0, 0],
-                ],
-                [
-                    [0, 0, 0, 0],
-                    [1, 1, 1, 0],
-                    [1, 0, 0, 0],
-                    [0, 0, 0, 0],
-                ],
-                [
-                    [1, 1, 0, 0],
-                    [0, 1, 0, 0],
-                    [0, 1, 0, 0],
-                    [0, 0, 0, 0],
-                ],
-            ],
-            ShapeType::O => [
-                [
-                    [1, 1, 0, 0],
-                    [1, 1, 0, 0],
-                    [0, 0, 0, 0],
-                    [0, 0, 0, 0],
-                ],
-                [
-                    [1, 1, 0, 0],
-                    [1, 1, 0, 0],
-                    [0, 0, 0, 0],
-                    [0, 0, 0, 0],
-                ],
-                [
-                    [1, 1, 0, 0],
-                    [1, 1, 0, 0],
-                    [0, 0, 0, 0],
-                    [0, 0, 0, 0],
-                ],
-                [
-                    [1, 1, 0, 0],
-                    [1, 1, 0, 0],
-                    [0, 0, 0, 0],
-                    [0, 0, 0, 0],
-                ],
-            ],
-            ShapeType::S => [
-                [
-                    [0, 1, 1, 0],
-                    [1, 1, 0, 0],
-                    [0, 0, 0, 0],
-                    [0, 0, 0, 0],
-                ],
-                [
-                    [0, 1, 0, 0],
-                    [0, 1, 1, 0],
-                    [0, 0, 1, 0],
-                    [0, 0, 0, 0],
-                ],
-                [
-                    [0, 0, 0, 0],
-                    [0, 1, 1, 0],
-                    [1, 1, 0, 0],
-                    [0, 0, 0, 0],
-                ],
-                [
-                    [1, 0, 0, 0],
-                    [1, 1, 0, 0],
-                    [0, 1, 0, 0],
-                    [0, 0, 0, 0],
-                ],
-            ],
-            ShapeType::T => [
-                [
-                    [0, 1, 0, 0],
-                    [1, 1, 1, 0],
-                    [0, 0, 0, 0],
-                    [0, 0, 0, 0],
-                ],
-                [
-                    [0, 1, 0, 0],
-                    [0, 1, 1, 0],
-                    [0, 1, 0, 0],
-                    [0, 0, 0, 0],
-                ],
-                [
-                    [0, 0, 0, 0],
-                    [1, 1, 1, 0],
-                    [0, 1, 0, 0],
-                    [0, 0, 0, 0],
-                ],
-                [
-                    [0, 1, 0, 0],
-                    [1, 1, 0, 0],
-                    [0, 1, 0, 0],
-                    [0, 0, 0, 0],
-                ],
-            ],
-            ShapeType::Z => [
-                [
-                    [1, 1, 0, 0],
-                    [0, 1, 1, 0],
-                    [0, 0, 0, 0],
-                    [0, 0, 0, 0],
-                ],
-                [
-                    [0, 0, 1, 0],
-                    [0, 1, 1, 0],
-                    [0, 1, 0, 0],
-                    [0, 0, 0, 0],
-                ],
-                [
-                    [0, 0, 0, 0],
-                    [1, 1, 0, 0],
-                    [0, 1, 1, 0],
-                    [0, 0, 0, 0],
-                ],
-                [
-                    [0, 1, 0, 0],
-                    [1, 1, 0, 0],
-                    [1, 0, 0, 0],
-                    [0, 0, 0, 0],
-                ],
-            ],
-        };
-        let color = match shape_type {
-            ShapeType::I => Color::Cyan,
-            ShapeType::J => Color::Blue,
-            ShapeType::L => Color::AnsiValue(208),
-            ShapeType::O => Color::Yellow,
-            ShapeType::S => Color::Green,
-            ShapeType::T => Color::Magenta,
-            ShapeType::Z => Color::Red,
+impl ShapeCells {
+    /// Panics if a cell lies outside of a `MAX_SHAPE_SIZE` box.
+    pub fn new(cells: &[Cell]) -> ShapeCells {
+        let size = MAX_SHAPE_SIZE as i8;
+        assert!(cells.iter().all(|&(x, y)| (0..size).contains(&x) && (0..size).contains(&y)),
+            "cells must fit in a {0}x{0} box", MAX_SHAPE_SIZE);
+        let mut shape_cells = ShapeCells {
+            cells: [(0, 0); MAX_SHAPE_SIZE * MAX_SHAPE_SIZE],
+            len: 0,
+            row_masks: [0; MAX_SHAPE_SIZE],
         };
-        Shape { matrices, x, y, color, shape_type, current_matrix: 0 }
+        for &(x, y) in cells {
+            if shape_cells.row_masks[y as usize] & 1 << x == 0 {
+                shape_cells.cells[shape_cells.len as usize] = (x, y);
+                shape_cells.len += 1;
+                shape_cells.row_masks[y as usize] |= 1 << x;
+            }
+        }
+        shape_cells
     }
-    
-    pub fn new_random(x: i16, y: i16) -> Shape {
-        Shape::new(ShapeType::random(), x, y)
+
+    /// Occupancy bits of every row of the box, column 0 is the lowest bit.
+    pub fn row_masks(&self) -> &[u16; MAX_SHAPE_SIZE] {
+        &self.row_masks
     }
 
+    pub fn iter(&self) -> impl Iterator<Item = &Cell> {
+        self.cells[..self.len as usize].iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+}
+
+impl Shape {
+    pub fn new(rotation_system: &dyn RotationSystem, shape_type: ShapeType, x: i16, y: i16) -> Shape {
+        let states = rotation_system.states(shape_type);
+        let rotation_state = rotation_system.spawn_state(shape_type);
+        Shape { states, x, y, shape_type, rotation_state }
+    }
+    
     pub fn rotate(&mut self, how_many_times: i8) {
         let keep_positive: i8 = if how_many_times > 0 {
             0
         } else {
             (how_many_times.abs() / 4 + 1) * 4
         };
-        self.current_matrix = 
-            (self.current_matrix as i8 + how_many_times + keep_positive) as u8 % 4;
+        self.rotation_state = 
+            (self.rotation_state as i8 + how_many_times + keep_positive) as u8 % 4;
     }
     
-    pub fn get_matrix(&self) -> &ShapeMatrix {
-        &self.matrices[self.current_matrix as usize]
+    pub fn rotation_state(&self) -> u8 {
+        self.rotation_state
+    }
+
+    pub fn get_cells(&self) -> &ShapeCells {
+        &self.states[self.rotation_state as usize]
     }
     
     pub fn is_occupying(&self, x: i16, y: i16) -> bool {
-        if x - self.x >= 4 || y - self.y >= 4 || x - self.x < 0 || y - self.y < 0 {
-            return false;
-        }
-        self.get_matrix()[(x - self.x) as usize][(y - self.y) as usize] == 1
+        self.get_cells().iter().any(|&(cell_x, cell_y)| self.x + cell_x as i16 == x && self.y + cell_y as i16 == y)
     }
     
     pub fn get_occupied_squares(&self) -> Vec<(i16, i16)> {
-        let mut result = Vec::new();
-        for x in 0..4 {
-            for y in 0..4 {
-                if self.get_matrix()[x][y] == 1 {
-                    result.push((x as i16 + self.x, y as i16 + self.y));   
-                }
-            }       
-        }
-        result
+        self.get_cells().iter().map(|&(x, y)| (self.x + x as i16, self.y + y as i16)).collect()
     }
 
 }
@@ -0,0 +1,412 @@
+use std::io::{BufRead, BufReader, Error, ErrorKind, Result};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::{BOARD_HEIGHT_IN_TILES, BOARD_WIDTH_IN_TILES};
+use crate::board::Board;
+use crate::config::Config;
+use crate::game::{Game, GameStatus, Step};
+use crate::input::{receive_input, Action};
+use crate::net::{self, Connection, Message, Scoreboard, PROTOCOL_VERSION};
+use crate::render::{self, Layout};
+use crate::ruleset::Ruleset;
+use crate::shape::{Shape, ShapeType};
+use crate::theme::Theme;
+
+/// How long a spectator may take to say `watch`.
+const WATCH_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long sending to a spectator may hold up the game, those who take longer are dropped.
+const WRITE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Everything spectators are shown of a game at one moment.
+#[derive(Clone, Debug)]
+struct Frame {
+    board: Board,
+    falling: Option<(ShapeType, u8, i16, i16)>,
+    held: Option<ShapeType>,
+    next: Vec<ShapeType>,
+    elapsed: Duration,
+    scoreboard: Scoreboard,
+}
+
+impl Frame {
+    fn of(game: &Game, hud_lines: &[String]) -> Frame {
+        let shape = &game.falling_shape;
+        Frame {
+            board: game.locked_squares,
+            falling: (game.status == GameStatus::Playing)
+                .then(|| (shape.shape_type, shape.rotation_state(), shape.x, shape.y)),
+            held: game.held_shape,
+            next: game.next_shapes.iter().take(game.preview_count()).map(|shape| shape.shape_type).collect(),
+            elapsed: game.stats.elapsed(),
+            scoreboard: Scoreboard {
+                score: game.score,
+                lines: game.stats.lines,
+                pieces: game.stats.pieces,
+                key_presses: game.stats.key_presses,
+                clears: game.stats.clears,
+                pieces_by_type: game.stats.pieces_by_type.iter().map(|&(_, count)| count).collect(),
+                hud_lines: hud_lines.to_vec(),
+            },
+        }
+    }
+}
+
+/// The messages that take spectators from the `previous` frame, or from nothing, to `frame`.
+/// Spectators keep their own clock, so the time alone changing sends nothing.
+fn frame_messages(previous: Option<&Frame>, frame: &Frame) -> Vec<Message> {
+    let Some(previous) = previous else {
+        return vec![
+            Message::Board(0, Box::new(frame.board)),
+            Message::Falling(frame.falling),
+            Message::Queue(frame.held, frame.next.clone()),
+            Message::Stats(frame.elapsed, Box::new(frame.scoreboard.clone())),
+        ];
+    };
+    let mut messages = Vec::new();
+    if previous.board != frame.board {
+        let mut squares = Vec::new();
+        for y in 0..BOARD_HEIGHT_IN_TILES {
+            for x in 0..BOARD_WIDTH_IN_TILES {
+                if previous.board.get(x, y) != frame.board.get(x, y) {
+                    squares.push((x, y, frame.board.get(x, y)));
+                }
+            }
+        }
+        messages.push(Message::Squares(squares));
+    }
+    if previous.falling != frame.falling {
+        messages.push(Message::Falling(frame.falling));
+    }
+    if (previous.held, &previous.next) != (frame.held, &frame.next) {
+        messages.push(Message::Queue(frame.held, frame.next.clone()));
+    }
+    if previous.scoreboard != frame.scoreboard {
+        messages.push(Message::Stats(frame.elapsed, Box::new(frame.scoreboard.clone())));
+    }
+    messages
+}
+
+/// Streams the games of this instance to spectators connecting to a socket.
+pub struct Broadcast {
+    spectators: Vec<TcpStream>,
+    joining: Receiver<TcpStream>,
+    /// The `rules` message of the current game, `None` before the first one.
+    rules: Option<Message>,
+    /// What the spectators were last sent.
+    frame: Option<Frame>,
+    game_over: Option<Message>,
+}
+
+impl Broadcast {
+    /// Listens for spectators on the address.
+    pub fn start(address: &str) -> Result<Broadcast> {
+        let listener = TcpListener::bind(address)?;
+        let (sender, joining) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else { continue };
+                let sender = sender.clone();
+                // Spectators are greeted on threads of their own so a slow one holds up nobody.
+                thread::spawn(move || {
+                    if let Ok(stream) = greet(stream) {
+                        let _ = sender.send(stream);
+                    }
+                });
+            }
+        });
+        Ok(Broadcast { spectators: Vec::new(), joining, rules: None, frame: None, game_over: None })
+    }
+
+    /// Tells the spectators that a new game starts.
+    pub fn new_game(&mut self, game: &Game) {
+        let pieces = game.ruleset.piece_set.as_ref().map(|piece_set| piece_set.name.clone());
+        let rules = Message::Rules(game.ruleset.name, game.ruleset.rotation, pieces);
+        self.send(std::slice::from_ref(&rules));
+        self.rules = Some(rules);
+        self.frame = None;
+        self.game_over = None;
+    }
+
+    /// Sends the spectators what changed since the last update, and everything to those who just
+    /// joined.
+    pub fn update(&mut self, game: &Game, hud_lines: &[String]) {
+        let frame = Frame::of(game, hud_lines);
+        self.send(&frame_messages(self.frame.as_ref(), &frame));
+
+        let joined: Vec<TcpStream> = self.joining.try_iter().collect();
+        if let Some(rules) = &self.rules {
+            let mut messages = vec![rules.clone()];
+            messages.extend(frame_messages(None, &frame));
+            messages.extend(self.game_over.clone());
+            for mut stream in joined {
+                if messages.iter().all(|message| net::send(&mut stream, message).is_ok()) {
+                    self.spectators.push(stream);
+                }
+            }
+        }
+        else {
+            self.spectators.extend(joined);
+        }
+        self.frame = Some(frame);
+    }
+
+    pub fn game_over(&mut self, title: &str) {
+        let message = Message::GameOver(title.to_string());
+        self.send(std::slice::from_ref(&message));
+        self.game_over = Some(message);
+    }
+
+    /// Sends the messages to every spectator, dropping those who left or stopped reading.
+    fn send(&mut self, messages: &[Message]) {
+        self.spectators.retain_mut(|stream| messages.iter().all(|message| net::send(stream, message).is_ok()));
+    }
+}
+
+/// Waits for a new connection to ask to watch and welcomes it.
+fn greet(mut stream: TcpStream) -> Result<TcpStream> {
+    stream.set_read_timeout(Some(WATCH_TIMEOUT))?;
+    let mut line = String::new();
+    BufReader::new(stream.try_clone()?).read_line(&mut line)?;
+    let refusal = match Message::parse(&line) {
+        Ok(Message::Watch(PROTOCOL_VERSION)) => {
+            stream.set_nodelay(true)?;
+            stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+            net::send(&mut stream, &Message::Welcome(PROTOCOL_VERSION, 0))?;
+            return Ok(stream);
+        },
+        Ok(Message::Watch(_)) => format!("this game speaks protocol version {}", PROTOCOL_VERSION),
+        _ => "this game can only be watched".to_string(),
+    };
+    net::send(&mut stream, &Message::Error(refusal.clone()))?;
+    Err(Error::new(ErrorKind::ConnectionRefused, refusal))
+}
+
+/// Shows the games broadcast on the other end of the connection until the spectator quits.
+pub fn spectate(theme: &Theme, config: &Config, connection: &mut Connection) -> Result<()> {
+    let layout = Layout::single();
+    let mut game: Option<Game> = None;
+    let mut hud_lines = Vec::new();
+    render::clear_screen()?;
+    render::render_borders(theme, &layout)?;
+    render::render_popup(theme, &layout, &["Waiting for a game".to_string()])?;
+
+    loop {
+        if receive_input()? == Action::Quit {
+            return Ok(());
+        }
+        let mut before = game.clone();
+        let mut queue_changed = false;
+        let mut title = None;
+        while let Some(message) = connection.try_receive()? {
+            match (message, &mut game) {
+                (Message::Rules(name, rotation, pieces), _) => {
+                    let own_pieces = config.piece_set.as_ref().map(|piece_set| piece_set.name.clone());
+                    if pieces != own_pieces {
+                        let describe = |pieces: Option<String>| pieces.unwrap_or_else(|| "tetromino".to_string());
+                        return Err(Error::new(ErrorKind::InvalidData, format!(
+                            "the game is played with the {} pieces, watch it with the same `pieces` instead of {}",
+                            describe(pieces), describe(own_pieces))));
+                    }
+                    let mut ruleset = Ruleset::preset(name);
+                    ruleset.rotation = rotation;
+                    ruleset.piece_set = config.piece_set.clone();
+                    let mut new_game = Game::new(config.game_mode(), ruleset);
+                    render::clear_screen()?;
+                    render::render_game(theme, &layout, &mut new_game)?;
+                    before = Some(new_game.clone());
+                    game = Some(new_game);
+                },
+                (message, Some(game)) => queue_changed |= apply(game, message, &mut hud_lines, &mut title),
+                _ => {},
+            }
+        }
+
+        if let (Some(mut before), Some(game)) = (before, &mut game) {
+            if before.status == GameStatus::Playing && game.status != GameStatus::Playing {
+                render::clear_shape(theme, &layout, &before.falling_shape)?;
+            }
+            let step = Step { locked: queue_changed, ..Step::default() };
+            render::render_game_changes(theme, &layout, &mut before, game, step, &hud_lines)?;
+            if let Some(title) = title {
+                render::render_game_over(theme, &layout, &title, &hud_lines, &game.stats, game.score)?;
+            }
+        }
+    }
+}
+
+/// Puts what a message tells about the watched game into `game`. Returns whether the next shapes
+/// changed, which has them redrawn. Shapes the rules of the game do not deal and falling shapes
+/// outside the board are ignored.
+fn apply(game: &mut Game, message: Message, hud_lines: &mut Vec<String>, title: &mut Option<String>) -> bool {
+    let shape_types = game.ruleset.shape_types();
+    match message {
+        Message::Board(_, board) => game.locked_squares = *board,
+        Message::Squares(squares) => {
+            for (x, y, square) in squares {
+                game.locked_squares.set(x, y, square);
+            }
+        },
+        Message::Falling(Some((shape_type, rotation, x, y))) => {
+            if !shape_types.contains(&shape_type) || rotation >= 4 {
+                return false;
+            }
+            let mut shape = Shape::new(game.rotation_system(), shape_type, x, y);
+            shape.rotate(rotation as i8 - shape.rotation_state() as i8);
+            // An empty board only collides with shapes that stick out of it.
+            if Board::new().collides(&shape) {
+                return false;
+            }
+            game.falling_shape = shape;
+            game.status = GameStatus::Playing;
+        },
+        Message::Falling(None) => game.status = GameStatus::ToppedOut,
+        Message::Queue(held, next) => {
+            if held.iter().chain(&next).any(|shape_type| !shape_types.contains(shape_type)) {
+                return false;
+            }
+            game.held_shape = held;
+            for (shape, shape_type) in game.next_shapes.iter_mut().zip(next) {
+                *shape = Shape::new(game.ruleset.rotation_system(), shape_type, 0, 0);
+            }
+            return true;
+        },
+        Message::Stats(elapsed, scoreboard) => {
+            let now = Instant::now();
+            game.score = scoreboard.score;
+            game.stats.lines = scoreboard.lines;
+            game.stats.pieces = scoreboard.pieces;
+            game.stats.key_presses = scoreboard.key_presses;
+            game.stats.clears = scoreboard.clears;
+            for ((_, count), &pieces) in game.stats.pieces_by_type.iter_mut().zip(&scoreboard.pieces_by_type) {
+                *count = pieces;
+            }
+            game.stats.started_at = now.checked_sub(elapsed).unwrap_or(now);
+            game.stats.stopped_at = (game.status != GameStatus::Playing).then_some(now);
+            *hud_lines = scoreboard.hud_lines;
+        },
+        Message::GameOver(game_over) => {
+            game.status = GameStatus::ToppedOut;
+            game.stats.stopped_at.get_or_insert(Instant::now());
+            *title = Some(game_over);
+        },
+        _ => {},
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Square;
+    use crate::mode::GameMode;
+    use crate::ruleset::RulesetName;
+
+    #[test]
+    fn sends_everything_once_and_then_only_changes() {
+        let mut game = Game::new(GameMode::Endless, Ruleset::preset(RulesetName::Guideline));
+        let first = Frame::of(&game, &[]);
+        let messages = frame_messages(None, &first);
+        assert_eq!(messages.len(), 4);
+        assert!(frame_messages(Some(&first), &first).is_empty());
+
+        game.locked_squares.set(3, 19, Some(Square::Garbage));
+        game.falling_shape.y += 1;
+        let second = Frame::of(&game, &[]);
+        assert_eq!(frame_messages(Some(&first), &second), vec![
+            Message::Squares(vec![(3, 19, Some(Square::Garbage))]),
+            Message::Falling(second.falling),
+        ]);
+    }
+
+    #[test]
+    fn spectators_see_the_broadcast_game() {
+        let mut game = Game::new(GameMode::Endless, Ruleset::preset(RulesetName::Guideline));
+        game.locked_squares.set(0, 19, Some(Square::Garbage));
+        game.score = 300;
+        let mut watched = Game::new(GameMode::Endless, Ruleset::preset(RulesetName::Guideline));
+        let (mut hud_lines, mut title) = (Vec::new(), None);
+        for message in frame_messages(None, &Frame::of(&game, &["Lines: 3".to_string()])) {
+            apply(&mut watched, message, &mut hud_lines, &mut title);
+        }
+        assert_eq!(watched.locked_squares, game.locked_squares);
+        assert_eq!(watched.falling_shape, game.falling_shape);
+        assert_eq!(watched.next_shapes.map(|shape| shape.shape_type), game.next_shapes.map(|shape| shape.shape_type));
+        assert_eq!(watched.score, 300);
+        assert_eq!(hud_lines, ["Lines: 3"]);
+    }
+
+    #[test]
+    fn streams_to_a_spectator_over_loopback() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        drop(listener);
+        let mut broadcast = Broadcast::start(&address).unwrap();
+        let game = Game::new(GameMode::Endless, Ruleset::preset(RulesetName::Standard));
+        broadcast.new_game(&game);
+
+        let mut connection = Connection::watch(&address).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut received = Vec::new();
+        while received.len() < 5 && Instant::now() < deadline {
+            broadcast.update(&game, &[]);
+            while let Some(message) = connection.try_receive().unwrap() {
+                received.push(message);
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(received[0], Message::Rules(RulesetName::Standard, game.ruleset.rotation, None));
+        assert_eq!(received[1], Message::Board(0, Box::new(game.locked_squares)));
+    }
+
+    #[test]
+    fn shapes_outside_the_rules_are_ignored() {
+        let mut watched = Game::new(GameMode::Endless, Ruleset::preset(RulesetName::Guideline));
+        let falling = watched.falling_shape;
+        let next = watched.next_shapes;
+        let (mut hud_lines, mut title) = (Vec::new(), None);
+        for message in [
+            Message::Falling(Some((ShapeType::Custom(0), 0, 3, 0))),
+            Message::Falling(Some((ShapeType::T, 128, 3, 0))),
+            Message::Falling(Some((ShapeType::T, 0, 9, 0))),
+            Message::Falling(Some((ShapeType::T, 0, 3, -5))),
+            Message::Queue(Some(ShapeType::Custom(3)), vec![ShapeType::I]),
+            Message::Queue(None, vec![ShapeType::I, ShapeType::Custom(30)]),
+        ] {
+            apply(&mut watched, message, &mut hud_lines, &mut title);
+        }
+        assert_eq!(watched.falling_shape, falling);
+        assert_eq!(watched.next_shapes, next);
+        assert_eq!(watched.held_shape, None);
+    }
+
+    #[test]
+    fn spectators_who_stop_reading_are_dropped() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        drop(listener);
+        let mut broadcast = Broadcast::start(&address).unwrap();
+        let game = Game::new(GameMode::Endless, Ruleset::preset(RulesetName::Standard));
+        broadcast.new_game(&game);
+
+        // Says `watch` and then never reads anything.
+        let mut stream = TcpStream::connect(&address).unwrap();
+        net::send(&mut stream, &Message::Watch(PROTOCOL_VERSION)).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while broadcast.spectators.is_empty() && Instant::now() < deadline {
+            broadcast.update(&game, &[]);
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(broadcast.spectators.len(), 1);
+
+        let message = Message::GameOver("x".repeat(60_000));
+        let deadline = Instant::now() + Duration::from_secs(20);
+        while !broadcast.spectators.is_empty() && Instant::now() < deadline {
+            let started = Instant::now();
+            broadcast.send(std::slice::from_ref(&message));
+            assert!(started.elapsed() < Duration::from_secs(1));
+        }
+        assert!(broadcast.spectators.is_empty());
+    }
+}
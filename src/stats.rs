@@ -0,0 +1,100 @@
+use std::time::{Duration, Instant};
+use crate::shape::ShapeType;
+
+/// Performance counters collected while a game is played.
+#[derive(Clone, Debug)]
+pub struct Stats {
+    pub started_at: Instant,
+    pub stopped_at: Option<Instant>,
+    pub lines: u32,
+    pub pieces: u32,
+    pub key_presses: u32,
+    /// Name and count of every shape type, indexed by `ShapeType::index`.
+    pub pieces_by_type: Vec<(String, u32)>,
+    /// Number of singles, doubles, triples and tetrises, in that order.
+    pub clears: [u32; 4],
+    /// Number of T-spins clearing zero to three rows, in that order.
+    pub t_spins: [u32; 4],
+    /// Clears that left the board empty.
+    pub perfect_clears: u32,
+    /// Cleared rows that contained garbage.
+    pub garbage_cleared: u32,
+    /// Time at which every tenth line was cleared.
+    pub splits: Vec<Duration>,
+}
+
+impl Stats {
+    pub fn new(shape_names: Vec<String>) -> Stats {
+        Stats {
+            started_at: Instant::now(),
+            stopped_at: None,
+            lines: 0,
+            pieces: 0,
+            key_presses: 0,
+            pieces_by_type: shape_names.into_iter().map(|name| (name, 0)).collect(),
+            clears: [0; 4],
+            t_spins: [0; 4],
+            perfect_clears: 0,
+            garbage_cleared: 0,
+            splits: Vec::new(),
+        }
+    }
+
+    pub fn record_key_press(&mut self) {
+        self.key_presses += 1;
+    }
+
+    pub fn record_piece(&mut self, shape_type: ShapeType, rows_deleted: u8, t_spin: bool) {
+        self.pieces += 1;
+        if let Some((_, count)) = self.pieces_by_type.get_mut(shape_type.index()) {
+            *count += 1;
+        }
+        self.lines += rows_deleted as u32;
+        if rows_deleted > 0 {
+            self.clears[rows_deleted.min(4) as usize - 1] += 1;
+        }
+        if t_spin {
+            self.t_spins[rows_deleted.min(3) as usize] += 1;
+        }
+        while self.splits.len() < (self.lines / 10) as usize {
+            self.splits.push(self.elapsed());
+        }
+    }
+
+    /// Freezes the timer at the current time.
+    pub fn stop(&mut self) {
+        self.stopped_at.get_or_insert_with(Instant::now);
+    }
+
+    pub fn level(&self) -> u32 {
+        self.lines / 10 + 1
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        match self.stopped_at {
+            Some(stopped_at) => stopped_at - self.started_at,
+            None => self.started_at.elapsed(),
+        }
+    }
+
+    pub fn pieces_per_second(&self) -> f64 {
+        let seconds = self.elapsed().as_secs_f64();
+        if seconds == 0.0 {
+            return 0.0;
+        }
+        self.pieces as f64 / seconds
+    }
+
+    pub fn keys_per_piece(&self) -> f64 {
+        if self.pieces == 0 {
+            return 0.0;
+        }
+        self.key_presses as f64 / self.pieces as f64
+    }
+}
+
+/// Formats a duration as `m:ss.mmm`.
+pub fn format_time(duration: Duration) -> String {
+    let millis = duration.as_millis();
+    format!("{}:{:02}.{:03}", millis / 60_000, millis / 1000 % 60, millis % 1000)
+}
@@ -0,0 +1,404 @@
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Error, ErrorKind, Result, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+use serde_json::{json, Value};
+use crate::{Square, BOARD_HEIGHT_IN_TILES, BOARD_WIDTH_IN_TILES};
+use crate::ai::{self, Placement};
+use crate::board::Board;
+use crate::game::Game;
+use crate::input::Action;
+use crate::shape::{Shape, ShapeType};
+
+/// Rows of the board as the protocol sees it, the rows above ours are always empty.
+const PROTOCOL_BOARD_HEIGHT: usize = 40;
+/// How long a bot may take to introduce itself and to accept the rules.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A bot running in its own process, speaking the Tetris Bot Protocol: one JSON message per line
+/// on its standard input and output.
+struct BotProcess {
+    child: Child,
+    stdin: ChildStdin,
+    messages: Receiver<Value>,
+}
+
+impl BotProcess {
+    /// Starts the command, waits for the bot to introduce itself and to accept the rules.
+    fn spawn(command: &str) -> Result<BotProcess> {
+        let mut words = command.split_whitespace();
+        let program = words.next().ok_or_else(|| invalid("the bot command is empty".to_string()))?;
+        let mut child = Command::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+
+        let (sender, messages) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                // Lines that are not JSON are skipped, so a bot may log to its output.
+                if let Ok(message) = serde_json::from_str::<Value>(&line)
+                    && sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut bot = BotProcess { child, stdin, messages };
+        bot.expect("info")?;
+        bot.send(&json!({ "type": "rules" }))?;
+        bot.expect("ready")?;
+        Ok(bot)
+    }
+
+    fn send(&mut self, message: &Value) -> Result<()> {
+        writeln!(self.stdin, "{}", message)?;
+        self.stdin.flush()
+    }
+
+    /// The next message of the bot if one has arrived, without waiting for it.
+    fn try_receive(&mut self) -> Result<Option<Value>> {
+        match self.messages.try_recv() {
+            Ok(message) => Ok(Some(message)),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err(Error::new(ErrorKind::BrokenPipe, "the bot has exited")),
+        }
+    }
+
+    /// Waits for a message of the given type. An `error` message from the bot fails.
+    fn expect(&mut self, message_type: &str) -> Result<Value> {
+        let message = match self.messages.recv_timeout(STARTUP_TIMEOUT) {
+            Ok(message) => message,
+            Err(RecvTimeoutError::Timeout) => {
+                return Err(Error::new(ErrorKind::TimedOut, format!("the bot did not send `{}` in time", message_type)));
+            },
+            Err(RecvTimeoutError::Disconnected) => return Err(Error::new(ErrorKind::BrokenPipe, "the bot has exited")),
+        };
+        match message["type"].as_str() {
+            Some(t) if t == message_type => Ok(message),
+            Some("error") => Err(invalid(format!("the bot refused to play: {}", message["reason"]))),
+            _ => Err(invalid(format!("expected `{}` from the bot, got {}", message_type, message))),
+        }
+    }
+}
+
+impl Drop for BotProcess {
+    /// Asks the bot to quit and kills it if it does not do so quickly.
+    fn drop(&mut self) {
+        let _ = self.send(&json!({ "type": "quit" }));
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while Instant::now() < deadline {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+enum BotState {
+    /// The bot does not know the current game state yet.
+    Stopped,
+    /// Waiting for the bot's suggestion.
+    Thinking,
+    /// Playing out the suggested move.
+    Playing(Box<PlannedMove>),
+    /// The bot exited or made a move that cannot be played, shapes are left to gravity from then on.
+    Failed,
+}
+
+struct PlannedMove {
+    /// The move as the bot sent it, echoed back once it is played.
+    message: Value,
+    placement: Placement,
+    plan: VecDeque<Action>,
+    expected: Option<Shape>,
+    /// The board and piece count from before the move, to tell when it is locked and whether the
+    /// board then looks like the bot expects.
+    board: Board,
+    pieces: u32,
+    /// Holding with nothing held brings in the next shape, which reveals one more.
+    hold_was_empty: bool,
+}
+
+/// Plays a game with the suggestions of an external bot, through the same actions as a human
+/// player. The game checks every suggested move and only plays those the player could make.
+pub struct ExternalBot {
+    process: BotProcess,
+    state: BotState,
+}
+
+impl ExternalBot {
+    pub fn spawn(command: &str) -> Result<ExternalBot> {
+        Ok(ExternalBot { process: BotProcess::spawn(command)?, state: BotState::Stopped })
+    }
+
+    /// Makes the bot forget the previous game, the next action starts it on the new one.
+    pub fn new_game(&mut self) {
+        if !matches!(self.state, BotState::Stopped | BotState::Failed) {
+            let _ = self.process.send(&json!({ "type": "stop" }));
+        }
+        if !matches!(self.state, BotState::Failed) {
+            self.state = BotState::Stopped;
+        }
+    }
+
+    pub fn has_failed(&self) -> bool {
+        matches!(self.state, BotState::Failed)
+    }
+
+    /// The next action to apply, `Action::None` while the bot thinks.
+    pub fn next_action(&mut self, game: &Game) -> Action {
+        match self.update(game) {
+            Ok(action) => action,
+            Err(_) => {
+                self.state = BotState::Failed;
+                Action::None
+            },
+        }
+    }
+
+    fn update(&mut self, game: &Game) -> Result<Action> {
+        if let BotState::Playing(planned) = &self.state
+            && game.stats.pieces > planned.pieces {
+            self.finish_move(game)?;
+        }
+        match &mut self.state {
+            BotState::Stopped => {
+                self.process.send(&start_message(game))?;
+                self.process.send(&json!({ "type": "suggest" }))?;
+                self.state = BotState::Thinking;
+                Ok(Action::None)
+            },
+            BotState::Thinking => {
+                let Some(message) = self.process.try_receive()? else {
+                    return Ok(Action::None);
+                };
+                if message["type"] != "suggestion" {
+                    return Err(invalid(format!("expected a suggestion from the bot, got {}", message)));
+                }
+                let moves = message["moves"].as_array().cloned().unwrap_or_default();
+                let planned = moves.into_iter()
+                    .find_map(|message| plan_move(game, message))
+                    .ok_or_else(|| invalid("the bot suggested no move that can be played".to_string()))?;
+                self.state = BotState::Playing(Box::new(planned));
+                self.update(game)
+            },
+            BotState::Playing(planned) => {
+                // Gravity may have moved the shape since the plan was made, it is then made again
+                // for the same placement.
+                if planned.expected != Some(game.falling_shape) {
+                    let replanned = plan_move(game, planned.message.clone())
+                        .ok_or_else(|| invalid("the suggested move can no longer be played".to_string()))?;
+                    **planned = PlannedMove {
+                        board: planned.board,
+                        pieces: planned.pieces,
+                        hold_was_empty: planned.hold_was_empty,
+                        ..replanned
+                    };
+                }
+                let action = planned.plan.pop_front().unwrap_or(Action::None);
+                planned.expected = ai::falling_shape_after(game, action);
+                Ok(action)
+            },
+            BotState::Failed => Ok(Action::None),
+        }
+    }
+
+    /// Tells the bot its move was played and which shapes it revealed, starting over when the board
+    /// did not end up the way the bot expects, e.g. after garbage came in.
+    fn finish_move(&mut self, game: &Game) -> Result<()> {
+        let BotState::Playing(planned) = std::mem::replace(&mut self.state, BotState::Thinking) else {
+            return Ok(());
+        };
+        self.process.send(&json!({ "type": "play", "move": planned.message }))?;
+
+        let mut expected_board = planned.board;
+        expected_board.lock(&planned.placement.shape);
+        expected_board.delete_full_rows();
+        if expected_board != game.locked_squares {
+            self.process.send(&json!({ "type": "stop" }))?;
+            self.state = BotState::Stopped;
+            return Ok(());
+        }
+
+        let revealed = 1 + planned.hold_was_empty as usize;
+        let queue = visible_queue(game);
+        for &shape_type in &queue[queue.len().saturating_sub(revealed)..] {
+            self.process.send(&json!({ "type": "new_piece", "piece": piece_name(shape_type) }))?;
+        }
+        self.process.send(&json!({ "type": "suggest" }))?;
+        Ok(())
+    }
+}
+
+/// Finds the placement the move describes among those the falling shape can reach, and the
+/// actions to get there.
+fn plan_move(game: &Game, message: Value) -> Option<PlannedMove> {
+    let (shape_type, mut cells) = location_cells(&message["location"])?;
+    cells.sort();
+    let placement = ai::find_placements(game).into_iter().find(|placement| {
+        let mut occupied: Vec<(i64, i64)> = placement.shape.get_occupied_squares().iter()
+            .map(|&(x, y)| (x as i64, y as i64))
+            .collect();
+        occupied.sort();
+        placement.shape.shape_type == shape_type && occupied == cells
+    })?;
+    Some(PlannedMove {
+        message,
+        plan: ai::plan_actions(game, &placement),
+        expected: Some(game.falling_shape),
+        board: game.locked_squares,
+        pieces: game.stats.pieces,
+        hold_was_empty: placement.use_hold && game.held_shape.is_none(),
+        placement,
+    })
+}
+
+/// The board cells a protocol location covers. The protocol puts the origin at the bottom left
+/// with `y` counting up, and gives the position of the centre of the piece.
+fn location_cells(location: &Value) -> Option<(ShapeType, Vec<(i64, i64)>)> {
+    let shape_type = ShapeType::ALL.into_iter().find(|&shape_type| location["type"] == piece_name(shape_type))?;
+    let rotations = ["north", "east", "south", "west"].iter().position(|&orientation| location["orientation"] == orientation)?;
+    let (x, y) = (location["x"].as_i64()?, location["y"].as_i64()?);
+    let cells = north_cells(shape_type).iter()
+        .map(|&(dx, dy)| {
+            let (dx, dy) = (0..rotations).fold((dx, dy), |(dx, dy), _| (dy, -dx));
+            (x + dx, BOARD_HEIGHT_IN_TILES as i64 - 1 - (y + dy))
+        })
+        .collect();
+    Some((shape_type, cells))
+}
+
+/// Cells of the piece around its centre when pointing north, `y` counting up.
+fn north_cells(shape_type: ShapeType) -> [(i64, i64); 4] {
+    match shape_type {
+        ShapeType::I => [(-1, 0), (0, 0), (1, 0), (2, 0)],
+        ShapeType::J => [(-1, 1), (-1, 0), (0, 0), (1, 0)],
+        ShapeType::L => [(1, 1), (-1, 0), (0, 0), (1, 0)],
+        ShapeType::O => [(0, 0), (1, 0), (0, 1), (1, 1)],
+        ShapeType::S => [(-1, 0), (0, 0), (0, 1), (1, 1)],
+        ShapeType::T => [(0, 1), (-1, 0), (0, 0), (1, 0)],
+        ShapeType::Z => [(-1, 1), (0, 1), (0, 0), (1, 0)],
+        ShapeType::Custom(_) => unreachable!("bots only play the standard pieces"),
+    }
+}
+
+fn piece_name(shape_type: ShapeType) -> &'static str {
+    match shape_type {
+        ShapeType::I => "I",
+        ShapeType::J => "J",
+        ShapeType::L => "L",
+        ShapeType::O => "O",
+        ShapeType::S => "S",
+        ShapeType::T => "T",
+        ShapeType::Z => "Z",
+        ShapeType::Custom(_) => unreachable!("bots only play the standard pieces"),
+    }
+}
+
+/// The falling shape followed by the shapes the player can see coming.
+fn visible_queue(game: &Game) -> Vec<ShapeType> {
+    let next = game.next_shapes.iter().take(game.preview_count()).map(|shape| shape.shape_type);
+    std::iter::once(game.falling_shape.shape_type).chain(next).collect()
+}
+
+fn start_message(game: &Game) -> Value {
+    let board: Vec<Vec<Value>> = (0..PROTOCOL_BOARD_HEIGHT)
+        .map(|row| {
+            (0..BOARD_WIDTH_IN_TILES)
+                .map(|x| {
+                    let square = (row < BOARD_HEIGHT_IN_TILES)
+                        .then(|| game.locked_squares.get(x, BOARD_HEIGHT_IN_TILES - 1 - row))
+                        .flatten();
+                    match square {
+                        Some(Square::Shape(shape_type)) => json!(piece_name(shape_type)),
+                        Some(Square::Garbage) => json!("G"),
+                        None => Value::Null,
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    json!({
+        "type": "start",
+        "hold": game.held_shape.map(piece_name),
+        "queue": visible_queue(game).into_iter().map(piece_name).collect::<Vec<_>>(),
+        "combo": game.combo,
+        "back_to_back": game.back_to_back,
+        "board": board,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mode::GameMode;
+    use crate::rotation::Srs;
+    use crate::ruleset::{Ruleset, RulesetName};
+
+    fn cells(piece: &str, orientation: &str, x: i64, y: i64) -> Vec<(i64, i64)> {
+        let location = json!({ "type": piece, "orientation": orientation, "x": x, "y": y });
+        let (_, mut cells) = location_cells(&location).unwrap();
+        cells.sort_by_key(|&(x, y)| (y, x));
+        cells
+    }
+
+    #[test]
+    fn locations_map_to_board_cells() {
+        // Board rows count down from the top, row 19 is the bottom one.
+        assert_eq!(cells("T", "north", 4, 1), [(4, 17), (3, 18), (4, 18), (5, 18)]);
+        assert_eq!(cells("T", "east", 4, 1), [(4, 17), (4, 18), (5, 18), (4, 19)]);
+        assert_eq!(cells("T", "south", 4, 1), [(3, 18), (4, 18), (5, 18), (4, 19)]);
+        assert_eq!(cells("T", "west", 4, 1), [(4, 17), (3, 18), (4, 18), (4, 19)]);
+
+        assert_eq!(cells("I", "north", 4, 0), [(3, 19), (4, 19), (5, 19), (6, 19)]);
+        assert_eq!(cells("I", "east", 4, 2), [(4, 16), (4, 17), (4, 18), (4, 19)]);
+        assert_eq!(cells("I", "south", 5, 0), [(3, 19), (4, 19), (5, 19), (6, 19)]);
+        assert_eq!(cells("I", "west", 4, 1), [(4, 16), (4, 17), (4, 18), (4, 19)]);
+        // The same cells as the game's vertical I, so moves can be matched against placements.
+        let mut shape = Shape::new(&Srs, ShapeType::I, 2, 16);
+        shape.rotate(1);
+        let mut squares: Vec<(i64, i64)> = shape.get_occupied_squares().iter().map(|&(x, y)| (x as i64, y as i64)).collect();
+        squares.sort_by_key(|&(x, y)| (y, x));
+        assert_eq!(squares, cells("I", "east", 4, 2));
+
+        // The centre of the O is the cell that would stay put when rotating about its middle.
+        let square = [(4, 18), (5, 18), (4, 19), (5, 19)];
+        assert_eq!(cells("O", "north", 4, 0), square);
+        assert_eq!(cells("O", "east", 4, 1), square);
+        assert_eq!(cells("O", "south", 5, 1), square);
+        assert_eq!(cells("O", "west", 5, 0), square);
+
+        assert!(location_cells(&json!({ "type": "T", "orientation": "up", "x": 4, "y": 1 })).is_none());
+        assert!(location_cells(&json!({ "type": "A", "orientation": "north", "x": 4, "y": 1 })).is_none());
+    }
+
+    #[test]
+    fn start_message_has_the_board_bottom_up() {
+        let mut game = Game::new(GameMode::Endless, Ruleset::preset(RulesetName::Guideline));
+        game.locked_squares.set(0, 19, Some(Square::Garbage));
+        game.locked_squares.set(9, 18, Some(Square::Shape(ShapeType::L)));
+        let message = start_message(&game);
+        let board = message["board"].as_array().unwrap();
+        assert_eq!(board.len(), PROTOCOL_BOARD_HEIGHT);
+        assert_eq!(board[0][0], "G");
+        assert_eq!(board[0][9], Value::Null);
+        assert_eq!(board[1][9], "L");
+        assert!(board[2..].iter().flat_map(|row| row.as_array().unwrap()).all(Value::is_null));
+        assert_eq!(message["queue"].as_array().unwrap().len(), 1 + game.ruleset.preview_count);
+    }
+}
@@ -0,0 +1,357 @@
+use std::env;
+use std::sync::Arc;
+use crossterm::style::Color;
+use crate::Square;
+use crate::choice::Choice;
+use crate::piece_set::PieceSet;
+use crate::shape::ShapeType;
+
+/// How many colours the terminal can display. Theme colours are defined in
+/// truecolor and degrade to the nearest 256-colour or 16-colour value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorSupport {
+    Ansi16,
+    Ansi256,
+    TrueColor,
+}
+
+impl ColorSupport {
+    pub fn detect() -> ColorSupport {
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            return ColorSupport::TrueColor;
+        }
+        if env::var("TERM").unwrap_or_default().contains("256") {
+            return ColorSupport::Ansi256;
+        }
+        ColorSupport::Ansi16
+    }
+
+    pub fn from_id(id: &str) -> Option<ColorSupport> {
+        match id {
+            "16" => Some(ColorSupport::Ansi16),
+            "256" => Some(ColorSupport::Ansi256),
+            "truecolor" | "24bit" => Some(ColorSupport::TrueColor),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ThemeName {
+    Guideline,
+    ClassicNes,
+    Monochrome,
+    HighContrast,
+    ColourBlindSafe,
+}
+
+impl Choice for ThemeName {
+    const ALL: &'static [ThemeName] = &[ThemeName::Guideline, ThemeName::ClassicNes,
+        ThemeName::Monochrome, ThemeName::HighContrast, ThemeName::ColourBlindSafe];
+
+    fn id(self) -> &'static str {
+        match self {
+            ThemeName::Guideline => "guideline",
+            ThemeName::ClassicNes => "classic-nes",
+            ThemeName::Monochrome => "monochrome",
+            ThemeName::HighContrast => "high-contrast",
+            ThemeName::ColourBlindSafe => "colour-blind-safe",
+        }
+    }
+}
+
+impl ThemeName {
+    pub fn label(self) -> &'static str {
+        match self {
+            ThemeName::Guideline => "Guideline",
+            ThemeName::ClassicNes => "Classic NES",
+            ThemeName::Monochrome => "Monochrome",
+            ThemeName::HighContrast => "High contrast",
+            ThemeName::ColourBlindSafe => "Colour-blind safe",
+        }
+    }
+}
+
+/// Characters used to draw a box around a panel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BorderSet {
+    pub top_left: char,
+    pub top_right: char,
+    pub bottom_left: char,
+    pub bottom_right: char,
+    pub horizontal: char,
+    pub vertical: char,
+}
+
+const LIGHT_BORDER: BorderSet = BorderSet {
+    top_left: '┌', top_right: '┐', bottom_left: '└', bottom_right: '┘', horizontal: '─', vertical: '│',
+};
+const HEAVY_BORDER: BorderSet = BorderSet {
+    top_left: '┏', top_right: '┓', bottom_left: '┗', bottom_right: '┛', horizontal: '━', vertical: '┃',
+};
+const DOUBLE_BORDER: BorderSet = BorderSet {
+    top_left: '╔', top_right: '╗', bottom_left: '╚', bottom_right: '╝', horizontal: '═', vertical: '║',
+};
+const ROUNDED_BORDER: BorderSet = BorderSet {
+    top_left: '╭', top_right: '╮', bottom_left: '╰', bottom_right: '╯', horizontal: '─', vertical: '│',
+};
+
+/// Two rows of text making up a single board tile.
+pub type TileGlyph = [&'static str; 2];
+
+/// A theme resolved for a particular terminal, ready to be used by the renderer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Theme {
+    pub name: ThemeName,
+    pub background: Color,
+    pub border_color: Color,
+    pub text_color: Color,
+    pub border: BorderSet,
+    piece_colors: [Color; 7],
+    piece_glyphs: [TileGlyph; 7],
+    garbage_color: Color,
+    garbage_glyph: TileGlyph,
+    color_support: ColorSupport,
+    piece_set: Option<Arc<PieceSet>>,
+}
+
+impl Theme {
+    pub fn new(name: ThemeName, color_support: ColorSupport) -> Theme {
+        let def = theme_definition(name);
+        Theme {
+            name,
+            background: def.background.resolve(color_support),
+            border_color: def.border_color.resolve(color_support),
+            text_color: def.text_color.resolve(color_support),
+            border: def.border,
+            piece_colors: def.piece_colors.map(|color| color.resolve(color_support)),
+            piece_glyphs: def.piece_glyphs,
+            garbage_color: def.garbage_color.resolve(color_support),
+            garbage_glyph: def.garbage_glyph,
+            color_support,
+            piece_set: None,
+        }
+    }
+
+    /// Takes the colours of custom pieces from their piece set.
+    pub fn with_piece_set(mut self, piece_set: Option<Arc<PieceSet>>) -> Theme {
+        self.piece_set = piece_set;
+        self
+    }
+
+    pub fn piece_color(&self, shape_type: ShapeType) -> Color {
+        match shape_type {
+            ShapeType::Custom(_) => match self.piece_set.as_ref().and_then(|piece_set| piece_set.piece(shape_type)) {
+                Some(piece) => {
+                    let (r, g, b) = piece.color;
+                    color(r, g, b, rgb_to_ansi16(r, g, b)).resolve(self.color_support)
+                },
+                None => self.garbage_color,
+            },
+            _ => self.piece_colors[shape_type.index()],
+        }
+    }
+
+    /// Custom pieces reuse the tetromino glyphs in turn.
+    pub fn piece_glyph(&self, shape_type: ShapeType) -> TileGlyph {
+        self.piece_glyphs[shape_type.index() % self.piece_glyphs.len()]
+    }
+
+    pub fn square_color(&self, square: Square) -> Color {
+        match square {
+            Square::Shape(shape_type) => self.piece_color(shape_type),
+            Square::Garbage => self.garbage_color,
+        }
+    }
+
+    pub fn square_glyph(&self, square: Square) -> TileGlyph {
+        match square {
+            Square::Shape(shape_type) => self.piece_glyph(shape_type),
+            Square::Garbage => self.garbage_glyph,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct ThemeColor {
+    rgb: (u8, u8, u8),
+    ansi16: Color,
+}
+
+impl ThemeColor {
+    fn resolve(self, color_support: ColorSupport) -> Color {
+        let (r, g, b) = self.rgb;
+        match color_support {
+            ColorSupport::TrueColor => Color::Rgb { r, g, b },
+            ColorSupport::Ansi256 => Color::AnsiValue(rgb_to_ansi256(r, g, b)),
+            ColorSupport::Ansi16 => self.ansi16,
+        }
+    }
+}
+
+struct ThemeDefinition {
+    background: ThemeColor,
+    border_color: ThemeColor,
+    text_color: ThemeColor,
+    border: BorderSet,
+    piece_colors: [ThemeColor; 7],
+    piece_glyphs: [TileGlyph; 7],
+    garbage_color: ThemeColor,
+    garbage_glyph: TileGlyph,
+}
+
+const fn color(r: u8, g: u8, b: u8, ansi16: Color) -> ThemeColor {
+    ThemeColor { rgb: (r, g, b), ansi16 }
+}
+
+const SHADED_TILE: TileGlyph = ["░░░░", "░░░░"];
+const SOLID_TILE: TileGlyph = ["████", "████"];
+const FRAMED_TILE: TileGlyph = ["┏━━┓", "┗━━┛"];
+
+// Piece arrays are indexed in `ShapeType` declaration order: I, J, L, O, S, T, Z.
+fn theme_definition(name: ThemeName) -> ThemeDefinition {
+    match name {
+        ThemeName::Guideline => ThemeDefinition {
+            background: color(0, 0, 0, Color::Black),
+            border_color: color(255, 255, 255, Color::White),
+            text_color: color(0, 255, 0, Color::Green),
+            border: LIGHT_BORDER,
+            piece_colors: [
+                color(0, 255, 255, Color::Cyan),
+                color(0, 0, 255, Color::Blue),
+                color(255, 135, 0, Color::DarkYellow),
+                color(255, 255, 0, Color::Yellow),
+                color(0, 255, 0, Color::Green),
+                color(160, 0, 240, Color::Magenta),
+                color(255, 0, 0, Color::Red),
+            ],
+            piece_glyphs: [SHADED_TILE; 7],
+            garbage_color: color(128, 128, 128, Color::DarkGrey),
+            garbage_glyph: SHADED_TILE,
+        },
+        ThemeName::ClassicNes => {
+            let white = color(252, 252, 252, Color::White);
+            let dark_blue = color(0, 88, 248, Color::Blue);
+            let light_blue = color(60, 188, 252, Color::Cyan);
+            ThemeDefinition {
+                background: color(0, 0, 0, Color::Black),
+                border_color: color(188, 188, 188, Color::Grey),
+                text_color: white,
+                border: HEAVY_BORDER,
+                piece_colors: [white, dark_blue, light_blue, white, light_blue, white, dark_blue],
+                piece_glyphs: [FRAMED_TILE; 7],
+                garbage_color: color(124, 124, 124, Color::DarkGrey),
+                garbage_glyph: FRAMED_TILE,
+            }
+        },
+        ThemeName::Monochrome => {
+            let white = color(255, 255, 255, Color::White);
+            ThemeDefinition {
+                background: color(0, 0, 0, Color::Black),
+                border_color: white,
+                text_color: white,
+                border: DOUBLE_BORDER,
+                piece_colors: [white; 7],
+                piece_glyphs: [
+                    ["████", "████"],
+                    ["▓▓▓▓", "▓▓▓▓"],
+                    ["▒▒▒▒", "▒▒▒▒"],
+                    ["░░░░", "░░░░"],
+                    ["╱╱╱╱", "╱╱╱╱"],
+                    ["[##]", "[##]"],
+                    ["╲╲╲╲", "╲╲╲╲"],
+                ],
+                garbage_color: color(128, 128, 128, Color::DarkGrey),
+                garbage_glyph: ["▚▚▚▚", "▚▚▚▚"],
+            }
+        },
+        ThemeName::HighContrast => ThemeDefinition {
+            background: color(0, 0, 0, Color::Black),
+            border_color: color(255, 255, 255, Color::White),
+            text_color: color(255, 255, 0, Color::Yellow),
+            border: DOUBLE_BORDER,
+            piece_colors: [
+                color(0, 255, 255, Color::Cyan),
+                color(80, 120, 255, Color::Blue),
+                color(255, 160, 0, Color::DarkYellow),
+                color(255, 255, 0, Color::Yellow),
+                color(0, 255, 0, Color::Green),
+                color(255, 0, 255, Color::Magenta),
+                color(255, 40, 40, Color::Red),
+            ],
+            piece_glyphs: [SOLID_TILE; 7],
+            garbage_color: color(170, 170, 170, Color::Grey),
+            garbage_glyph: ["▒▒▒▒", "▒▒▒▒"],
+        },
+        // Okabe-Ito palette, distinguishable with the common forms of colour blindness.
+        ThemeName::ColourBlindSafe => ThemeDefinition {
+            background: color(0, 0, 0, Color::Black),
+            border_color: color(255, 255, 255, Color::White),
+            text_color: color(240, 228, 66, Color::Yellow),
+            border: ROUNDED_BORDER,
+            piece_colors: [
+                color(86, 180, 233, Color::Cyan),
+                color(0, 114, 178, Color::Blue),
+                color(230, 159, 0, Color::DarkYellow),
+                color(240, 228, 66, Color::Yellow),
+                color(0, 158, 115, Color::DarkGreen),
+                color(204, 121, 167, Color::Magenta),
+                color(213, 94, 0, Color::DarkRed),
+            ],
+            piece_glyphs: [
+                ["▓▓▓▓", "▓▓▓▓"],
+                ["▓▓▓▓", "▓▓▓▓"],
+                ["▓▓▓▓", "▓▓▓▓"],
+                ["▓▓▓▓", "▓▓▓▓"],
+                ["▒▒▒▒", "▒▒▒▒"],
+                ["████", "████"],
+                ["░░░░", "░░░░"],
+            ],
+            garbage_color: color(150, 150, 150, Color::DarkGrey),
+            garbage_glyph: ["▚▚▚▚", "▚▚▚▚"],
+        },
+    }
+}
+
+/// Maps a truecolor value to the closest of the 16 basic terminal colours.
+fn rgb_to_ansi16(r: u8, g: u8, b: u8) -> Color {
+    const PALETTE: [((i32, i32, i32), Color); 16] = [
+        ((0, 0, 0), Color::Black),
+        ((128, 0, 0), Color::DarkRed),
+        ((0, 128, 0), Color::DarkGreen),
+        ((128, 128, 0), Color::DarkYellow),
+        ((0, 0, 128), Color::DarkBlue),
+        ((128, 0, 128), Color::DarkMagenta),
+        ((0, 128, 128), Color::DarkCyan),
+        ((192, 192, 192), Color::Grey),
+        ((128, 128, 128), Color::DarkGrey),
+        ((255, 0, 0), Color::Red),
+        ((0, 255, 0), Color::Green),
+        ((255, 255, 0), Color::Yellow),
+        ((0, 0, 255), Color::Blue),
+        ((255, 0, 255), Color::Magenta),
+        ((0, 255, 255), Color::Cyan),
+        ((255, 255, 255), Color::White),
+    ];
+    let (r, g, b) = (r as i32, g as i32, b as i32);
+    PALETTE.iter()
+        .min_by_key(|((pr, pg, pb), _)| (r - pr).pow(2) + (g - pg).pow(2) + (b - pb).pow(2))
+        .map(|&(_, color)| color)
+        .unwrap()
+}
+
+/// Maps a truecolor value to the closest entry of the 6x6x6 colour cube or grey ramp.
+fn rgb_to_ansi256(r: u8, g: u8, b: u8) -> u8 {
+    if r == g && g == b {
+        if r < 8 {
+            return 16;
+        }
+        if r > 248 {
+            return 231;
+        }
+        return 232 + ((r as u16 - 8) * 24 / 247) as u8;
+    }
+    let to_cube = |c: u8| if c < 48 { 0 } else if c < 115 { 1 } else { (c - 35) / 40 };
+    16 + 36 * to_cube(r) + 6 * to_cube(g) + to_cube(b)
+}
@@ -1,129 +1,152 @@
-use std::cmp::{max, min};
-use crate::{LockedSquareMatrix, NextShapes};
+use crate::{NextShapes, BOARD_HEIGHT_IN_TILES, BOARD_WIDTH_IN_TILES};
+use crate::board::Board;
+use crate::randomizer::Randomizer;
+use crate::rotation::RotationSystem;
+use crate::ruleset::Scoring;
 use crate::shape::{Shape, ShapeType};
 
-const BOARD_WIDTH_IN_TILES: u16 = 10;
-const BOARD_HEIGHT_IN_TILES: u16 = 20;
-
-pub fn put_next_shape_on_board_and_check_collision
-        (next_shapes: &mut NextShapes, locked_squares: &LockedSquareMatrix) -> (Shape, bool) {
-    let shape = Shape::new(next_shapes[0].shape_type, BOARD_WIDTH_IN_TILES as i16 / 2, 0);
+pub fn put_next_shape_on_board_and_check_collision(next_shapes: &mut NextShapes, randomizer: &mut Randomizer,
+        rotation_system: &dyn RotationSystem, board: &Board) -> (Shape, bool) {
+    let shape_type = next_shapes[0].shape_type;
     next_shapes[0] = next_shapes[1];
     next_shapes[1] = next_shapes[2];
-    next_shapes[2] = Shape::new_random(0, 0);
-    let is_colliding = 
-        check_collision_with_walls(&shape) || check_collision_with_locked_squares(&shape, locked_squares);
-    (shape, is_colliding)   
+    next_shapes[2] = Shape::new(rotation_system, randomizer.next(), 0, 0);
+    put_shape_on_board_and_check_collision(shape_type, rotation_system, board)
+}
+
+/// Puts a shape of the given type at its spawn position.
+pub fn put_shape_on_board_and_check_collision(shape_type: ShapeType, rotation_system: &dyn RotationSystem,
+        board: &Board) -> (Shape, bool) {
+    let (x, y) = rotation_system.spawn_position(shape_type);
+    let shape = Shape::new(rotation_system, shape_type, x, y);
+    let is_colliding = check_collision(&shape, board);
+    (shape, is_colliding)
 }
 
-pub fn fall_instantly(shape: &mut Shape, locked_squares: &LockedSquareMatrix) {
-    while !check_collision_with_walls(shape) && !check_collision_with_locked_squares(shape, locked_squares) {
+/// Drops the shape as far as it goes and returns the number of rows it fell.
+pub fn fall_instantly(shape: &mut Shape, board: &Board) -> u32 {
+    let start_y = shape.y;
+    while !check_collision(shape, board) {
         shape.y += 1;
     }
     shape.y -= 1;
+    (shape.y - start_y) as u32
 }
 
-pub fn try_move_left(shape: &mut Shape, locked_squares: &LockedSquareMatrix) -> bool {
-    try_move(shape, locked_squares, -1, 0)
+pub fn try_move_left(shape: &mut Shape, board: &Board) -> bool {
+    try_move(shape, board, -1, 0)
 }
 
-pub fn try_move_right(shape: &mut Shape, locked_squares: &LockedSquareMatrix) -> bool {
-    try_move(shape, locked_squares, 1, 0)
+pub fn try_move_right(shape: &mut Shape, board: &Board) -> bool {
+    try_move(shape, board, 1, 0)
 }
 
-pub fn try_fall(shape: &mut Shape, locked_squares: &LockedSquareMatrix) -> bool {
-    try_move(shape, locked_squares, 0, 1)
+pub fn try_fall(shape: &mut Shape, board: &Board) -> bool {
+    try_move(shape, board, 0, 1)
+}
+
+/// Rotates the shape clockwise when `direction` is 1 or counter-clockwise when it is -1,
+/// trying the kicks of the rotation system until one fits.
+pub fn try_rotate(shape: &mut Shape, board: &Board,
+        rotation_system: &dyn RotationSystem, direction: i8) -> bool {
+    let from = shape.rotation_state();
+    shape.rotate(direction);
+    let to = shape.rotation_state();
+    for (i, (dx, dy)) in rotation_system.kicks(shape.shape_type, from, to).into_iter().enumerate() {
+        if i == 1 && !rotation_system.can_kick(shape, board) {
+            break;
+        }
+        shape.x += dx;
+        shape.y += dy;
+        if !check_collision(shape, board) {
+            return true;
+        }
+        shape.x -= dx;
+        shape.y -= dy;
+    }
+    shape.rotate(-direction);
+    false
 }
 
-pub fn try_rotate(shape: &mut Shape, locked_squares: &LockedSquareMatrix) -> bool {
-    shape.rotate(1);
-    if check_collision_with_locked_squares(shape, locked_squares) || check_collision_with_walls(shape) {
-        shape.rotate(-1);
+/// Whether a T locked at its position counts as a T-spin, when it got there by a rotation:
+/// at least three of the corners of its 3x3 box must be filled or outside the board.
+pub fn is_t_spin(shape: &Shape, board: &Board) -> bool {
+    if shape.shape_type != ShapeType::T {
         return false;
     }
-    true
+    let corners = [(0, 0), (2, 0), (0, 2), (2, 2)];
+    let filled = corners.iter()
+        .filter(|&&(dx, dy)| {
+            let (x, y) = (shape.x + dx, shape.y + dy);
+            if x < 0 || x >= BOARD_WIDTH_IN_TILES as i16 || y >= BOARD_HEIGHT_IN_TILES as i16 {
+                return true;
+            }
+            y >= 0 && board.get(x as usize, y as usize).is_some()
+        })
+        .count();
+    filled >= 3
 }
 
-pub fn delete_full_rows(locked_squares: &mut LockedSquareMatrix) -> u8 {
-    let mut full_rows = Vec::new();
-    for y in 0..BOARD_HEIGHT_IN_TILES as usize {
-        let mut is_full_row = true;
-        for x in 0..BOARD_WIDTH_IN_TILES as usize {
-            if locked_squares[x][y].is_none() {
-                is_full_row = false;
+pub fn calculate_score(scoring: Scoring, current_score: u32, did_shape_fall: bool, rows_deleted: u8, level: u32) -> u32 {
+    let rows_deleted = rows_deleted.min(4) as usize;
+    match scoring {
+        Scoring::Standard => {
+            let mut result = current_score;
+            if did_shape_fall {
+                result += 25;
             }
-        }
-        if is_full_row {
-            full_rows.push(y);
-        }
+            result + rows_deleted as u32 * 100
+        },
+        Scoring::Guideline => current_score + [0, 100, 300, 500, 800][rows_deleted] * level,
+        Scoring::Nes => current_score + [0, 40, 100, 300, 1200][rows_deleted] * level,
     }
-    if full_rows.is_empty() {
-        return 0;
-    }
-    let mut copy_to_y = BOARD_HEIGHT_IN_TILES as usize - 1;
-    for y in (0..BOARD_HEIGHT_IN_TILES as usize).rev() {
-        if full_rows.contains(&y) {
-            continue;
-        }
-        if y != copy_to_y {
-            for x in 0..BOARD_WIDTH_IN_TILES as usize {
-                locked_squares[x][copy_to_y] = locked_squares[x][y];
-                locked_squares[x][y] = None;
-            }
-        }
-        copy_to_y -= 1;
+}
+
+/// Points on top of those of the clear for one that leaves the board empty. NES scoring has none,
+/// the NES never rewarded them.
+pub fn perfect_clear_bonus(scoring: Scoring, rows_deleted: u8, level: u32) -> u32 {
+    let rows_deleted = rows_deleted.min(4) as usize;
+    match scoring {
+        Scoring::Standard => 1000,
+        Scoring::Guideline => [0, 800, 1200, 1800, 2000][rows_deleted] * level,
+        Scoring::Nes => 0,
     }
-    full_rows.len() as u8  
 }
 
-pub fn calculate_score(current_score: u32, did_shape_fall: bool, rows_deleted: u8) -> u32 {
-    let mut result = current_score;
-    if did_shape_fall {
-        result += 25;
+/// Adds the points for dropping a shape by `rows` rows on the player's command.
+pub fn calculate_drop_score(scoring: Scoring, current_score: u32, rows: u32, is_hard_drop: bool) -> u32 {
+    match scoring {
+        Scoring::Guideline if is_hard_drop => current_score + rows * 2,
+        Scoring::Guideline => current_score + rows,
+        Scoring::Standard | Scoring::Nes => current_score,
     }
-    result + rows_deleted as u32 * 100
 }
 
-fn try_move(shape: &mut Shape, locked_squares: &LockedSquareMatrix, dx: i16, dy: i16) -> bool {
-    shape.x = shape.x + dx;
-    shape.y = shape.y + dy;
-    if check_collision_with_locked_squares(shape, locked_squares) || check_collision_with_walls(shape) {
-        shape.x = shape.x - dx;
-        shape.y = shape.y - dy;
+fn try_move(shape: &mut Shape, board: &Board, dx: i16, dy: i16) -> bool {
+    shape.x += dx;
+    shape.y += dy;
+    if check_collision(shape, board) {
+        shape.x -= dx;
+        shape.y -= dy;
         return false
     }
     true
 }
 
-fn check_collision_with_locked_squares(shape: &Shape, locked_squares: &LockedSquareMatrix) -> bool {
-    for x in 0..locked_squares.len() {
-        for y in 0..locked_squares[x].len() {
-            if locked_squares[x][y].is_some() && shape.is_occupying(x as i16, y as i16) {
-                return true;
-            }
-        }
-    }
-    false
+pub fn check_collision(shape: &Shape, board: &Board) -> bool {
+    board.collides(shape)
 }
 
-fn check_collision_with_walls(shape: &Shape) -> bool {
-    let mut top: i16 = 3;
-    let mut right: i16 = 0;
-    let mut bottom: i16 = 0;
-    let mut left: i16 = 3;
-    let mat = shape.get_matrix();
-    for x in 0..4 {
-        for y in 0..4 {
-            if mat[x][y] == 1 {
-                top = min(top, y as i16);
-                right = max(right, x as i16);
-                bottom = max(bottom, y as i16);
-                left = min(left, x as i16);
-            }
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nes_scoring_multiplies_the_clear_by_the_level() {
+        let scores: Vec<u32> = (0..=4).map(|rows| calculate_score(Scoring::Nes, 0, true, rows, 1)).collect();
+        assert_eq!(scores, vec![0, 40, 100, 300, 1200]);
+        assert_eq!(calculate_score(Scoring::Nes, 500, false, 4, 3), 500 + 3600);
+        assert_eq!(calculate_drop_score(Scoring::Nes, 500, 18, true), 500);
+        assert_eq!(perfect_clear_bonus(Scoring::Nes, 4, 3), 0);
     }
-    
-    shape.x + left < 0 || shape.y + top < 0 || 
-        shape.x + right >= BOARD_WIDTH_IN_TILES as i16 ||
-        shape.y + bottom >= BOARD_HEIGHT_IN_TILES as i16
 }
@@ -0,0 +1,323 @@
+use std::io::Result;
+use std::time::SystemTime;
+use crate::ai::Opponent;
+use crate::attack::AttackTable;
+use crate::choice::Choice;
+use crate::config::Config;
+use crate::board::Board;
+use crate::game::{Game, GameStatus, Step};
+use crate::input::{receive_input, receive_versus_input, wait_for_key, Action};
+use crate::net::{Connection, Message};
+use crate::render::{self, Layout};
+use crate::theme::Theme;
+
+/// Who plays when a game starts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Players {
+    Single,
+    /// Two players sharing the keyboard, sending garbage to each other.
+    Versus,
+    /// A player against the AI, sending garbage to each other.
+    Ai,
+    /// A player against a number of AI players, everybody sending garbage to somebody.
+    Royale,
+}
+
+impl Choice for Players {
+    const ALL: &'static [Players] = &[Players::Single, Players::Versus, Players::Ai, Players::Royale];
+
+    fn id(self) -> &'static str {
+        match self {
+            Players::Single => "single",
+            Players::Versus => "versus",
+            Players::Ai => "ai",
+            Players::Royale => "royale",
+        }
+    }
+}
+
+impl Players {
+    pub fn label(self) -> &'static str {
+        match self {
+            Players::Single => "1 player",
+            Players::Versus => "2 players",
+            Players::Ai => "vs AI",
+            Players::Royale => "Royale",
+        }
+    }
+}
+
+/// One side of a versus game.
+#[derive(Clone)]
+pub struct Player {
+    /// Shown above the statistics.
+    pub name: String,
+    pub game: Game,
+    pub layout: Layout,
+    pub next_fall: SystemTime,
+    /// Lines of attack, including those that cancelled incoming garbage.
+    pub sent: u32,
+    /// The garbage rows coming in and those of them that arrived, as last drawn by the meter.
+    pub meter: (u32, u32),
+}
+
+impl Player {
+    pub fn new(name: String, config: &Config, layout: Layout) -> Player {
+        let mut game = Game::new(config.game_mode(), config.game_ruleset());
+        game.garbage_queue = config.garbage_queue();
+        let next_fall = SystemTime::now() + game.gravity_interval();
+        Player { name, game, layout, next_fall, sent: 0, meter: (0, 0) }
+    }
+
+    pub fn hud_lines(&self) -> Vec<String> {
+        vec![
+            self.name.clone(),
+            format!("Sent:   {}", self.sent),
+            format!("Coming: {}", self.game.garbage_queue.total()),
+            String::new(),
+        ]
+    }
+
+    /// Uses the attack of a step to cancel the player's own incoming garbage and returns the lines
+    /// left for the opponent.
+    pub fn attack(&mut self, step: &Step, table: &AttackTable) -> u32 {
+        let attack = table.attack(step);
+        self.sent += attack;
+        self.game.garbage_queue.cancel(attack)
+    }
+
+    /// Redraws the garbage meter if the garbage coming in changed since it was last drawn.
+    pub fn render_garbage_meter(&mut self, theme: &Theme) -> Result<()> {
+        let queue = &self.game.garbage_queue;
+        let meter = (queue.total(), queue.arrived());
+        if meter != self.meter {
+            render::render_garbage_meter(theme, &self.layout, meter.0, meter.1)?;
+            self.meter = meter;
+        }
+        Ok(())
+    }
+}
+
+/// Plays a game of two players sharing the keyboard, or of a player against the AI, until one of
+/// them tops out. Returns `false` if they quit.
+pub fn play(theme: &Theme, config: &Config) -> Result<bool> {
+    let mut opponent = (config.players == Players::Ai).then(|| Opponent::new(config.ai_difficulty, config.ai_weights));
+    let mut players: Vec<Player> = (0..2)
+        .map(|i| {
+            let name = match &opponent {
+                Some(opponent) if i == 1 => format!("AI ({})", opponent.difficulty.label()),
+                Some(_) => "You".to_string(),
+                None => format!("Player {}", i + 1),
+            };
+            Player::new(name, config, Layout::side_by_side(i, 2))
+        })
+        .collect();
+    for player in &mut players {
+        render::render_game(theme, &player.layout, &mut player.game)?;
+        render::render_garbage_meter(theme, &player.layout, 0, 0)?;
+    }
+
+    while players.iter().all(|player| player.game.status == GameStatus::Playing) {
+        let before = players.clone();
+        let mut locked = [false; 2];
+
+        for i in 0..players.len() {
+            if players[i].next_fall < SystemTime::now() {
+                let interval = players[i].game.gravity_interval();
+                players[i].next_fall += interval;
+                let step = players[i].game.apply_gravity();
+                exchange_garbage(&mut players, i, &step, &config.attack);
+                locked[i] |= step.locked;
+            }
+        }
+        // Against the AI the player has the keyboard to themselves.
+        let input = match opponent {
+            Some(_) => Some((0, receive_input()?)).filter(|&(_, action)| action != Action::None),
+            None => receive_versus_input()?,
+        };
+        match input {
+            Some((_, Action::Quit)) => return Ok(false),
+            Some((i, action)) => {
+                let step = players[i].game.apply_action(action);
+                exchange_garbage(&mut players, i, &step, &config.attack);
+                locked[i] |= step.locked;
+            },
+            None => {},
+        }
+        if let Some(opponent) = &mut opponent
+            && let Some(action) = opponent.next_action(&players[1].game) {
+            let step = players[1].game.apply_action(action);
+            exchange_garbage(&mut players, 1, &step, &config.attack);
+            locked[1] |= step.locked;
+        }
+
+        for (i, (player, mut before)) in players.iter_mut().zip(before).enumerate() {
+            player.game.update_clock();
+            let step = Step { locked: locked[i], ..Step::default() };
+            let hud_lines = player.hud_lines();
+            render::render_game_changes(theme, &player.layout, &mut before.game, &mut player.game, step, &hud_lines)?;
+            player.render_garbage_meter(theme)?;
+        }
+    }
+
+    for (i, player) in players.iter().enumerate() {
+        let opponent = &players[1 - i];
+        let title = match (player.game.status, opponent.game.status) {
+            (GameStatus::ToppedOut, GameStatus::ToppedOut) => "DRAW",
+            (GameStatus::ToppedOut, _) | (GameStatus::Playing, GameStatus::Finished) => "LOSER",
+            _ => "WINNER",
+        };
+        let lines = [title.to_string(), String::new(), format!("Sent:   {}", player.sent), format!("Lines:  {}", player.game.stats.lines)];
+        render::render_popup(theme, &player.layout, &lines)?;
+    }
+    wait_for_key()?;
+    Ok(true)
+}
+
+/// Sends the attack of player `i`'s step to the opponent.
+fn exchange_garbage(players: &mut [Player], i: usize, step: &Step, table: &AttackTable) {
+    let lines = players[i].attack(step, table);
+    players[1 - i].game.garbage_queue.push(lines);
+}
+
+/// Plays matches against another player on a server, going back to the lobby after every match,
+/// until the player quits.
+pub fn play_online(theme: &Theme, config: &Config, connection: &mut Connection) -> Result<()> {
+    while wait_in_lobby(theme, connection)? {
+        if !play_match(theme, config, connection)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Shows the lobby until the server starts a match. Returns `false` if the player quit.
+fn wait_in_lobby(theme: &Theme, connection: &mut Connection) -> Result<bool> {
+    let layout = Layout::side_by_side(connection.player as u16, 2);
+    let opponent_layout = Layout::side_by_side(1 - connection.player as u16, 2);
+    let (mut players, mut ready, mut countdown) = (1, false, None);
+    let mut shown = Vec::new();
+    loop {
+        while let Some(message) = connection.try_receive()? {
+            match message {
+                Message::Lobby { players: count, ready: ready_count } => {
+                    players = count;
+                    countdown = None;
+                    // The server calls everyone back when a player leaves during the countdown.
+                    ready &= ready_count > 0;
+                },
+                Message::Countdown(seconds) => countdown = Some(seconds),
+                Message::Start => return Ok(true),
+                _ => {},
+            }
+        }
+        let status = match countdown {
+            Some(seconds) => format!("Starting in {}", seconds),
+            None if players < 2 => "Waiting for an opponent".to_string(),
+            None if ready => "Waiting for the opponent".to_string(),
+            None => "Press Space when ready".to_string(),
+        };
+        let lines = vec![format!("Player {}", connection.player + 1), String::new(), status];
+        if lines != shown {
+            render::clear_screen()?;
+            render::render_borders(theme, &opponent_layout)?;
+            render::render_borders(theme, &layout)?;
+            render::render_popup(theme, &layout, &lines)?;
+            shown = lines;
+        }
+
+        match receive_input()? {
+            Action::Quit => return Ok(false),
+            Action::HardDrop if !ready => {
+                connection.send(&Message::Ready)?;
+                ready = true;
+            },
+            _ => {},
+        }
+    }
+}
+
+/// Ends the game if its time is up and, if it was still being played at the start of the pass,
+/// tells the message that lets the server know it is over.
+pub fn end_of_game_message(game: &mut Game, was_playing: bool) -> Option<Message> {
+    game.update_clock();
+    match game.status {
+        GameStatus::ToppedOut if was_playing => Some(Message::ToppedOut),
+        GameStatus::Finished if was_playing => Some(Message::Finished),
+        _ => None,
+    }
+}
+
+/// Plays a single match until the server tells who won. Returns `false` if the player quit.
+fn play_match(theme: &Theme, config: &Config, connection: &mut Connection) -> Result<bool> {
+    let index = connection.player;
+    let mut player = Player::new(format!("Player {}", index + 1), config, Layout::side_by_side(index as u16, 2));
+    let opponent_layout = Layout::side_by_side(1 - index as u16, 2);
+    let mut opponent_board = Board::new();
+    let mut sent_board = Board::new();
+    render::clear_screen()?;
+    render::render_game(theme, &player.layout, &mut player.game)?;
+    render::render_garbage_meter(theme, &player.layout, 0, 0)?;
+    render::render_borders(theme, &opponent_layout)?;
+
+    let won = loop {
+        let mut before = player.clone();
+        let playing = player.game.status == GameStatus::Playing;
+
+        let step = if playing && player.next_fall < SystemTime::now() {
+            player.next_fall += player.game.gravity_interval();
+            player.game.apply_gravity()
+        }
+        else {
+            match receive_input()? {
+                Action::Quit => return Ok(false),
+                action if playing => player.game.apply_action(action),
+                _ => Step::default(),
+            }
+        };
+        let lines = player.attack(&step, &config.attack);
+        if lines > 0 {
+            connection.send(&Message::Garbage(lines))?;
+        }
+        if let Some(message) = end_of_game_message(&mut player.game, playing) {
+            connection.send(&message)?;
+        }
+
+        let mut result = None;
+        // Messages after the result belong to the lobby.
+        while result.is_none() && let Some(message) = connection.try_receive()? {
+            match message {
+                Message::Garbage(rows) => player.game.garbage_queue.push(rows),
+                Message::Board(_, board) => {
+                    render::render_locked_squares_changes(theme, &opponent_layout, &opponent_board, &board)?;
+                    opponent_board = *board;
+                },
+                Message::Result(won) => result = Some(won),
+                _ => {},
+            }
+        }
+
+        let mut board = player.game.locked_squares;
+        if player.game.status == GameStatus::Playing {
+            board.lock(&player.game.falling_shape);
+        }
+        if board != sent_board {
+            connection.send(&Message::Board(index, Box::new(board)))?;
+            sent_board = board;
+        }
+
+        let hud_lines = player.hud_lines();
+        render::render_game_changes(theme, &player.layout, &mut before.game, &mut player.game, step, &hud_lines)?;
+        player.render_garbage_meter(theme)?;
+        if let Some(won) = result {
+            break won;
+        }
+    };
+
+    let title = if won { "WINNER" } else { "LOSER" };
+    let lines = [title.to_string(), String::new(), format!("Sent:   {}", player.sent),
+        format!("Lines:  {}", player.game.stats.lines), String::new(), "Press any key".to_string()];
+    render::render_popup(theme, &player.layout, &lines)?;
+    wait_for_key()?;
+    Ok(true)
+}